@@ -0,0 +1,47 @@
+import crypto from 'crypto'
+import { keyIdFor, resetReplayGuard, signatureFromHeaders, signedMessage, verifyBatch } from '../lib/batch_signature'
+
+// Signed by the agent (`AgentKey::from_seed(&[7; 32]).sign_batch(body, 1760000000)`)
+const AGENT_PUBLIC_KEY = 'ea4a6c63e29c520abef5507b132ec5f9954776aebebe7b92421eea691446d22c'
+const AGENT_BODY = Buffer.from('{"schema_version":5,"agent_id":null,"events":[]}')
+const AGENT_SIG = {
+  keyId: 'fe812c12f3ab4ce6ac5db69ac352f906',
+  timestamp: 1760000000,
+  nonce: '3e3420f48fe0e241cc11dcef17e7a9bf',
+  signature: 'b8be92c3fa9146343fc70f64c297654616bdcf657133c12f7ad60628519425b705f0db2bea5cfbe3c7b1e83afed18564e3637f446756ea738530432f4768a907',
+}
+
+beforeEach(() => resetReplayGuard())
+
+test('signatures made by the agent verify', () => {
+  expect(keyIdFor(AGENT_PUBLIC_KEY)).toBe(AGENT_SIG.keyId)
+  expect(verifyBatch(AGENT_PUBLIC_KEY, AGENT_BODY, AGENT_SIG, AGENT_SIG.timestamp + 10)).toBeNull()
+})
+
+test('tampered, stale, replayed, and foreign batches are rejected', () => {
+  const now = AGENT_SIG.timestamp
+  const tampered = Buffer.from(AGENT_BODY.toString().replace('null', '"x"'))
+  expect(verifyBatch(AGENT_PUBLIC_KEY, tampered, AGENT_SIG, now)).toBe('invalid signature')
+  expect(verifyBatch(AGENT_PUBLIC_KEY, AGENT_BODY, AGENT_SIG, now + 301)).toBe('timestamp outside the accepted window')
+  expect(verifyBatch(AGENT_PUBLIC_KEY, AGENT_BODY, { ...AGENT_SIG, signature: 'zz' }, now)).toBe('malformed signature headers')
+
+  const other = crypto.generateKeyPairSync('ed25519').publicKey.export({ format: 'der', type: 'spki' }).subarray(12).toString('hex')
+  expect(verifyBatch(other, AGENT_BODY, AGENT_SIG, now)).toBe('key id does not match the public key')
+
+  expect(verifyBatch(AGENT_PUBLIC_KEY, AGENT_BODY, AGENT_SIG, now)).toBeNull()
+  expect(verifyBatch(AGENT_PUBLIC_KEY, AGENT_BODY, AGENT_SIG, now)).toBe('nonce already used')
+})
+
+test('signature headers are read from the lowercased request headers', () => {
+  const headers = {
+    'x-sentinel-key-id': AGENT_SIG.keyId,
+    'x-sentinel-timestamp': String(AGENT_SIG.timestamp),
+    'x-sentinel-nonce': AGENT_SIG.nonce,
+    'x-sentinel-signature': AGENT_SIG.signature,
+  }
+  expect(signatureFromHeaders(headers)).toEqual(AGENT_SIG)
+  expect(signatureFromHeaders({ ...headers, 'x-sentinel-timestamp': 'soon' })).toBeNull()
+  const { 'x-sentinel-signature': _, ...unsigned } = headers
+  expect(signatureFromHeaders(unsigned)).toBeNull()
+  expect(signedMessage('k', 1, 'n', Buffer.from('{}')).toString()).toBe('sentinel-batch-v1\nk\n1\nn\n{}')
+})
@@ -0,0 +1,95 @@
+import crypto from 'crypto'
+import { Client } from 'pg'
+import httpMocks from 'node-mocks-http'
+import { createEnrollmentCode } from '../lib/adminActions'
+import { keyIdFor, signedMessage } from '../lib/batch_signature'
+import enroll from '../pages/api/agents/enroll'
+import token from '../pages/api/agents/token'
+import events from '../pages/api/events'
+
+// This e2e test expects DATABASE_URL env var to point to a migrated Postgres instance.
+const dbUrl = process.env.DATABASE_URL
+
+async function postJson(handler: any, body: object) {
+  const req = httpMocks.createRequest({ method: 'POST', body })
+  const res = httpMocks.createResponse()
+  await handler(req, res)
+  return res
+}
+
+// A batch upload as the agent sends it: raw body, bearer credential, and signature headers
+async function upload(body: object, bearer: string, privateKey: crypto.KeyObject, keyId: string) {
+  const raw = Buffer.from(JSON.stringify(body))
+  const timestamp = Math.floor(Date.now() / 1000)
+  const nonce = crypto.randomBytes(16).toString('hex')
+  const signature = crypto.sign(null, signedMessage(keyId, timestamp, nonce, raw), privateKey).toString('hex')
+  const req = httpMocks.createRequest({
+    method: 'POST',
+    headers: {
+      authorization: `Bearer ${bearer}`,
+      'x-sentinel-key-id': keyId,
+      'x-sentinel-timestamp': String(timestamp),
+      'x-sentinel-nonce': nonce,
+      'x-sentinel-signature': signature,
+    },
+  })
+  const res = httpMocks.createResponse()
+  const done = events(req as any, res as any)
+  req.emit('data', raw)
+  req.emit('end')
+  await done
+  return res
+}
+
+if (!dbUrl) {
+  // Skip test when no DB available
+  test('e2e: skipped (no DATABASE_URL)', () => {
+    expect(true).toBe(true)
+  })
+} else {
+  test('e2e: an enrolled device uploads with its issued credential', async () => {
+    const client = new Client({ connectionString: dbUrl })
+    await client.connect()
+    await client.query('DELETE FROM agent_devices')
+    await client.query('DELETE FROM enrollment_codes')
+    await client.query('DELETE FROM agent_keys')
+
+    const code = await createEnrollmentCode(client, 'e2e-laptop', 600, 'e2e-test')
+    const agent = crypto.generateKeyPairSync('ed25519')
+    const publicKey = agent.publicKey.export({ format: 'der', type: 'spki' }).subarray(12).toString('hex')
+    const keyId = keyIdFor(publicKey)
+
+    const enrolled = await postJson(enroll, { code, key_id: keyId, public_key: publicKey, machine_id: 'e2e-machine', agent_version: '0.1.0' })
+    expect(enrolled.statusCode).toBe(200)
+    const cred = enrolled._getJSONData()
+    expect(cred.device_id).toEqual(expect.any(String))
+    expect(cred.expires_in).toBeGreaterThan(0)
+    // Codes are single-use
+    expect((await postJson(enroll, { code, key_id: keyId, public_key: publicKey })).statusCode).toBe(401)
+
+    const id = crypto.randomUUID()
+    const batch = { schema_version: 5, agent_id: null, events: [{ id, timestamp: new Date().toISOString(), secret_type: 'AWS', action: 'blocked' }] }
+    const res = await upload(batch, cred.access_token, agent.privateKey, keyId)
+    expect(res.statusCode).toBe(200)
+    expect(res._getJSONData().accepted).toEqual([id])
+    const stored = await client.query('SELECT 1 FROM telemetry_events WHERE id = $1', [id])
+    expect(stored.rowCount).toBe(1)
+
+    // The credential only covers batches signed with the device's own key
+    const other = crypto.generateKeyPairSync('ed25519')
+    const otherKeyId = keyIdFor(other.publicKey.export({ format: 'der', type: 'spki' }).subarray(12).toString('hex'))
+    expect((await upload(batch, cred.access_token, other.privateKey, otherKeyId)).statusCode).toBe(403)
+
+    // Refreshing rotates both tokens; the old ones stop working
+    const refreshed = await postJson(token, { grant_type: 'refresh_token', device_id: cred.device_id, refresh_token: cred.refresh_token })
+    expect(refreshed.statusCode).toBe(200)
+    const next = refreshed._getJSONData()
+    expect(next.device_id).toBe(cred.device_id)
+    expect((await upload(batch, cred.access_token, agent.privateKey, keyId)).statusCode).toBe(401)
+    expect((await upload(batch, next.access_token, agent.privateKey, keyId)).statusCode).toBe(200)
+    expect((await postJson(token, { grant_type: 'refresh_token', device_id: cred.device_id, refresh_token: cred.refresh_token })).statusCode).toBe(401)
+
+    await client.query('DELETE FROM telemetry_events WHERE id = $1', [id])
+    await client.end()
+  }, 20000)
+}
@@ -1,4 +1,9 @@
-import { validateEvent } from '../lib/validation'
+import {
+  validateEvent, validateBatch, isBatch,
+  ACTIONS, SECRET_TYPES, CLIPBOARD_STATUSES,
+  TelemetryEventObject, HeartbeatSchema, BypassGrantSchema, EventBatchSchema,
+} from '../lib/validation'
+import agentSchema from '../lib/event-schema.json'
 
 test('valid event passes validation', () => {
   const ev = {
@@ -19,3 +24,107 @@ test('invalid secret_type fails', () => {
   const res = validateEvent(ev)
   expect(res.success).toBe(false)
 })
+
+test('batch envelope passes validation', () => {
+  const batch = {
+    schema_version: 1,
+    agent_id: 'abc',
+    events: [{ id: '7d6f1c1e-8a0b-4c55-9a39-2a8c4e2b5f10', timestamp: new Date().toISOString(), secret_type: 'AWS', action: 'blocked' }],
+  }
+  expect(isBatch(batch)).toBe(true)
+  expect(validateBatch(batch).success).toBe(true)
+})
+
+test('timestamps must be RFC 3339', () => {
+  const ev = { timestamp: 't1', secret_type: 'AWS', action: 'blocked' }
+  expect(validateEvent(ev).success).toBe(false)
+})
+
+test('v2 batch with typed events passes validation', () => {
+  const batch = {
+    schema_version: 2,
+    agent_id: null,
+    events: [],
+  }
+  expect(validateBatch(batch).success).toBe(true)
+  const ev = {
+    schema_version: 2,
+    id: '7d6f1c1e-8a0b-4c55-9a39-2a8c4e2b5f10',
+    timestamp: '2025-03-04T05:06:07.891Z',
+    secret_type: 'npm',
+    action: 'clipboard_write_failed',
+  }
+  expect(validateEvent(ev).success).toBe(true)
+})
+
+test('batch with unknown schema version fails', () => {
+  const res = validateBatch({ schema_version: 99, events: [] })
+  expect(res.success).toBe(false)
+})
+
+test('heartbeat events carry health instead of a secret type', () => {
+  const hb = {
+    schema_version: 3,
+    id: '7d6f1c1e-8a0b-4c55-9a39-2a8c4e2b5f10',
+    timestamp: '2025-03-04T05:06:07Z',
+    action: 'heartbeat',
+    heartbeat: { uptime_secs: 300, policy_version: null, policy_hash: null, detector_count: 5, clipboard: 'ok', active_app_supported: false },
+  }
+  expect(validateEvent(hb).success).toBe(true)
+  expect(validateBatch({ schema_version: 3, events: [hb] }).success).toBe(true)
+  const { heartbeat, ...bare } = hb
+  expect(validateEvent(bare).success).toBe(false)
+  expect(validateEvent({ ...hb, action: 'blocked' }).success).toBe(false)
+})
+
+test('bypass events carry the justification and may omit the secret type', () => {
+  const ev = {
+    schema_version: 4,
+    id: '0b7e6a52-2f4c-4d3b-8f0e-6c1d2a9b3e47',
+    timestamp: '2025-03-04T05:06:07Z',
+    action: 'bypass_granted',
+    rule: 'pause',
+    bypass: { justification: 'AWS console setup', duration_secs: 900, expires_at: '2025-03-04T05:21:07Z' },
+  }
+  expect(validateEvent(ev).success).toBe(true)
+  expect(validateEvent({ ...ev, secret_type: 'AWS', rule: 'bypass' }).success).toBe(true)
+  expect(validateBatch({ schema_version: 4, events: [ev] }).success).toBe(true)
+  const { bypass, ...bare } = ev
+  expect(validateEvent(bare).success).toBe(false)
+  expect(validateEvent({ ...ev, bypass: { ...ev.bypass, justification: '' } }).success).toBe(false)
+})
+
+test('false positive reports carry the finding fingerprint', () => {
+  const ev = {
+    schema_version: 5,
+    id: '5c2d8f1e-7a3b-4e6c-9d0f-1b2a3c4d5e6f',
+    timestamp: '2025-03-04T05:06:07Z',
+    secret_type: 'AWS',
+    action: 'false_positive_reported',
+    app_name: 'Slack',
+    rule: 'user-report',
+    fingerprint: '3f9a0c1d2e4b5a6c',
+  }
+  expect(validateEvent(ev).success).toBe(true)
+  expect(validateBatch({ schema_version: 5, events: [ev] }).success).toBe(true)
+  const { fingerprint, ...bare } = ev
+  expect(validateEvent(bare).success).toBe(false)
+  expect(validateEvent({ ...ev, fingerprint: 'AKIA-not-a-hash' }).success).toBe(false)
+})
+
+// lib/event-schema.json is the output of `sentinel_pii schema`; the agent's tests fail when it is stale
+test('validators match the agent JSON Schema', () => {
+  const defs: any = agentSchema.definitions
+  const enumOf = (def: any): string[] => def.enum ?? def.oneOf.flatMap((v: any) => v.enum)
+  expect([...ACTIONS].sort()).toEqual(enumOf(defs.Action).sort())
+  expect([...SECRET_TYPES].sort()).toEqual(enumOf(defs.Detector).sort())
+  expect([...CLIPBOARD_STATUSES].sort()).toEqual(enumOf(defs.ClipboardStatus).sort())
+
+  const fields = (shape: object) => Object.keys(shape).sort()
+  expect(fields(EventBatchSchema.shape)).toEqual(Object.keys(agentSchema.properties).sort())
+  expect(fields(HeartbeatSchema.shape)).toEqual(Object.keys(defs.Heartbeat.properties).sort())
+  expect(fields(BypassGrantSchema.shape)).toEqual(Object.keys(defs.BypassGrant.properties).sort())
+  // `event_id` is still accepted from agents that predate the `id` rename
+  const eventFields = fields(TelemetryEventObject.shape).filter(f => f !== 'event_id')
+  expect(eventFields).toEqual(Object.keys(defs.TelemetryEvent.properties).sort())
+})
@@ -22,3 +22,16 @@ export async function rotateIngestKey(client: any, id: string, actor?: string) {
 
   return token
 }
+
+// One-time code an agent redeems with `sentinel_pii enroll`; valid for `ttlSecs`
+export async function createEnrollmentCode(client: any, name: string, ttlSecs: number, actor?: string) {
+  const code = crypto.randomBytes(16).toString('hex')
+  const hash = crypto.createHash('sha256').update(code).digest('hex')
+
+  await client.query('INSERT INTO enrollment_codes(code_hash, name, expires_at) VALUES($1,$2,now() + make_interval(secs => $3))', [hash, name, ttlSecs])
+
+  const details = { name, ttl_secs: ttlSecs }
+  await client.query('INSERT INTO audit_logs(actor, action, details) VALUES($1,$2,$3)', [actor || null, 'CREATE_ENROLLMENT_CODE', JSON.stringify(details)])
+
+  return code
+}
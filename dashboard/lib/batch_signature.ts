@@ -0,0 +1,101 @@
+import crypto from 'crypto'
+import { Client } from 'pg'
+
+// Verifies the agent's batch signatures; mirrors `sentinel_pii::signing::verify_batch`.
+
+export const HEADER_KEY_ID = 'x-sentinel-key-id'
+export const HEADER_TIMESTAMP = 'x-sentinel-timestamp'
+export const HEADER_NONCE = 'x-sentinel-nonce'
+export const HEADER_SIGNATURE = 'x-sentinel-signature'
+
+const SIGNATURE_CONTEXT = 'sentinel-batch-v1'
+export const MAX_SKEW_SECS = 300
+
+// DER SubjectPublicKeyInfo header for a raw 32-byte Ed25519 public key
+const ED25519_SPKI_PREFIX = Buffer.from('302a300506032b6570032100', 'hex')
+
+export type BatchSignature = { keyId: string; timestamp: number; nonce: string; signature: string }
+
+// Hex of the first 16 bytes of SHA-256 over the raw public key
+export function keyIdFor(publicKeyHex: string): string {
+  return crypto.createHash('sha256').update(Buffer.from(publicKeyHex, 'hex')).digest('hex').slice(0, 32)
+}
+
+export function isPublicKey(publicKeyHex: unknown): publicKeyHex is string {
+  return typeof publicKeyHex === 'string' && /^[0-9a-f]{64}$/i.test(publicKeyHex)
+}
+
+// Reads the four signature headers; null if any is missing or the timestamp is not a number
+export function signatureFromHeaders(headers: Record<string, string | string[] | undefined>): BatchSignature | null {
+  const header = (name: string) => {
+    const v = headers[name]
+    return Array.isArray(v) ? v[0] : v
+  }
+  const keyId = header(HEADER_KEY_ID)
+  const timestamp = header(HEADER_TIMESTAMP)
+  const nonce = header(HEADER_NONCE)
+  const signature = header(HEADER_SIGNATURE)
+  if (!keyId || !timestamp || !nonce || !signature || !/^-?\d+$/.test(timestamp.trim())) return null
+  return { keyId, timestamp: Number(timestamp.trim()), nonce, signature }
+}
+
+export function signedMessage(keyId: string, timestamp: number, nonce: string, body: Buffer): Buffer {
+  return Buffer.concat([Buffer.from(`${SIGNATURE_CONTEXT}\n${keyId}\n${timestamp}\n${nonce}\n`), body])
+}
+
+// Nonces seen within the clock window, per key id; a captured request cannot be replayed
+const seen: Map<string, number> = new Map()
+
+export function resetReplayGuard() {
+  seen.clear()
+}
+
+// Returns why the batch is rejected, or null when the signature checks out. `now` is unix
+// seconds. The nonce is only recorded for valid signatures, so forged requests cannot fill the cache.
+export function verifyBatch(publicKeyHex: string, body: Buffer, sig: BatchSignature, now: number): string | null {
+  if (sig.keyId !== keyIdFor(publicKeyHex)) return 'key id does not match the public key'
+  if (!/^([0-9a-f]{2}){16,}$/i.test(sig.nonce) || !/^[0-9a-f]{128}$/i.test(sig.signature)) return 'malformed signature headers'
+  if (Math.abs(now - sig.timestamp) > MAX_SKEW_SECS) return 'timestamp outside the accepted window'
+  let ok = false
+  try {
+    const key = crypto.createPublicKey({ key: Buffer.concat([ED25519_SPKI_PREFIX, Buffer.from(publicKeyHex, 'hex')]), format: 'der', type: 'spki' })
+    ok = crypto.verify(null, signedMessage(sig.keyId, sig.timestamp, sig.nonce, body), key, Buffer.from(sig.signature, 'hex'))
+  } catch {
+    ok = false
+  }
+  if (!ok) return 'invalid signature'
+
+  for (const [k, ts] of seen) {
+    if (Math.abs(now - ts) > MAX_SKEW_SECS) seen.delete(k)
+  }
+  const replayKey = `${sig.keyId}:${sig.nonce}`
+  if (seen.has(replayKey)) return 'nonce already used'
+  seen.set(replayKey, sig.timestamp)
+  return null
+}
+
+// Public key (hex) of an enrolled, enabled agent signing key
+export async function lookupAgentKey(keyId: string, connString: string): Promise<string | null> {
+  const client = new Client({ connectionString: connString })
+  try {
+    await client.connect()
+    const res = await client.query('SELECT public_key FROM agent_keys WHERE key_id = $1 AND enabled = true LIMIT 1', [keyId])
+    return res.rows[0]?.public_key ?? null
+  } catch (err) {
+    console.error('DB error looking up agent key', err)
+    return null
+  } finally {
+    await client.end()
+  }
+}
+
+// The exact request body; the signature covers these bytes, so Next's body parser is off for
+// signed endpoints
+export function readRawBody(req: NodeJS.ReadableStream): Promise<Buffer> {
+  return new Promise((resolve, reject) => {
+    const chunks: Buffer[] = []
+    req.on('data', (c: Buffer | string) => chunks.push(Buffer.from(c)))
+    req.on('end', () => resolve(Buffer.concat(chunks)))
+    req.on('error', reject)
+  })
+}
@@ -0,0 +1,84 @@
+import crypto from 'crypto'
+import { Client } from 'pg'
+import { v4 as uuidv4 } from 'uuid'
+
+// Per-device credentials issued at enrollment (see docs/telemetry.md, Enrollment). Only hashes
+// of codes and tokens are stored, like ingest keys.
+
+export const ACCESS_TOKEN_TTL_SECS = 3600
+
+export type TokenResponse = { device_id: string; access_token: string; refresh_token: string; expires_in: number }
+
+export type EnrollRequest = { code: string; key_id: string; public_key: string; machine_id?: string | null; agent_version?: string | null }
+
+const hash = (token: string) => crypto.createHash('sha256').update(token).digest('hex')
+const newToken = () => crypto.randomBytes(32).toString('hex')
+
+// Redeem a one-time code: enrolls the device and its signing key. Null if the code is unknown,
+// used, or expired.
+export async function enrollDevice(client: any, req: EnrollRequest): Promise<TokenResponse | null> {
+  await client.query('BEGIN')
+  try {
+    const code = await client.query(
+      'UPDATE enrollment_codes SET used_at = now() WHERE code_hash = $1 AND used_at IS NULL AND expires_at > now() RETURNING name',
+      [hash(req.code)]
+    )
+    if (code.rowCount === 0) {
+      await client.query('ROLLBACK')
+      return null
+    }
+    const publicKey = req.public_key.toLowerCase()
+    await client.query(
+      `INSERT INTO agent_keys(key_id, public_key, name, enabled) VALUES($1, $2, $3, TRUE)
+       ON CONFLICT (key_id) DO UPDATE SET enabled = TRUE`,
+      [req.key_id, publicKey, code.rows[0].name || null]
+    )
+    const device_id = uuidv4()
+    const access_token = newToken()
+    const refresh_token = newToken()
+    await client.query(
+      `INSERT INTO agent_devices(device_id, key_id, machine_id, agent_version, access_token_hash, access_expires_at, refresh_token_hash)
+       VALUES($1, $2, $3, $4, $5, now() + make_interval(secs => $6), $7)`,
+      [device_id, req.key_id, req.machine_id || null, req.agent_version || null, hash(access_token), ACCESS_TOKEN_TTL_SECS, hash(refresh_token)]
+    )
+    await client.query('COMMIT')
+    return { device_id, access_token, refresh_token, expires_in: ACCESS_TOKEN_TTL_SECS }
+  } catch (err) {
+    await client.query('ROLLBACK')
+    throw err
+  }
+}
+
+// Issue a new access token and rotate the refresh token. Null if the device is unknown or
+// disabled, or the refresh token is not its current one.
+export async function refreshDevice(client: any, deviceId: string, refreshToken: string): Promise<TokenResponse | null> {
+  const access_token = newToken()
+  const refresh_token = newToken()
+  const res = await client.query(
+    `UPDATE agent_devices SET access_token_hash = $3, access_expires_at = now() + make_interval(secs => $4), refresh_token_hash = $5
+     WHERE device_id::text = $1 AND refresh_token_hash = $2 AND enabled = TRUE`,
+    [deviceId, hash(refreshToken), hash(access_token), ACCESS_TOKEN_TTL_SECS, hash(refresh_token)]
+  )
+  if (res.rowCount === 0) return null
+  return { device_id: deviceId, access_token, refresh_token, expires_in: ACCESS_TOKEN_TTL_SECS }
+}
+
+// The device an unexpired access token belongs to, with the signing key it enrolled
+export async function verifyDeviceToken(token: string, connString: string): Promise<{ deviceId: string; keyId: string } | null> {
+  if (!token) return null
+  const client = new Client({ connectionString: connString })
+  try {
+    await client.connect()
+    const res = await client.query(
+      'SELECT device_id, key_id FROM agent_devices WHERE access_token_hash = $1 AND enabled = true AND access_expires_at > now() LIMIT 1',
+      [hash(token)]
+    )
+    const row = res.rows[0]
+    return row ? { deviceId: String(row.device_id), keyId: row.key_id } : null
+  } catch (err) {
+    console.error('DB error checking device token', err)
+    return null
+  } finally {
+    await client.end()
+  }
+}
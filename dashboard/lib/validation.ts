@@ -1,18 +1,86 @@
 import { z } from 'zod'
 
-export const TelemetryEventSchema = z.object({
+// Mirrors the agent's JSON Schema (`sentinel_pii schema`), checked in as `event-schema.json`;
+// __tests__/validation.test.ts fails when the two drift apart.
+
+export const SECRET_TYPES = ['AWS', 'Stripe', 'GitHub', 'npm', 'Slack'] as const
+export const ACTIONS = ['blocked', 'allowed', 'detected_but_skipped', 'clipboard_write_failed', 'heartbeat', 'bypass_granted', 'false_positive_reported'] as const
+export const CLIPBOARD_STATUSES = ['ok', 'failing'] as const
+
+export const HeartbeatSchema = z.object({
+  uptime_secs: z.number().int().nonnegative(),
+  policy_version: z.string().optional().nullable(),
+  policy_hash: z.string().optional().nullable(),
+  detector_count: z.number().int().nonnegative(),
+  clipboard: z.enum(CLIPBOARD_STATUSES),
+  active_app_supported: z.boolean(),
+})
+
+export type Heartbeat = z.infer<typeof HeartbeatSchema>
+
+export const BypassGrantSchema = z.object({
+  justification: z.string().min(1),
+  duration_secs: z.number().int().nonnegative(),
+  expires_at: z.string().datetime({ offset: true }),
+})
+
+export type BypassGrant = z.infer<typeof BypassGrantSchema>
+
+// Without the per-action refinement, so its fields can be compared with the JSON Schema
+export const TelemetryEventObject = z.object({
+  schema_version: z.number().int().optional(),
+  id: z.string().uuid().optional(),
   event_id: z.string().uuid().optional(),
-  timestamp: z.string(),
-  secret_type: z.enum(['AWS', 'Stripe']),
-  action: z.enum(['blocked', 'allowed', 'detected_but_skipped']),
+  timestamp: z.string().datetime({ offset: true }),
+  // Absent on heartbeats and unscoped bypasses, required on decisions (see the refinement below)
+  secret_type: z.enum(SECRET_TYPES).optional(),
+  action: z.enum(ACTIONS),
   app_name: z.string().optional().nullable(),
   rule: z.string().optional().nullable(),
   machine_id_hashed: z.string().optional().nullable(),
   agent_version: z.string().optional().nullable(),
+  prev_hash: z.string().optional().nullable(),
+  heartbeat: HeartbeatSchema.optional(),
+  bypass: BypassGrantSchema.optional(),
+  // Finding fingerprint (truncated HMAC-SHA256 hex) on false_positive_reported events
+  fingerprint: z.string().regex(/^[0-9a-f]+$/).optional(),
 })
 
+export const TelemetryEventSchema = TelemetryEventObject.refine(
+  ev => {
+    if (ev.action === 'heartbeat') return ev.heartbeat !== undefined
+    if (ev.action === 'bypass_granted') return ev.bypass !== undefined
+    if (ev.action === 'false_positive_reported') return ev.secret_type !== undefined && ev.fingerprint !== undefined
+    return ev.secret_type !== undefined
+  },
+  { message: 'heartbeat events need `heartbeat`, bypass_granted events need `bypass`, false_positive_reported events need `fingerprint`; other events need `secret_type`' },
+)
+
 export type TelemetryEvent = z.infer<typeof TelemetryEventSchema>
 
+// Maximum events accepted in one batch; agents chunk their queue below this.
+export const MAX_BATCH_EVENTS = 500
+
+// Versioned upload envelope sent by agents. Events are validated one by one so a single bad
+// event does not reject the whole batch.
+export const EventBatchSchema = z.object({
+  // 1: untyped events; 2: typed enums, RFC 3339 timestamps, per-event schema_version;
+  // 3: heartbeat events; 4: bypass_granted events; 5: false_positive_reported events
+  schema_version: z.union([z.literal(1), z.literal(2), z.literal(3), z.literal(4), z.literal(5)]),
+  agent_id: z.string().optional().nullable(),
+  events: z.array(z.unknown()).max(MAX_BATCH_EVENTS),
+})
+
+export type EventBatch = z.infer<typeof EventBatchSchema>
+
 export function validateEvent(body: any) {
   return TelemetryEventSchema.safeParse(body)
 }
+
+export function validateBatch(body: any) {
+  return EventBatchSchema.safeParse(body)
+}
+
+export function isBatch(body: any): boolean {
+  return body != null && typeof body === 'object' && 'schema_version' in body && 'events' in body
+}
@@ -0,0 +1,44 @@
+import type { NextApiRequest, NextApiResponse } from 'next'
+import { Client } from 'pg'
+import { ensureAdminOr401 } from '../../../lib/adminAuth'
+import { z } from 'zod'
+
+// Codes are short-lived: a day by default, at most a week
+const CreateSchema = z.object({
+  name: z.string().min(1).max(100),
+  ttl_secs: z.number().int().positive().max(7 * 86400).default(86400),
+})
+
+export default async function handler(req: NextApiRequest, res: NextApiResponse) {
+  if (!(await ensureAdminOr401(req, res))) {
+    return
+  }
+
+  if (req.method !== 'POST') {
+    res.status(405).json({ error: 'Method not allowed' })
+    return
+  }
+
+  const parsed = CreateSchema.safeParse(req.body)
+  if (!parsed.success) {
+    res.status(400).json({ error: 'Invalid payload' })
+    return
+  }
+
+  const conn = process.env.DATABASE_URL
+  if (!conn) return res.status(500).json({ error: 'Database not configured' })
+
+  const client = new Client({ connectionString: conn })
+  await client.connect()
+  try {
+    const { name, ttl_secs } = parsed.data
+    const code = await import('../../../lib/adminActions').then(m => m.createEnrollmentCode(client, name, ttl_secs, req.headers['authorization'] || undefined))
+    // Return the plaintext code ONLY at creation time
+    res.status(201).json({ code, expires_in: ttl_secs })
+  } catch (err) {
+    console.error(err)
+    res.status(500).json({ error: 'Server error' })
+  } finally {
+    await client.end()
+  }
+}
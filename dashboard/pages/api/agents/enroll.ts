@@ -0,0 +1,50 @@
+import type { NextApiRequest, NextApiResponse } from 'next'
+import { Client } from 'pg'
+import { z } from 'zod'
+
+import { isPublicKey, keyIdFor } from '../../../lib/batch_signature'
+import { enrollDevice } from '../../../lib/devices'
+
+// Sent by `sentinel_pii enroll`; the public key becomes the device's batch signing key
+const EnrollSchema = z.object({
+  code: z.string().min(1),
+  key_id: z.string(),
+  public_key: z.string().refine(isPublicKey, 'public_key must be 32 bytes of hex'),
+  machine_id: z.string().optional().nullable(),
+  agent_version: z.string().optional().nullable(),
+}).refine(r => r.key_id === keyIdFor(r.public_key), { message: 'key_id does not match public_key' })
+
+export default async function handler(req: NextApiRequest, res: NextApiResponse) {
+  if (req.method !== 'POST') {
+    res.status(405).json({ error: 'Method not allowed' })
+    return
+  }
+
+  const parsed = EnrollSchema.safeParse(req.body)
+  if (!parsed.success) {
+    res.status(400).json({ error: 'Invalid payload', details: parsed.error.flatten() })
+    return
+  }
+
+  const conn = process.env.DATABASE_URL
+  if (!conn) {
+    res.status(500).json({ error: 'Database not configured' })
+    return
+  }
+
+  const client = new Client({ connectionString: conn })
+  try {
+    await client.connect()
+    const token = await enrollDevice(client, parsed.data)
+    if (!token) {
+      res.status(401).json({ error: 'Invalid or expired enrollment code' })
+      return
+    }
+    res.status(200).json(token)
+  } catch (err) {
+    console.error(err)
+    res.status(500).json({ error: 'Failed to enroll device' })
+  } finally {
+    await client.end()
+  }
+}
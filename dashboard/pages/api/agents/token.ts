@@ -0,0 +1,47 @@
+import type { NextApiRequest, NextApiResponse } from 'next'
+import { Client } from 'pg'
+import { z } from 'zod'
+
+import { refreshDevice } from '../../../lib/devices'
+
+// Refresh grant sent by agents before their access token expires, and after a 401 on upload
+const RefreshSchema = z.object({
+  grant_type: z.literal('refresh_token'),
+  device_id: z.string().min(1),
+  refresh_token: z.string().min(1),
+})
+
+export default async function handler(req: NextApiRequest, res: NextApiResponse) {
+  if (req.method !== 'POST') {
+    res.status(405).json({ error: 'Method not allowed' })
+    return
+  }
+
+  const parsed = RefreshSchema.safeParse(req.body)
+  if (!parsed.success) {
+    res.status(400).json({ error: 'Invalid payload', details: parsed.error.flatten() })
+    return
+  }
+
+  const conn = process.env.DATABASE_URL
+  if (!conn) {
+    res.status(500).json({ error: 'Database not configured' })
+    return
+  }
+
+  const client = new Client({ connectionString: conn })
+  try {
+    await client.connect()
+    const token = await refreshDevice(client, parsed.data.device_id, parsed.data.refresh_token)
+    if (!token) {
+      res.status(401).json({ error: 'Invalid refresh token' })
+      return
+    }
+    res.status(200).json(token)
+  } catch (err) {
+    console.error(err)
+    res.status(500).json({ error: 'Failed to refresh token' })
+  } finally {
+    await client.end()
+  }
+}
@@ -2,14 +2,19 @@ import type { NextApiRequest, NextApiResponse } from 'next'
 import { Client } from 'pg'
 import { v4 as uuidv4 } from 'uuid'
 
-import { validateEvent } from '../../lib/validation'
+import { validateEvent, validateBatch, isBatch, TelemetryEvent } from '../../lib/validation'
 import { allowRequest } from '../../lib/rate_limiter'
+import { lookupAgentKey, readRawBody, signatureFromHeaders, verifyBatch } from '../../lib/batch_signature'
+
+// Signatures cover the exact body bytes, so the body is read raw
+export const config = { api: { bodyParser: false } }
 
 export default async function handler(req: NextApiRequest, res: NextApiResponse) {
   if (req.method !== 'POST') {
     res.status(405).json({ error: 'Method not allowed' })
     return
   }
+  const raw = await readRawBody(req)
 
   // Basic API key token
   const auth = req.headers.authorization || ''
@@ -28,6 +33,14 @@ export default async function handler(req: NextApiRequest, res: NextApiResponse)
     }
   }
 
+  // Then the per-device access token issued at enrollment; uploads must be signed with that
+  // device's key. An unknown or expired token gets a 401, on which agents refresh it once.
+  let device: { deviceId: string; keyId: string } | null = null
+  if (!authorized && process.env.DATABASE_URL && token) {
+    device = await import('../../lib/devices').then(m => m.verifyDeviceToken(token, process.env.DATABASE_URL!))
+    authorized = device !== null
+  }
+
   if (!authorized) {
     res.status(401).json({ error: 'Unauthorized' })
     return
@@ -50,28 +63,91 @@ export default async function handler(req: NextApiRequest, res: NextApiResponse)
     }
   }
 
-  const body = req.body
-  const parsed = validateEvent(body)
-  if (!parsed.success) {
-    res.status(400).json({ error: 'Invalid payload', details: parsed.error.flatten() })
+  const conn = process.env.DATABASE_URL
+
+  // Every upload must be signed by an enrolled agent key (see docs/telemetry.md, Batch signatures)
+  const sig = signatureFromHeaders(req.headers)
+  if (!sig) {
+    res.status(403).json({ error: 'Missing batch signature' })
+    return
+  }
+  if (device && sig.keyId !== device.keyId) {
+    res.status(403).json({ error: 'Batch not signed with this device\'s key' })
     return
   }
-
-  const conn = process.env.DATABASE_URL
   if (!conn) {
     res.status(500).json({ error: 'Database not configured' })
     return
   }
+  const publicKey = await lookupAgentKey(sig.keyId, conn)
+  if (!publicKey) {
+    res.status(403).json({ error: 'Unknown agent key' })
+    return
+  }
+  const sigError = verifyBatch(publicKey, raw, sig, Math.floor(Date.now() / 1000))
+  if (sigError) {
+    res.status(403).json({ error: `Invalid batch signature: ${sigError}` })
+    return
+  }
+
+  let body: any
+  try {
+    body = JSON.parse(raw.toString('utf8'))
+  } catch {
+    res.status(400).json({ error: 'Invalid JSON' })
+    return
+  }
+
+  // Versioned batch envelope from agents: validate each event and ack per event id
+  if (isBatch(body)) {
+    const batch = validateBatch(body)
+    if (!batch.success) {
+      res.status(400).json({ error: 'Invalid batch', details: batch.error.flatten() })
+      return
+    }
+    const accepted: string[] = []
+    // `index` is the event's position in the batch, so events without a usable id are reported too
+    const rejected: { index: number; id?: string; error: string }[] = []
+    const client = new Client({ connectionString: conn })
+    try {
+      await client.connect()
+      for (const [index, raw] of batch.data.events.entries()) {
+        const parsed = validateEvent(raw)
+        const rawId = (raw as any)?.id ?? (raw as any)?.event_id
+        if (!parsed.success) {
+          rejected.push(typeof rawId === 'string' ? { index, id: rawId, error: 'invalid event' } : { index, error: 'invalid event' })
+          continue
+        }
+        const id = parsed.data.id ?? parsed.data.event_id
+        if (!id) {
+          rejected.push({ index, error: 'missing id' })
+          continue
+        }
+        // Agents retry until acked, so the event id doubles as an idempotency key
+        await insertEvent(client, id, parsed.data)
+        accepted.push(id)
+      }
+      res.status(200).json({ accepted, rejected })
+    } catch (err) {
+      console.error(err)
+      res.status(500).json({ error: 'Failed to store events' })
+    } finally {
+      await client.end()
+    }
+    return
+  }
+
+  const parsed = validateEvent(body)
+  if (!parsed.success) {
+    res.status(400).json({ error: 'Invalid payload', details: parsed.error.flatten() })
+    return
+  }
 
   const client = new Client({ connectionString: conn })
   try {
     await client.connect()
-    const id = uuidv4()
-    await client.query(
-      `INSERT INTO telemetry_events(id, timestamp, secret_type, action, app_name, rule, machine_id_hashed, agent_version)
-       VALUES($1, $2, $3, $4, $5, $6, $7, $8)`,
-      [id, parsed.data.timestamp, parsed.data.secret_type, parsed.data.action, parsed.data.app_name || null, parsed.data.rule || null, parsed.data.machine_id_hashed || null, parsed.data.agent_version || null]
-    )
+    const id = parsed.data.id ?? parsed.data.event_id ?? uuidv4()
+    await insertEvent(client, id, parsed.data)
     res.status(201).json({ id })
   } catch (err) {
     console.error(err)
@@ -80,3 +156,52 @@ export default async function handler(req: NextApiRequest, res: NextApiResponse)
     await client.end()
   }
 }
+
+async function insertEvent(client: Client, id: string, ev: TelemetryEvent) {
+  // Heartbeats only track agent liveness; they are kept out of the decision table
+  if (ev.action === 'heartbeat' && ev.heartbeat) {
+    const hb = ev.heartbeat
+    await client.query(
+      `INSERT INTO agent_heartbeats(machine_id_hashed, last_seen, agent_version, uptime_secs, policy_version, policy_hash, detector_count, clipboard, active_app_supported)
+       VALUES($1, $2, $3, $4, $5, $6, $7, $8, $9)
+       ON CONFLICT (machine_id_hashed) DO UPDATE SET
+         last_seen = GREATEST(agent_heartbeats.last_seen, EXCLUDED.last_seen),
+         agent_version = EXCLUDED.agent_version, uptime_secs = EXCLUDED.uptime_secs,
+         policy_version = EXCLUDED.policy_version, policy_hash = EXCLUDED.policy_hash,
+         detector_count = EXCLUDED.detector_count, clipboard = EXCLUDED.clipboard,
+         active_app_supported = EXCLUDED.active_app_supported
+       WHERE EXCLUDED.last_seen >= agent_heartbeats.last_seen`,
+      [ev.machine_id_hashed || 'unknown', ev.timestamp, ev.agent_version || null, hb.uptime_secs, hb.policy_version || null,
+       hb.policy_hash || null, hb.detector_count, hb.clipboard, hb.active_app_supported]
+    )
+    return
+  }
+  // Bypasses are not decisions on a secret; they are kept for review with their justification
+  if (ev.action === 'bypass_granted' && ev.bypass) {
+    const b = ev.bypass
+    await client.query(
+      `INSERT INTO agent_bypasses(id, timestamp, machine_id_hashed, agent_version, kind, secret_type, app_name, justification, duration_secs, expires_at)
+       VALUES($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+       ON CONFLICT (id) DO NOTHING`,
+      [id, ev.timestamp, ev.machine_id_hashed || null, ev.agent_version || null, ev.rule || 'bypass', ev.secret_type || null,
+       ev.app_name || null, b.justification, b.duration_secs, b.expires_at]
+    )
+    return
+  }
+  // False-positive reports are feedback on an earlier block, not decisions; kept for detector tuning
+  if (ev.action === 'false_positive_reported' && ev.fingerprint) {
+    await client.query(
+      `INSERT INTO false_positive_reports(id, timestamp, machine_id_hashed, agent_version, secret_type, app_name, fingerprint)
+       VALUES($1, $2, $3, $4, $5, $6, $7)
+       ON CONFLICT (id) DO NOTHING`,
+      [id, ev.timestamp, ev.machine_id_hashed || null, ev.agent_version || null, ev.secret_type, ev.app_name || null, ev.fingerprint]
+    )
+    return
+  }
+  await client.query(
+    `INSERT INTO telemetry_events(id, timestamp, secret_type, action, app_name, rule, machine_id_hashed, agent_version)
+     VALUES($1, $2, $3, $4, $5, $6, $7, $8)
+     ON CONFLICT (id) DO NOTHING`,
+    [id, ev.timestamp, ev.secret_type, ev.action, ev.app_name || null, ev.rule || null, ev.machine_id_hashed || null, ev.agent_version || null]
+  )
+}
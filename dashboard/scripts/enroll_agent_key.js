@@ -0,0 +1,31 @@
+const { Client } = require('pg');
+const crypto = require('crypto');
+require('dotenv').config();
+
+// Usage: node scripts/enroll_agent_key.js <public key hex> [name]
+// The public key is the one printed by `sentinel_pii public-key` on the agent.
+async function enroll() {
+  const url = process.env.DATABASE_URL;
+  if (!url) {
+    console.error('DATABASE_URL not set');
+    process.exit(1);
+  }
+
+  const [publicKey, name] = process.argv.slice(2);
+  if (!/^[0-9a-fA-F]{64}$/.test(publicKey || '')) {
+    console.error('usage: enroll_agent_key.js <public key hex> [name]');
+    process.exit(1);
+  }
+  const keyId = crypto.createHash('sha256').update(Buffer.from(publicKey, 'hex')).digest('hex').slice(0, 32);
+
+  const client = new Client({ connectionString: url });
+  await client.connect();
+  await client.query(
+    'INSERT INTO agent_keys(key_id, public_key, name, enabled) VALUES($1,$2,$3,true) ON CONFLICT (key_id) DO UPDATE SET enabled = true',
+    [keyId, publicKey.toLowerCase(), name || null]
+  );
+  await client.end();
+  console.log('Enrolled agent key:', keyId);
+}
+
+enroll().catch(err => { console.error(err); process.exit(1); });
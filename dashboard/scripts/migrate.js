@@ -15,12 +15,18 @@ async function migrate() {
   const schema = fs.readFileSync('./sql/schema.sql', 'utf8');
   await client.query(schema);
 
-  // Run additional migrations (ingest keys table and audit logs)
+  // Run additional migrations (ingest keys, audit logs, agent signing keys, and enrolled devices)
   const ingest = fs.readFileSync('./sql/add_ingest_keys.sql', 'utf8');
   await client.query(ingest);
 
   const audit = fs.readFileSync('./sql/add_audit_logs.sql', 'utf8');
   await client.query(audit);
+
+  const agentKeys = fs.readFileSync('./sql/add_agent_keys.sql', 'utf8');
+  await client.query(agentKeys);
+
+  const enrollment = fs.readFileSync('./sql/add_agent_enrollment.sql', 'utf8');
+  await client.query(enrollment);
   await client.end();
   console.log('Migration complete');
 }
@@ -0,0 +1,21 @@
+-- One-time enrollment codes; redeemed by `sentinel_pii enroll`
+CREATE TABLE IF NOT EXISTS enrollment_codes (
+  code_hash TEXT PRIMARY KEY,
+  name TEXT,
+  expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+  used_at TIMESTAMP WITH TIME ZONE,
+  created_at TIMESTAMP WITH TIME ZONE DEFAULT now()
+);
+
+-- Enrolled devices with their upload credential and signing key
+CREATE TABLE IF NOT EXISTS agent_devices (
+  device_id UUID PRIMARY KEY,
+  key_id TEXT NOT NULL REFERENCES agent_keys(key_id),
+  machine_id TEXT,
+  agent_version TEXT,
+  access_token_hash TEXT NOT NULL UNIQUE,
+  access_expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+  refresh_token_hash TEXT NOT NULL UNIQUE,
+  enabled BOOLEAN NOT NULL DEFAULT TRUE,
+  created_at TIMESTAMP WITH TIME ZONE DEFAULT now()
+);
@@ -0,0 +1,8 @@
+-- Agent Ed25519 signing keys; uploads must be signed by an enabled key
+CREATE TABLE IF NOT EXISTS agent_keys (
+  key_id TEXT PRIMARY KEY,
+  public_key TEXT NOT NULL,
+  name TEXT,
+  enabled BOOLEAN NOT NULL DEFAULT TRUE,
+  created_at TIMESTAMP WITH TIME ZONE DEFAULT now()
+);
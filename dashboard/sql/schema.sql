@@ -12,3 +12,47 @@ CREATE TABLE IF NOT EXISTS telemetry_events (
 
 CREATE INDEX IF NOT EXISTS idx_telemetry_events_timestamp ON telemetry_events (timestamp);
 CREATE INDEX IF NOT EXISTS idx_telemetry_events_app_name ON telemetry_events (app_name);
+
+-- Latest heartbeat per agent, for telling quiet machines from dead agents
+CREATE TABLE IF NOT EXISTS agent_heartbeats (
+  machine_id_hashed TEXT PRIMARY KEY,
+  last_seen TIMESTAMP WITH TIME ZONE NOT NULL,
+  agent_version TEXT,
+  uptime_secs BIGINT NOT NULL,
+  policy_version TEXT,
+  policy_hash TEXT,
+  detector_count INTEGER NOT NULL,
+  clipboard TEXT NOT NULL,
+  active_app_supported BOOLEAN NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_agent_heartbeats_last_seen ON agent_heartbeats (last_seen);
+
+-- User bypasses and pauses with their justification, for review by admins
+CREATE TABLE IF NOT EXISTS agent_bypasses (
+  id UUID PRIMARY KEY,
+  timestamp TIMESTAMP WITH TIME ZONE NOT NULL,
+  machine_id_hashed TEXT,
+  agent_version TEXT,
+  kind TEXT NOT NULL,
+  secret_type TEXT,
+  app_name TEXT,
+  justification TEXT NOT NULL,
+  duration_secs BIGINT NOT NULL,
+  expires_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_agent_bypasses_timestamp ON agent_bypasses (timestamp);
+
+-- Findings users reported as false positives, by fingerprint, for detector tuning
+CREATE TABLE IF NOT EXISTS false_positive_reports (
+  id UUID PRIMARY KEY,
+  timestamp TIMESTAMP WITH TIME ZONE NOT NULL,
+  machine_id_hashed TEXT,
+  agent_version TEXT,
+  secret_type TEXT NOT NULL,
+  app_name TEXT,
+  fingerprint TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_false_positive_reports_fingerprint ON false_positive_reports (fingerprint);
@@ -0,0 +1,246 @@
+use crate::telemetry::TelemetryEvent;
+use crate::verifier::Verification;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+/// One local audit entry: the same fields as a telemetry event plus the finding fingerprint.
+/// Never contains the secret itself.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AuditRecord {
+    #[serde(flatten)]
+    pub event: TelemetryEvent,
+    pub fingerprint: String,
+    /// Result of the live provider check (`--live-verify`), when one was made.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub live: Option<Verification>,
+}
+
+#[derive(Clone, Debug)]
+pub struct AuditConfig {
+    pub path: PathBuf,
+    /// Rotate the active log once it grows beyond this many bytes.
+    pub max_bytes: u64,
+    /// Number of rotated files (`{path}.1` .. `{path}.N`) to keep.
+    pub max_files: usize,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            path: crate::config::state_dir(false).join("audit.jsonl"),
+            max_bytes: 1_000_000,
+            max_files: 5,
+        }
+    }
+}
+
+/// Filters for `AuditLog::query`. Unset fields match everything.
+#[derive(Clone, Debug, Default)]
+pub struct AuditFilter {
+    pub since: Option<DateTime<Utc>>,
+    pub secret_type: Option<String>,
+    pub action: Option<String>,
+    pub app: Option<String>,
+    /// Return at most this many of the most recent matching records.
+    pub limit: Option<usize>,
+}
+
+impl AuditFilter {
+    pub fn matches(&self, r: &AuditRecord) -> bool {
+        if let Some(since) = self.since
+            && r.event.timestamp < since
+        {
+            return false;
+        }
+        if let Some(st) = &self.secret_type
+            && !r.event.secret_type.is_some_and(|d| d.as_str().eq_ignore_ascii_case(st))
+        {
+            return false;
+        }
+        if let Some(a) = &self.action
+            && !r.event.action.as_str().eq_ignore_ascii_case(a)
+        {
+            return false;
+        }
+        if let Some(app) = &self.app {
+            match &r.event.app_name {
+                Some(name) if crate::context::matches_app(name, app) => {}
+                _ => return false,
+            }
+        }
+        true
+    }
+}
+
+/// Append-only local log of detections and decisions, kept regardless of telemetry settings.
+pub struct AuditLog {
+    cfg: AuditConfig,
+}
+
+impl AuditLog {
+    pub fn new(cfg: AuditConfig) -> Self {
+        Self { cfg }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.cfg.path
+    }
+
+    /// Append a record, rotating first if the active file is over the size limit.
+    pub fn append(&self, record: &AuditRecord) -> std::io::Result<()> {
+        if let Some(parent) = self.cfg.path.parent() {
+            crate::config::create_private_dir(parent)?;
+        }
+        self.rotate_if_needed()?;
+
+        let mut opts = OpenOptions::new();
+        opts.create(true).append(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            opts.mode(0o600);
+        }
+        let mut f = opts.open(&self.cfg.path)?;
+        let line = serde_json::to_string(record)?;
+        writeln!(f, "{}", line)?;
+        Ok(())
+    }
+
+    fn rotated_path(&self, n: usize) -> PathBuf {
+        let mut s = self.cfg.path.clone().into_os_string();
+        s.push(format!(".{}", n));
+        PathBuf::from(s)
+    }
+
+    /// Shift `{path}.N-1` to `{path}.N` (dropping the oldest) and move the active file to `{path}.1`.
+    pub fn rotate_if_needed(&self) -> std::io::Result<()> {
+        let len = match std::fs::metadata(&self.cfg.path) {
+            Ok(m) => m.len(),
+            Err(_) => return Ok(()),
+        };
+        if len <= self.cfg.max_bytes {
+            return Ok(());
+        }
+        if self.cfg.max_files == 0 {
+            return std::fs::remove_file(&self.cfg.path);
+        }
+        let _ = std::fs::remove_file(self.rotated_path(self.cfg.max_files));
+        for n in (1..self.cfg.max_files).rev() {
+            let from = self.rotated_path(n);
+            if from.exists() {
+                std::fs::rename(&from, self.rotated_path(n + 1))?;
+            }
+        }
+        std::fs::rename(&self.cfg.path, self.rotated_path(1))?;
+        log::info!("Rotated audit log {:?}", self.cfg.path);
+        Ok(())
+    }
+
+    /// Read all records, oldest first, across rotated files and the active file.
+    /// Unparseable lines are skipped.
+    pub fn read_all(&self) -> std::io::Result<Vec<AuditRecord>> {
+        let mut files: Vec<PathBuf> = (1..=self.cfg.max_files).rev().map(|n| self.rotated_path(n)).collect();
+        files.push(self.cfg.path.clone());
+
+        let mut out = Vec::new();
+        for p in files {
+            let f = match OpenOptions::new().read(true).open(&p) {
+                Ok(f) => f,
+                Err(_) => continue,
+            };
+            for line in BufReader::new(f).lines() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match serde_json::from_str::<AuditRecord>(&line) {
+                    Ok(r) => out.push(r),
+                    Err(e) => log::debug!("Skipping unparseable audit line in {:?}: {}", p, e),
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    /// Records matching `filter`, oldest first, keeping only the last `filter.limit` entries.
+    pub fn query(&self, filter: &AuditFilter) -> std::io::Result<Vec<AuditRecord>> {
+        let mut recs: Vec<AuditRecord> = self.read_all()?.into_iter().filter(|r| filter.matches(r)).collect();
+        if let Some(limit) = filter.limit
+            && recs.len() > limit
+        {
+            recs.drain(..recs.len() - limit);
+        }
+        Ok(recs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::Detector;
+    use crate::telemetry::{Action, SCHEMA_VERSION};
+
+    fn record(id: &str, action: Action, app: &str) -> AuditRecord {
+        AuditRecord {
+            event: TelemetryEvent {
+                schema_version: SCHEMA_VERSION,
+                event_id: id.to_string(),
+                timestamp: Utc::now(),
+                secret_type: Some(Detector::Aws),
+                action,
+                app_name: Some(app.to_string()),
+                rule: None,
+                machine_id_hashed: None,
+                agent_version: "0.1.0".to_string(),
+                heartbeat: None,
+                bypass: None,
+                fingerprint: None,
+                prev_hash: None,
+            },
+            fingerprint: "0123456789abcdef".to_string(),
+            live: None,
+        }
+    }
+
+    #[test]
+    fn append_rotate_and_query() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = AuditLog::new(AuditConfig {
+            path: dir.path().join("audit.jsonl"),
+            max_bytes: 200,
+            max_files: 2,
+        });
+
+        for i in 0..6 {
+            let action = if i % 2 == 0 { Action::Blocked } else { Action::DetectedButSkipped };
+            log.append(&record(&i.to_string(), action, "Slack")).unwrap();
+        }
+        assert!(dir.path().join("audit.jsonl.1").exists());
+
+        let blocked = log
+            .query(&AuditFilter { action: Some("blocked".to_string()), ..Default::default() })
+            .unwrap();
+        assert!(!blocked.is_empty());
+        assert!(blocked.iter().all(|r| r.event.action == Action::Blocked));
+
+        let last = log.query(&AuditFilter { limit: Some(1), ..Default::default() }).unwrap();
+        assert_eq!(last.len(), 1);
+        assert_eq!(last[0].event.event_id, "5");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn log_and_its_directory_are_private() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let state = dir.path().join("state");
+        let log = AuditLog::new(AuditConfig { path: state.join("audit.jsonl"), ..Default::default() });
+        log.append(&record("1", Action::Blocked, "Slack")).unwrap();
+        let mode = |p: &Path| std::fs::metadata(p).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&state), 0o700);
+        assert_eq!(mode(&state.join("audit.jsonl")), 0o600);
+    }
+}
@@ -0,0 +1,227 @@
+//! Temporary user bypass ("snooze"): for a limited time, detected secrets are let through
+//! instead of blocked, optionally only for one detector or one app. Every bypass needs a
+//! justification, expires by itself, and is reported as a `bypass_granted` telemetry event.
+//! A `pause` of the control API is the broadest bypass and follows the same rules.
+//!
+//! Admins can turn bypasses off or cap their duration with `BypassPolicy`, in the config file
+//! or the remote policy.
+
+use crate::context::matches_app;
+use crate::scanner::Detector;
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::sync::{Mutex, RwLock};
+
+/// Longest bypass when the policy sets no cap.
+pub const DEFAULT_MAX_MINUTES: u32 = 480;
+
+/// Longest accepted justification, in characters.
+pub const MAX_JUSTIFICATION_CHARS: usize = 500;
+
+/// Admin limits on bypasses and pauses.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct BypassPolicy {
+    /// Whether users may bypass or pause (default: true).
+    pub enabled: Option<bool>,
+    /// Longest bypass or pause, in minutes (default: 480).
+    pub max_minutes: Option<u32>,
+}
+
+impl BypassPolicy {
+    pub fn allowed(&self) -> bool {
+        self.enabled.unwrap_or(true)
+    }
+
+    pub fn max_minutes(&self) -> u32 {
+        self.max_minutes.unwrap_or(DEFAULT_MAX_MINUTES)
+    }
+}
+
+/// A bypass or pause request.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct BypassRequest {
+    pub minutes: u32,
+    pub justification: String,
+    /// Only let this detector's findings through.
+    #[serde(default)]
+    pub detector: Option<Detector>,
+    /// Only while an app matching this (case-insensitive substring) is frontmost.
+    #[serde(default)]
+    pub app: Option<String>,
+}
+
+/// An active bypass.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Bypass {
+    pub justification: String,
+    pub granted_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub detector: Option<Detector>,
+    pub app: Option<String>,
+}
+
+impl Bypass {
+    /// Whether this bypass covers a finding of `detector` while `active_app` is frontmost. An
+    /// app-scoped bypass never covers an unknown app.
+    pub fn covers(&self, detector: Detector, active_app: Option<&str>) -> bool {
+        self.detector.is_none_or(|d| d == detector)
+            && self.app.as_deref().is_none_or(|scope| active_app.is_some_and(|app| matches_app(app, scope)))
+    }
+
+    /// Telemetry payload of the `bypass_granted` event.
+    pub fn grant(&self) -> BypassGrant {
+        BypassGrant {
+            justification: self.justification.clone(),
+            duration_secs: (self.expires_at - self.granted_at).num_seconds().max(0) as u64,
+            expires_at: self.expires_at,
+        }
+    }
+}
+
+/// Payload of a `bypass_granted` event. The scope is in the event itself: `secret_type` for
+/// the detector and `app_name` for the app, so the app goes through the privacy controls.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+pub struct BypassGrant {
+    /// Why the user bypassed the agent, as they typed it.
+    pub justification: String,
+    pub duration_secs: u64,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Check a request against the policy and turn it into a bypass starting now.
+pub fn validate(req: BypassRequest, policy: &BypassPolicy) -> Result<Bypass, String> {
+    if !policy.allowed() {
+        return Err("bypass is disabled by policy".to_string());
+    }
+    if req.minutes == 0 {
+        return Err("minutes must be at least 1".to_string());
+    }
+    if req.minutes > policy.max_minutes() {
+        return Err(format!("policy allows at most {} minutes", policy.max_minutes()));
+    }
+    let justification = req.justification.trim();
+    if justification.is_empty() {
+        return Err("a justification is required".to_string());
+    }
+    if justification.chars().count() > MAX_JUSTIFICATION_CHARS {
+        return Err(format!("justification is longer than {} characters", MAX_JUSTIFICATION_CHARS));
+    }
+    let app = req.app.map(|a| a.trim().to_string()).filter(|a| !a.is_empty());
+    let granted_at = Utc::now();
+    Ok(Bypass {
+        justification: justification.to_string(),
+        granted_at,
+        expires_at: granted_at + chrono::Duration::minutes(req.minutes.into()),
+        detector: req.detector,
+        app,
+    })
+}
+
+/// Active bypasses and the policy that limits new ones.
+#[derive(Default)]
+pub struct Bypasses {
+    policy: RwLock<BypassPolicy>,
+    active: Mutex<Vec<Bypass>>,
+}
+
+impl Bypasses {
+    pub fn set_policy(&self, policy: BypassPolicy) {
+        if !policy.allowed() && !self.active.lock().unwrap().is_empty() {
+            log::warn!("Bypass disabled by policy; ending active bypasses");
+            self.clear();
+        }
+        *self.policy.write().unwrap() = policy;
+    }
+
+    pub fn policy(&self) -> BypassPolicy {
+        self.policy.read().unwrap().clone()
+    }
+
+    /// Validate and activate a bypass.
+    pub fn grant(&self, req: BypassRequest) -> Result<Bypass, String> {
+        let bypass = validate(req, &self.policy())?;
+        log::warn!(
+            "Bypass granted until {} (detector={}, app={}): {}",
+            bypass.expires_at.to_rfc3339(),
+            bypass.detector.map_or("any", |d| d.as_str()),
+            bypass.app.as_deref().unwrap_or("any"),
+            bypass.justification
+        );
+        self.active.lock().unwrap().push(bypass.clone());
+        Ok(bypass)
+    }
+
+    /// The active bypass covering this finding, if any. Expired bypasses are dropped here.
+    pub fn covering(&self, detector: Detector, active_app: Option<&str>) -> Option<Bypass> {
+        self.active().into_iter().find(|b| b.covers(detector, active_app))
+    }
+
+    /// Bypasses that have not expired yet.
+    pub fn active(&self) -> Vec<Bypass> {
+        let now = Utc::now();
+        let mut active = self.active.lock().unwrap();
+        active.retain(|b| {
+            let live = b.expires_at > now;
+            if !live {
+                log::info!("Bypass expired (detector={}, app={})", b.detector.map_or("any", |d| d.as_str()), b.app.as_deref().unwrap_or("any"));
+            }
+            live
+        });
+        active.clone()
+    }
+
+    pub fn clear(&self) {
+        self.active.lock().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(minutes: u32, justification: &str) -> BypassRequest {
+        BypassRequest { minutes, justification: justification.to_string(), detector: None, app: None }
+    }
+
+    #[test]
+    fn requests_are_checked_against_the_policy() {
+        let open = BypassPolicy::default();
+        assert!(validate(request(30, "AWS console setup"), &open).is_ok());
+        assert!(validate(request(30, "   "), &open).unwrap_err().contains("justification"));
+        assert!(validate(request(0, "x"), &open).is_err());
+        assert!(validate(request(DEFAULT_MAX_MINUTES + 1, "x"), &open).is_err());
+        assert!(validate(request(30, &"x".repeat(MAX_JUSTIFICATION_CHARS + 1)), &open).is_err());
+
+        let capped = BypassPolicy { enabled: None, max_minutes: Some(10) };
+        assert!(validate(request(11, "x"), &capped).unwrap_err().contains("at most 10"));
+        let off = BypassPolicy { enabled: Some(false), max_minutes: None };
+        assert!(validate(request(5, "x"), &off).unwrap_err().contains("disabled"));
+    }
+
+    #[test]
+    fn scope_and_expiry() {
+        let bypasses = Bypasses::default();
+        let mut req = request(5, "pasting into the AWS console");
+        req.detector = Some(Detector::Aws);
+        req.app = Some("chrome".to_string());
+        let b = bypasses.grant(req).unwrap();
+        assert_eq!(b.grant().duration_secs, 300);
+
+        assert!(bypasses.covering(Detector::Aws, Some("Google Chrome")).is_some());
+        assert!(bypasses.covering(Detector::Aws, Some("Slack")).is_none());
+        assert!(bypasses.covering(Detector::Aws, None).is_none());
+        assert!(bypasses.covering(Detector::Stripe, Some("Google Chrome")).is_none());
+
+        bypasses.active.lock().unwrap()[0].expires_at = Utc::now() - chrono::Duration::seconds(1);
+        assert!(bypasses.covering(Detector::Aws, Some("Google Chrome")).is_none());
+        assert!(bypasses.active().is_empty());
+
+        bypasses.grant(request(5, "x")).unwrap();
+        bypasses.set_policy(BypassPolicy { enabled: Some(false), max_minutes: None });
+        assert!(bypasses.active().is_empty());
+        assert!(bypasses.grant(request(5, "x")).is_err());
+    }
+}
@@ -0,0 +1,368 @@
+//! Tamper-evident hash chain over the telemetry queue.
+//!
+//! Every queued event stores `prev_hash`, the SHA-256 of the previous queue line. Operations
+//! that legitimately remove lines (pruning) replace each removed run with a signed checkpoint
+//! line, and rotation starts the new segment with a signed checkpoint naming the segment it
+//! continues from. A signed head file records where the chain starts and ends, so truncating
+//! the tail or deleting a whole segment is also detected.
+//!
+//! Signatures are HMAC-SHA256 with a per-install key: derived from the queue master key when
+//! that is kept in the OS keyring, otherwise stored next to the queue (0600). This catches
+//! casual edits and deletions; it cannot stop someone who can also read the key.
+//!
+//! The agent writes the head together with every append, so `verify` requires the head's
+//! `last_hash` to be the hash of the final line.
+
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// SHA-256 (hex) of a single queue line, without its trailing newline.
+pub fn line_hash(line: &str) -> String {
+    hex::encode(Sha256::digest(line.as_bytes()))
+}
+
+/// A signed marker that stands in for removed lines or a segment boundary.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Checkpoint {
+    /// "prune" or "rotate"
+    pub reason: String,
+    pub timestamp: String,
+    /// Chain hash expected immediately before this checkpoint.
+    pub prev_hash: Option<String>,
+    /// Chain hash that the next line must reference. For "prune" this is the hash of the last
+    /// removed line; for "rotate" it equals `prev_hash`.
+    pub last_hash: Option<String>,
+    /// Number of lines this checkpoint replaces.
+    pub dropped: usize,
+    /// File name of the previous segment, for "rotate".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub segment: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CheckpointLine {
+    pub checkpoint: Checkpoint,
+    pub sig: String,
+}
+
+/// Signed record of where the chain currently starts and ends.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChainHead {
+    /// `prev_hash` expected on the first line of the oldest rotated segment, if any.
+    #[serde(default)]
+    pub segments_base: Option<String>,
+    /// `prev_hash` expected on the first line of the active queue file. Advances on flush.
+    pub base_hash: Option<String>,
+    /// Hash of the most recently written line.
+    pub last_hash: Option<String>,
+    #[serde(default)]
+    pub sig: String,
+}
+
+/// Per-install HMAC key used to sign checkpoints and the chain head.
+pub struct ChainKey(Vec<u8>);
+
+impl ChainKey {
+    /// Load the key from `path`, creating a random 32 byte key with 0600 permissions if missing.
+    pub fn load_or_create(path: &Path) -> std::io::Result<Self> {
+        if let Ok(mut f) = OpenOptions::new().read(true).open(path) {
+            let mut s = String::new();
+            f.read_to_string(&mut s)?;
+            if let Ok(k) = hex::decode(s.trim())
+                && !k.is_empty()
+            {
+                return Ok(Self(k));
+            }
+        }
+        let mut key = vec![0u8; 32];
+        getrandom::getrandom(&mut key).map_err(std::io::Error::other)?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut opts = OpenOptions::new();
+        opts.create(true).write(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            opts.mode(0o600);
+        }
+        let mut f = opts.open(path)?;
+        writeln!(f, "{}", hex::encode(&key))?;
+        Ok(Self(key))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Self(bytes.to_vec())
+    }
+
+    /// Derive the key from the queue master key (see `crypto`), e.g. one kept in the OS keyring.
+    pub fn from_master_key(master: &[u8]) -> Self {
+        let mut mac = HmacSha256::new_from_slice(master).expect("HMAC accepts any key length");
+        mac.update(b"sentinel-queue-chain-v1");
+        Self(mac.finalize().into_bytes().to_vec())
+    }
+
+    fn sign(&self, data: &[u8]) -> String {
+        let mut mac = HmacSha256::new_from_slice(&self.0).expect("HMAC accepts any key length");
+        mac.update(data);
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    fn check(&self, data: &[u8], sig: &str) -> bool {
+        let Ok(raw) = hex::decode(sig) else {
+            return false;
+        };
+        let mut mac = HmacSha256::new_from_slice(&self.0).expect("HMAC accepts any key length");
+        mac.update(data);
+        mac.verify_slice(&raw).is_ok()
+    }
+
+    pub fn sign_checkpoint(&self, checkpoint: Checkpoint) -> CheckpointLine {
+        let body = serde_json::to_vec(&checkpoint).expect("checkpoint serializes");
+        CheckpointLine { sig: self.sign(&body), checkpoint }
+    }
+
+    pub fn verify_checkpoint(&self, line: &CheckpointLine) -> bool {
+        let body = serde_json::to_vec(&line.checkpoint).expect("checkpoint serializes");
+        self.check(&body, &line.sig)
+    }
+
+    fn head_body(head: &ChainHead) -> String {
+        format!(
+            "{}|{}|{}",
+            head.segments_base.as_deref().unwrap_or(""),
+            head.base_hash.as_deref().unwrap_or(""),
+            head.last_hash.as_deref().unwrap_or("")
+        )
+    }
+
+    pub fn sign_head(&self, head: &mut ChainHead) {
+        head.sig = self.sign(Self::head_body(head).as_bytes());
+    }
+
+    pub fn verify_head(&self, head: &ChainHead) -> bool {
+        self.check(Self::head_body(head).as_bytes(), &head.sig)
+    }
+}
+
+/// Build a signed checkpoint line for the queue file.
+pub fn checkpoint_line(
+    key: &ChainKey,
+    reason: &str,
+    prev_hash: Option<String>,
+    last_hash: Option<String>,
+    dropped: usize,
+    segment: Option<String>,
+) -> String {
+    let cp = Checkpoint {
+        reason: reason.to_string(),
+        timestamp: Utc::now().to_rfc3339(),
+        prev_hash,
+        last_hash,
+        dropped,
+        segment,
+    };
+    serde_json::to_string(&key.sign_checkpoint(cp)).expect("checkpoint serializes")
+}
+
+/// Chain hash that the line after `line` must reference: the checkpoint's `last_hash` for
+/// checkpoint lines, otherwise the hash of the line itself.
+pub fn next_hash(line: &str) -> Option<String> {
+    match parse_checkpoint(line) {
+        Some(cp) => cp.checkpoint.last_hash,
+        None => Some(line_hash(line)),
+    }
+}
+
+/// `prev_hash` referenced by a queue line (checkpoint or event).
+pub fn referenced_hash(line: &str) -> Option<String> {
+    match parse_checkpoint(line) {
+        Some(cp) => cp.checkpoint.prev_hash,
+        None => serde_json::from_str::<serde_json::Value>(line)
+            .ok()?
+            .get("prev_hash")?
+            .as_str()
+            .map(|s| s.to_string()),
+    }
+}
+
+/// Returns the checkpoint if the queue line is one.
+pub fn parse_checkpoint(line: &str) -> Option<CheckpointLine> {
+    if !line.trim_start().starts_with("{\"checkpoint\"") {
+        return None;
+    }
+    serde_json::from_str(line).ok()
+}
+
+pub fn read_head(path: &Path) -> Option<ChainHead> {
+    let s = std::fs::read_to_string(path).ok()?;
+    serde_json::from_str(&s).ok()
+}
+
+/// Sign and atomically replace the head file.
+pub fn write_head(path: &Path, key: &ChainKey, mut head: ChainHead) -> std::io::Result<()> {
+    key.sign_head(&mut head);
+    let mut tmp = path.to_path_buf().into_os_string();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    {
+        let mut opts = OpenOptions::new();
+        opts.create(true).write(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            opts.mode(0o600);
+        }
+        let mut f = opts.open(&tmp)?;
+        f.write_all(serde_json::to_string(&head)?.as_bytes())?;
+        f.sync_all()?;
+    }
+    std::fs::rename(&tmp, path)
+}
+
+/// One problem found by `verify`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChainIssue {
+    pub file: PathBuf,
+    /// 1-based line number, or 0 for file-level issues.
+    pub line: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for ChainIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.file.display(), self.line, self.message)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct VerifyReport {
+    pub lines: usize,
+    pub checkpoints: usize,
+    pub issues: Vec<ChainIssue>,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// Walk rotated `segments` (oldest first) and then the `active` queue file, checking every link,
+/// checkpoint signature, and the head.
+pub fn verify(segments: &[PathBuf], active: &Path, head_path: &Path, key: &ChainKey) -> std::io::Result<VerifyReport> {
+    let mut report = VerifyReport::default();
+    let head = read_head(head_path);
+    let issue = |file: &Path, line: usize, message: String| ChainIssue { file: file.to_path_buf(), line, message };
+
+    let head = match head {
+        Some(h) => {
+            if !key.verify_head(&h) {
+                report.issues.push(issue(head_path, 0, "chain head signature invalid".to_string()));
+            }
+            h
+        }
+        None => {
+            if active.exists() || !segments.is_empty() {
+                report.issues.push(issue(head_path, 0, "chain head missing".to_string()));
+            }
+            ChainHead::default()
+        }
+    };
+
+    let mut running = if segments.is_empty() { head.base_hash.clone() } else { head.segments_base.clone() };
+    let mut prev_segment: Option<String> = None;
+    let files: Vec<&Path> = segments.iter().map(|p| p.as_path()).chain(std::iter::once(active)).collect();
+    for (idx, path) in files.iter().copied().enumerate() {
+        let is_active = idx == files.len() - 1;
+        let f = match OpenOptions::new().read(true).open(path) {
+            Ok(f) => f,
+            Err(_) => continue,
+        };
+        if is_active && idx > 0 {
+            // The active file either continues straight from the newest segment (starting with
+            // its rotation checkpoint) or was flushed since, in which case the head's base applies.
+            let first = BufReader::new(OpenOptions::new().read(true).open(path)?)
+                .lines()
+                .map_while(Result::ok)
+                .find(|l| !l.trim().is_empty());
+            let rotated_here = first
+                .as_deref()
+                .and_then(parse_checkpoint)
+                .is_some_and(|cp| cp.checkpoint.reason == "rotate");
+            if !rotated_here {
+                running = head.base_hash.clone();
+            }
+        }
+        for (i, line) in BufReader::new(f).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            report.lines += 1;
+            let lineno = i + 1;
+
+            if let Some(cp) = parse_checkpoint(&line) {
+                report.checkpoints += 1;
+                if !key.verify_checkpoint(&cp) {
+                    report.issues.push(issue(path, lineno, "checkpoint signature invalid".to_string()));
+                }
+                if cp.checkpoint.prev_hash != running {
+                    report.issues.push(issue(path, lineno, "checkpoint does not link to previous line".to_string()));
+                }
+                if cp.checkpoint.reason == "rotate" && idx > 0 && cp.checkpoint.segment != prev_segment {
+                    report.issues.push(issue(path, lineno, "rotation checkpoint names an unexpected segment".to_string()));
+                }
+                running = cp.checkpoint.last_hash.clone();
+            } else if serde_json::from_str::<serde_json::Value>(&line).is_err() {
+                report.issues.push(issue(path, lineno, "line is not valid JSON".to_string()));
+                running = Some(line_hash(&line));
+            } else {
+                if referenced_hash(&line) != running {
+                    report.issues.push(issue(path, lineno, "gap or edit: prev_hash does not match previous line".to_string()));
+                }
+                running = Some(line_hash(&line));
+            }
+        }
+        prev_segment = path.file_name().map(|n| n.to_string_lossy().to_string());
+    }
+
+    if head.last_hash != running {
+        report.issues.push(issue(head_path, 0, "chain head does not match last line (truncated log?)".to_string()));
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checkpoint_signature_roundtrip() {
+        let key = ChainKey::from_bytes(b"k");
+        let line = checkpoint_line(&key, "prune", Some("a".into()), Some("b".into()), 2, None);
+        let mut cp = parse_checkpoint(&line).expect("parses as checkpoint");
+        assert!(key.verify_checkpoint(&cp));
+        cp.checkpoint.dropped = 1;
+        assert!(!key.verify_checkpoint(&cp));
+        assert!(!ChainKey::from_bytes(b"other").verify_checkpoint(&parse_checkpoint(&line).unwrap()));
+    }
+
+    #[test]
+    fn head_signature_roundtrip() {
+        let key = ChainKey::from_bytes(b"k");
+        let mut head = ChainHead { last_hash: Some("x".into()), ..Default::default() };
+        key.sign_head(&mut head);
+        assert!(key.verify_head(&head));
+        head.last_hash = Some("y".into());
+        assert!(!key.verify_head(&head));
+    }
+}
@@ -0,0 +1,425 @@
+use crate::bypass::BypassPolicy;
+use crate::crypto::KeySource;
+use crate::machine_id::MachineIdMode;
+use crate::policy_sync::PolicyBundle;
+use crate::privacy::AppNameLevel;
+use crate::telemetry::{Action, TelemetryConfig};
+use crate::throttle::{NotifyThrottleConfig, QuietHours};
+use anyhow::Context;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Config file read in system mode (pushed by MDM).
+pub const SYSTEM_CONFIG_PATH: &str = "/etc/sentinel/config.json";
+
+/// State directory in system mode.
+pub const SYSTEM_STATE_DIR: &str = "/var/lib/sentinel";
+
+/// Runtime directory (control socket) in system mode.
+pub const SYSTEM_RUNTIME_DIR: &str = "/run/sentinel";
+
+/// Per-user (or, in system mode, machine-wide) directory for agent state such as the telemetry
+/// queue: `$XDG_STATE_HOME/sentinel`, falling back to `~/.local/state/sentinel`.
+pub fn state_dir(system: bool) -> PathBuf {
+    if system {
+        return PathBuf::from(SYSTEM_STATE_DIR);
+    }
+    if let Some(d) = env_dir("XDG_STATE_HOME") {
+        return d.join("sentinel");
+    }
+    #[cfg(windows)]
+    if let Some(d) = env_dir("LOCALAPPDATA") {
+        return d.join("sentinel");
+    }
+    match env_dir("HOME") {
+        Some(home) => home.join(".local").join("state").join("sentinel"),
+        None => std::env::temp_dir().join("sentinel"),
+    }
+}
+
+/// Default config file: `/etc/sentinel/config.json` in system mode, otherwise
+/// `$XDG_CONFIG_HOME/sentinel/config.json` (falling back to `~/.config`).
+pub fn default_config_path(system: bool) -> PathBuf {
+    if system {
+        return PathBuf::from(SYSTEM_CONFIG_PATH);
+    }
+    let base = env_dir("XDG_CONFIG_HOME")
+        .or_else(|| env_dir("HOME").map(|h| h.join(".config")))
+        .unwrap_or_else(std::env::temp_dir);
+    base.join("sentinel").join("config.json")
+}
+
+/// Absolute path from an environment variable; relative or empty values are ignored, as the
+/// XDG spec requires.
+fn env_dir(var: &str) -> Option<PathBuf> {
+    let v = std::env::var_os(var)?;
+    let p = PathBuf::from(v);
+    p.is_absolute().then_some(p)
+}
+
+/// Create `path` and any missing parents with 0700 permissions. Existing directories are left
+/// as they are, so pointing the queue at a shared directory never changes its mode.
+pub fn create_private_dir(path: &Path) -> std::io::Result<()> {
+    let mut builder = std::fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    builder.create(path)
+}
+
+/// Directory for the control socket: `$XDG_RUNTIME_DIR/sentinel`, falling back to the state
+/// directory.
+pub fn runtime_dir(system: bool) -> PathBuf {
+    if system {
+        return PathBuf::from(SYSTEM_RUNTIME_DIR);
+    }
+    match env_dir("XDG_RUNTIME_DIR") {
+        Some(d) => d.join("sentinel"),
+        None => state_dir(false),
+    }
+}
+
+/// Agent config file (JSON). Every field is optional; command-line flags take precedence.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct FileConfig {
+    pub denylist: Option<Vec<String>>,
+    pub allowlist: Option<Vec<String>>,
+    pub verified_only: Option<Vec<String>>,
+    pub telemetry: TelemetryFileConfig,
+    pub remote_policy: RemotePolicyFileConfig,
+    pub metrics: MetricsFileConfig,
+    pub control: ControlFileConfig,
+    pub bypass: BypassPolicy,
+    pub notifications: NotificationsFileConfig,
+}
+
+/// `notifications` section of the config file.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct NotificationsFileConfig {
+    /// Seconds after a notification during which blocks of the same detector or in the same
+    /// app are coalesced into a summary (default: 60).
+    pub cooldown_secs: Option<u64>,
+    /// No notifications in this local-time range, e.g. `"22:00-07:00"`.
+    pub quiet_hours: Option<QuietHours>,
+}
+
+/// `control` section of the config file.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ControlFileConfig {
+    /// Serve the local control socket (default: true).
+    pub enabled: Option<bool>,
+    /// Socket path; defaults to `control.sock` in the runtime directory.
+    pub socket: Option<PathBuf>,
+}
+
+/// `metrics` section of the config file.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsFileConfig {
+    /// Serve Prometheus metrics on this loopback address. Off when unset.
+    pub addr: Option<std::net::SocketAddr>,
+}
+
+/// `remote_policy` section of the config file.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct RemotePolicyFileConfig {
+    /// Policy endpoint; defaults to `/api/agents/policy` on the telemetry server.
+    pub url: Option<String>,
+    /// Management server's Ed25519 public key (hex). Remote policy is off without it.
+    pub public_key: Option<String>,
+    pub interval_secs: Option<u64>,
+}
+
+/// `telemetry` section of the config file.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct TelemetryFileConfig {
+    pub enabled: Option<bool>,
+    pub url: Option<String>,
+    pub api_key: Option<String>,
+    pub queue_file: Option<PathBuf>,
+    pub max_queue_bytes: Option<u64>,
+    pub max_age_days: Option<u32>,
+    pub max_total_bytes: Option<u64>,
+    pub encrypt: Option<bool>,
+    pub key_source: Option<KeySource>,
+    pub actions: Option<Vec<Action>>,
+    pub heartbeat_interval_secs: Option<u64>,
+    pub throttle: ThrottleFileConfig,
+    pub machine_id: MachineIdFileConfig,
+    pub privacy: PrivacyFileConfig,
+    pub transport: TransportFileConfig,
+}
+
+/// `telemetry.throttle` section of the config file.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThrottleFileConfig {
+    pub window_secs: Option<u64>,
+    pub max_events: Option<u32>,
+}
+
+/// `telemetry.transport` section of the config file.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct TransportFileConfig {
+    pub ca_bundle: Option<PathBuf>,
+    pub client_cert: Option<PathBuf>,
+    pub client_key: Option<PathBuf>,
+    pub client_pkcs12: Option<PathBuf>,
+    pub client_pkcs12_password: Option<String>,
+    pub pinned_spki: Option<Vec<String>>,
+    pub proxy: Option<String>,
+    pub no_proxy: Option<String>,
+}
+
+/// `telemetry.privacy` section of the config file.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct PrivacyFileConfig {
+    pub app_name: Option<AppNameLevel>,
+    pub min_app_count: Option<u32>,
+    pub sample_rates: Option<HashMap<Action, f64>>,
+    pub timestamp_granularity_secs: Option<u64>,
+}
+
+/// `telemetry.machine_id` section of the config file.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct MachineIdFileConfig {
+    pub mode: Option<MachineIdMode>,
+    pub salt: Option<String>,
+    pub rotation_days: Option<u32>,
+    pub mdm_device_id: Option<String>,
+}
+
+impl FileConfig {
+    /// Parse the config file at `path`.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let data = std::fs::read_to_string(path).with_context(|| format!("reading config file {:?}", path))?;
+        serde_json::from_str(&data).with_context(|| format!("parsing config file {:?}", path))
+    }
+
+    /// Like `load`, but a missing file yields the empty config.
+    pub fn load_optional(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            log::debug!("No config file at {:?}", path);
+            return Ok(Self::default());
+        }
+        Self::load(path)
+    }
+
+    /// Overlay a remote policy bundle: every field it sets replaces the file's.
+    pub fn apply_policy(&mut self, bundle: &PolicyBundle) {
+        if bundle.denylist.is_some() {
+            self.denylist = bundle.denylist.clone();
+        }
+        if bundle.allowlist.is_some() {
+            self.allowlist = bundle.allowlist.clone();
+        }
+        if bundle.verified_only.is_some() {
+            self.verified_only = bundle.verified_only.clone();
+        }
+        if bundle.actions.is_some() {
+            self.telemetry.actions = bundle.actions.clone();
+        }
+        if let Some(b) = &bundle.bypass {
+            if b.enabled.is_some() {
+                self.bypass.enabled = b.enabled;
+            }
+            if b.max_minutes.is_some() {
+                self.bypass.max_minutes = b.max_minutes;
+            }
+        }
+    }
+
+    /// Overlay the file's notification settings onto `cfg`.
+    pub fn apply_notifications(&self, cfg: &mut NotifyThrottleConfig) {
+        let n = &self.notifications;
+        if let Some(v) = n.cooldown_secs {
+            cfg.cooldown = std::time::Duration::from_secs(v);
+        }
+        if n.quiet_hours.is_some() {
+            cfg.quiet_hours = n.quiet_hours;
+        }
+    }
+
+    /// Overlay the file's telemetry settings onto `cfg`.
+    pub fn apply_telemetry(&self, cfg: &mut TelemetryConfig) {
+        let t = &self.telemetry;
+        if let Some(v) = t.enabled {
+            cfg.enabled = v;
+        }
+        if t.url.is_some() {
+            cfg.url = t.url.clone();
+        }
+        if t.api_key.is_some() {
+            cfg.api_key = t.api_key.clone();
+        }
+        if let Some(v) = &t.queue_file {
+            cfg.queue_file = v.clone();
+        }
+        if let Some(v) = t.max_queue_bytes {
+            cfg.max_queue_bytes = v;
+        }
+        if let Some(v) = t.max_age_days {
+            cfg.max_age_days = v;
+        }
+        if let Some(v) = t.max_total_bytes {
+            cfg.max_total_bytes = v;
+        }
+        if let Some(v) = t.encrypt {
+            cfg.encrypt = v;
+        }
+        if let Some(v) = t.key_source {
+            cfg.key_source = v;
+        }
+        if let Some(v) = &t.actions {
+            cfg.actions = v.clone();
+        }
+        if let Some(v) = t.heartbeat_interval_secs {
+            cfg.heartbeat_interval_secs = v;
+        }
+        if let Some(v) = t.throttle.window_secs {
+            cfg.throttle.window = std::time::Duration::from_secs(v);
+        }
+        if let Some(v) = t.throttle.max_events {
+            cfg.throttle.max_events = v;
+        }
+        let m = &t.machine_id;
+        if let Some(v) = m.mode {
+            cfg.machine_id.mode = v;
+        }
+        if m.salt.is_some() {
+            cfg.machine_id.salt = m.salt.clone();
+        }
+        if m.rotation_days.is_some() {
+            cfg.machine_id.rotation_days = m.rotation_days;
+        }
+        if m.mdm_device_id.is_some() {
+            cfg.machine_id.mdm_device_id = m.mdm_device_id.clone();
+        }
+        let p = &t.privacy;
+        if let Some(v) = p.app_name {
+            cfg.privacy.app_name = v;
+        }
+        if let Some(v) = p.min_app_count {
+            cfg.privacy.min_app_count = v;
+        }
+        if let Some(v) = &p.sample_rates {
+            cfg.privacy.sample_rates = v.clone();
+        }
+        if let Some(v) = p.timestamp_granularity_secs {
+            cfg.privacy.timestamp_granularity_secs = v;
+        }
+        let tr = &t.transport;
+        if tr.ca_bundle.is_some() {
+            cfg.transport.ca_bundle = tr.ca_bundle.clone();
+        }
+        if tr.client_cert.is_some() {
+            cfg.transport.client_cert = tr.client_cert.clone();
+        }
+        if tr.client_key.is_some() {
+            cfg.transport.client_key = tr.client_key.clone();
+        }
+        if tr.client_pkcs12.is_some() {
+            cfg.transport.client_pkcs12 = tr.client_pkcs12.clone();
+        }
+        if tr.client_pkcs12_password.is_some() {
+            cfg.transport.client_pkcs12_password = tr.client_pkcs12_password.clone();
+        }
+        if tr.proxy.is_some() {
+            cfg.transport.proxy = tr.proxy.clone();
+        }
+        if tr.no_proxy.is_some() {
+            cfg.transport.no_proxy = tr.no_proxy.clone();
+        }
+        if let Some(v) = &tr.pinned_spki {
+            cfg.transport.pinned_spki = v.clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_config_overlays_telemetry_settings() {
+        let file: FileConfig = serde_json::from_str(
+            r#"{"denylist": ["Slack"], "telemetry": {"enabled": true, "max_queue_bytes": 4096, "max_age_days": 7, "key_source": "keyring", "heartbeat_interval_secs": 60, "throttle": {"max_events": 5}, "actions": ["blocked", "clipboard_write_failed"], "machine_id": {"mode": "hostname-hmac", "salt": "s"},
+                "privacy": {"app_name": "category", "sample_rates": {"detected_but_skipped": 0.1}},
+                "transport": {"ca_bundle": "/etc/sentinel/ca.pem", "pinned_spki": ["sha256/abc"], "proxy": "http://proxy:3128"}}}"#,
+        )
+        .unwrap();
+        let mut cfg = TelemetryConfig::default();
+        file.apply_telemetry(&mut cfg);
+        assert!(cfg.enabled);
+        assert_eq!(cfg.max_queue_bytes, 4096);
+        assert_eq!(cfg.max_age_days, 7);
+        assert_eq!(cfg.key_source, KeySource::Keyring);
+        assert!(!cfg.encrypt);
+        assert_eq!(cfg.heartbeat_interval_secs, 60);
+        assert_eq!(cfg.throttle.max_events, 5);
+        assert_eq!(cfg.throttle.window, TelemetryConfig::default().throttle.window);
+        assert_eq!(cfg.actions, vec![Action::Blocked, Action::ClipboardWriteFailed]);
+        assert_eq!(cfg.machine_id.mode, MachineIdMode::HostnameHmac);
+        assert_eq!(cfg.machine_id.salt.as_deref(), Some("s"));
+        assert_eq!(cfg.privacy.app_name, AppNameLevel::Category);
+        assert_eq!(cfg.privacy.sample_rates.get(&Action::DetectedButSkipped), Some(&0.1));
+        assert_eq!(cfg.max_total_bytes, TelemetryConfig::default().max_total_bytes);
+        assert_eq!(cfg.transport.ca_bundle, Some(PathBuf::from("/etc/sentinel/ca.pem")));
+        assert_eq!(cfg.transport.pinned_spki, vec!["sha256/abc".to_string()]);
+        assert_eq!(cfg.transport.proxy.as_deref(), Some("http://proxy:3128"));
+        assert!(cfg.transport.client_cert.is_none());
+        assert_eq!(file.denylist, Some(vec!["Slack".to_string()]));
+
+        assert!(serde_json::from_str::<FileConfig>(r#"{"telemetry": {"max_age": 7}}"#).is_err());
+
+        let file: FileConfig = serde_json::from_str(r#"{"metrics": {"addr": "127.0.0.1:9464"}}"#).unwrap();
+        assert_eq!(file.metrics.addr, Some("127.0.0.1:9464".parse().unwrap()));
+
+        let file: FileConfig = serde_json::from_str(r#"{"notifications": {"cooldown_secs": 300, "quiet_hours": "22:00-07:00"}}"#).unwrap();
+        let mut notify = NotifyThrottleConfig::default();
+        file.apply_notifications(&mut notify);
+        assert_eq!(notify.cooldown.as_secs(), 300);
+        assert_eq!(notify.quiet_hours, Some("22:00-07:00".parse().unwrap()));
+        assert!(serde_json::from_str::<FileConfig>(r#"{"notifications": {"quiet_hours": "late"}}"#).is_err());
+    }
+
+    #[test]
+    fn policy_bundle_overrides_the_file() {
+        let mut file: FileConfig = serde_json::from_str(
+            r#"{"denylist": ["Slack"], "allowlist": ["Terminal"], "telemetry": {"actions": ["blocked"]}, "bypass": {"max_minutes": 60}}"#,
+        )
+        .unwrap();
+        let bundle: PolicyBundle =
+            serde_json::from_str(r#"{"version": "7", "denylist": ["Discord"], "actions": ["blocked", "allowed"], "bypass": {"enabled": false}}"#).unwrap();
+        file.apply_policy(&bundle);
+        assert_eq!(file.denylist, Some(vec!["Discord".to_string()]));
+        assert_eq!(file.allowlist, Some(vec!["Terminal".to_string()]));
+        assert_eq!(file.telemetry.actions, Some(vec![Action::Blocked, Action::Allowed]));
+        assert_eq!(file.bypass, BypassPolicy { enabled: Some(false), max_minutes: Some(60) });
+        assert!(serde_json::from_str::<PolicyBundle>(r#"{"denylist": [], "rules": []}"#).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn private_dirs_are_0700() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let nested = dir.path().join("a").join("b");
+        create_private_dir(&nested).unwrap();
+        for p in [dir.path().join("a"), nested] {
+            assert_eq!(std::fs::metadata(&p).unwrap().permissions().mode() & 0o777, 0o700);
+        }
+    }
+}
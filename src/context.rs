@@ -1,3 +1,4 @@
+#[cfg(target_os = "macos")]
 use std::process::Command;
 
 /// Returns the frontmost application's name when available.
@@ -29,11 +30,21 @@ pub fn get_active_app() -> Option<String> {
     None
 }
 
+/// Whether `get_active_app` can report the frontmost app on this platform.
+pub fn active_app_supported() -> bool {
+    cfg!(target_os = "macos")
+}
+
 /// Case-insensitive substring match helper.
 pub fn matches_app(app_name: &str, pattern: &str) -> bool {
     app_name.to_lowercase().contains(&pattern.to_lowercase())
 }
 
+/// True if the active app is known and matches an allowlist entry.
+pub fn is_allowlisted(active_app: Option<&str>, allowlist: &[String]) -> bool {
+    active_app.is_some_and(|app| allowlist.iter().any(|a| matches_app(app, a)))
+}
+
 /// Decide whether to redact based on the active app, allowlist, and denylist.
 ///
 /// Rules:
@@ -41,12 +52,8 @@ pub fn matches_app(app_name: &str, pattern: &str) -> bool {
 /// - If denylist is non-empty -> redact only if the active app matches any denylist entry (otherwise DO NOT redact).
 /// - If both lists are empty -> redact by default.
 pub fn should_redact(active_app: Option<&str>, denylist: &[String], allowlist: &[String]) -> bool {
-    if !allowlist.is_empty() {
-        if let Some(app) = active_app {
-            if allowlist.iter().any(|a| matches_app(app, a)) {
-                return false;
-            }
-        }
+    if is_allowlisted(active_app, allowlist) {
+        return false;
     }
 
     if !denylist.is_empty() {
@@ -0,0 +1,623 @@
+//! Local control API of the running agent: line-delimited JSON-RPC 2.0 over a Unix domain
+//! socket that only the owning user can reach.
+//!
+//! Methods: `status`, `pause {minutes, justification}`, `bypass {minutes, justification,
+//! detector?, app?}`, `resume`, `reload`, `restore_last`, `explain`, and
+//! `recent_events {limit}`. The server thread only records requests in `AgentControl`; the
+//! main loop applies them (reload, clipboard restore) on its next poll, so the clipboard and
+//! config are never touched from two threads.
+//!
+//! The transport is behind `ControlListener` so a Windows named pipe can replace the socket
+//! without touching the protocol.
+
+use crate::audit::AuditRecord;
+use crate::bypass::{self, Bypass, BypassPolicy, BypassRequest, Bypasses};
+use crate::heartbeat::{Health, Heartbeat};
+use crate::scanner::Detector;
+use crate::telemetry::{Action, Telemetry};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// Socket file name in the runtime directory.
+pub const SOCKET_NAME: &str = "control.sock";
+
+/// How many decisions `recent_events` can return.
+const RECENT_CAPACITY: usize = 100;
+
+/// Clients that stop sending are dropped after this long, so one stuck client cannot block
+/// the others.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+// JSON-RPC 2.0 error codes
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+/// The request was valid but cannot be carried out, e.g. nothing to restore.
+pub const REQUEST_FAILED: i64 = -32000;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Request {
+    pub jsonrpc: String,
+    #[serde(default)]
+    pub id: Value,
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+}
+
+impl Request {
+    pub fn new(id: u64, method: &str, params: Value) -> Self {
+        Self { jsonrpc: "2.0".to_string(), id: id.into(), method: method.to_string(), params }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Response {
+    pub jsonrpc: String,
+    pub id: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<RpcError>,
+}
+
+impl Response {
+    fn new(id: Value, outcome: Result<Value, RpcError>) -> Self {
+        let (result, error) = match outcome {
+            Ok(v) => (Some(v), None),
+            Err(e) => (None, Some(e)),
+        };
+        Self { jsonrpc: "2.0".to_string(), id, result, error }
+    }
+}
+
+fn rpc_error(code: i64, message: impl Into<String>) -> RpcError {
+    RpcError { code, message: message.into() }
+}
+
+/// A decision as reported by `explain` and `recent_events`: the audit record plus why it was
+/// made.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Decision {
+    #[serde(flatten)]
+    pub record: AuditRecord,
+    pub reason: String,
+}
+
+/// Result of `status`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Status {
+    pub version: String,
+    pub pid: u32,
+    /// Scanning resumes at this time; `None` while active.
+    pub paused_until: Option<DateTime<Utc>>,
+    /// Active bypasses (see `bypass`).
+    pub bypasses: Vec<Bypass>,
+    pub health: Heartbeat,
+    /// Telemetry events not yet delivered.
+    pub telemetry_pending: usize,
+    pub last_decision: Option<Decision>,
+}
+
+/// Human-readable explanation of a decision, from its action and rule.
+pub fn reason(action: Action, rule: Option<&str>) -> String {
+    match (action, rule) {
+        (Action::ClipboardWriteFailed, _) => "the secret should have been blocked, but the clipboard could not be overwritten",
+        (_, Some("denylist-default")) => "blocked: the active app is on the denylist, or no denylist is configured",
+        (_, Some("allowlist")) => "not blocked: the active app is on the allowlist",
+        (_, Some("context")) => "not blocked: the active app is not on the denylist, or could not be determined",
+        (_, Some("verified-only")) => "not blocked: this detector only blocks matches that pass its structural check",
+        (_, Some("dry-run")) => "not blocked: the agent runs in dry-run mode",
+        (_, Some("bypass")) => "not blocked: a user bypass was active",
+        (_, Some("user-report")) => "the user reported the blocked finding as a false positive",
+        _ => "no further detail recorded",
+    }
+    .to_string()
+}
+
+/// The last blocked clipboard contents, kept in memory only so `restore_last` can put them back.
+struct Blocked {
+    text: String,
+    detector: Detector,
+    fingerprint: String,
+}
+
+/// State shared between the control server and the main loop.
+pub struct AgentControl {
+    health: Arc<Health>,
+    telemetry: Arc<Telemetry>,
+    paused_until: Mutex<Option<DateTime<Utc>>>,
+    bypasses: Bypasses,
+    reload: AtomicBool,
+    last_blocked: Mutex<Option<Blocked>>,
+    pending_restore: Mutex<Option<String>>,
+    recent: Mutex<VecDeque<Decision>>,
+}
+
+impl AgentControl {
+    pub fn new(health: Arc<Health>, telemetry: Arc<Telemetry>) -> Self {
+        Self {
+            health,
+            telemetry,
+            paused_until: Mutex::new(None),
+            bypasses: Bypasses::default(),
+            reload: AtomicBool::new(false),
+            last_blocked: Mutex::new(None),
+            pending_restore: Mutex::new(None),
+            recent: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Whether scanning is paused. An expired pause is cleared here.
+    pub fn is_paused(&self) -> bool {
+        let mut until = self.paused_until.lock().unwrap();
+        match *until {
+            Some(t) if t > Utc::now() => true,
+            Some(_) => {
+                log::info!("Pause expired; scanning resumed");
+                *until = None;
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// Stop scanning for `minutes`. A pause is an unscoped bypass: it needs a justification,
+    /// follows the bypass policy, and is reported as `bypass_granted`.
+    pub fn pause(&self, minutes: u32, justification: &str) -> Result<DateTime<Utc>, String> {
+        let req = BypassRequest { minutes, justification: justification.to_string(), detector: None, app: None };
+        let pause = bypass::validate(req, &self.bypasses.policy())?;
+        *self.paused_until.lock().unwrap() = Some(pause.expires_at);
+        log::warn!("Scanning paused until {}: {}", pause.expires_at.to_rfc3339(), pause.justification);
+        self.report_bypass(&pause, "pause");
+        Ok(pause.expires_at)
+    }
+
+    /// Let findings within the request's scope through until it expires.
+    pub fn bypass(&self, req: BypassRequest) -> Result<Bypass, String> {
+        let bypass = self.bypasses.grant(req)?;
+        self.report_bypass(&bypass, "bypass");
+        Ok(bypass)
+    }
+
+    fn report_bypass(&self, bypass: &Bypass, rule: &str) {
+        if let Err(e) = self.telemetry.queue_event(self.telemetry.make_bypass_event(bypass, rule)) {
+            log::warn!("Failed to queue telemetry event: {}", e);
+        }
+    }
+
+    /// The active bypass covering this finding, if any.
+    pub fn bypass_covering(&self, detector: Detector, active_app: Option<&str>) -> Option<Bypass> {
+        self.bypasses.covering(detector, active_app)
+    }
+
+    /// Apply the admin limits. Disabling bypasses also ends a pause.
+    pub fn set_bypass_policy(&self, policy: BypassPolicy) {
+        if !policy.allowed() && self.paused_until.lock().unwrap().take().is_some() {
+            log::warn!("Bypass disabled by policy; scanning resumed");
+        }
+        self.bypasses.set_policy(policy);
+    }
+
+    /// End a pause and all bypasses.
+    pub fn resume(&self) {
+        let paused = self.paused_until.lock().unwrap().take().is_some();
+        let bypassed = !self.bypasses.active().is_empty();
+        self.bypasses.clear();
+        if paused || bypassed {
+            log::info!("Scanning resumed");
+        }
+    }
+
+    /// Whether a reload was requested since the last call.
+    pub fn take_reload(&self) -> bool {
+        self.reload.swap(false, Ordering::SeqCst)
+    }
+
+    /// Remember the contents the agent just replaced, for `restore_last`.
+    pub fn set_blocked(&self, text: &str, detector: Detector, fingerprint: &str) {
+        *self.last_blocked.lock().unwrap() =
+            Some(Blocked { text: text.to_string(), detector, fingerprint: fingerprint.to_string() });
+    }
+
+    /// Hand the last blocked contents to the main loop for restoring, if there are any and,
+    /// with `fingerprint`, they are still that finding's. Returns the finding's detector.
+    pub fn restore(&self, fingerprint: Option<&str>) -> Option<Detector> {
+        let mut last = self.last_blocked.lock().unwrap();
+        if fingerprint.is_some_and(|fp| last.as_ref().is_none_or(|b| b.fingerprint != fp)) {
+            return None;
+        }
+        let blocked = last.take()?;
+        log::warn!("Restoring blocked {} secret to the clipboard on request", blocked.detector);
+        *self.pending_restore.lock().unwrap() = Some(blocked.text);
+        Some(blocked.detector)
+    }
+
+    /// Contents the user asked to restore, if any.
+    pub fn take_restore(&self) -> Option<String> {
+        self.pending_restore.lock().unwrap().take()
+    }
+
+    pub fn record(&self, record: AuditRecord) {
+        let reason = reason(record.event.action, record.event.rule.as_deref());
+        let mut recent = self.recent.lock().unwrap();
+        if recent.len() == RECENT_CAPACITY {
+            recent.pop_front();
+        }
+        recent.push_back(Decision { record, reason });
+    }
+
+    pub fn status(&self) -> Status {
+        self.is_paused();
+        Status {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            pid: std::process::id(),
+            paused_until: *self.paused_until.lock().unwrap(),
+            bypasses: self.bypasses.active(),
+            health: self.health.snapshot(),
+            telemetry_pending: self.telemetry.pending(),
+            last_decision: self.recent.lock().unwrap().back().cloned(),
+        }
+    }
+
+    /// Answer one request.
+    pub fn handle(&self, req: Request) -> Response {
+        let outcome = if req.jsonrpc != "2.0" {
+            Err(rpc_error(INVALID_REQUEST, "jsonrpc must be \"2.0\""))
+        } else {
+            self.dispatch(&req.method, req.params)
+        };
+        Response::new(req.id, outcome)
+    }
+
+    fn dispatch(&self, method: &str, params: Value) -> Result<Value, RpcError> {
+        match method {
+            "status" => Ok(json!(self.status())),
+            "pause" => {
+                #[derive(Deserialize)]
+                #[serde(deny_unknown_fields)]
+                struct Params {
+                    minutes: u32,
+                    justification: String,
+                }
+                let p: Params = parse_params(params)?;
+                let until = self.pause(p.minutes, &p.justification).map_err(|e| rpc_error(REQUEST_FAILED, e))?;
+                Ok(json!({ "paused_until": until }))
+            }
+            "bypass" => {
+                let bypass = self.bypass(parse_params(params)?).map_err(|e| rpc_error(REQUEST_FAILED, e))?;
+                Ok(json!(bypass))
+            }
+            "resume" => {
+                self.resume();
+                Ok(json!({ "paused_until": null }))
+            }
+            "reload" => {
+                self.reload.store(true, Ordering::SeqCst);
+                Ok(json!({ "reload": "scheduled" }))
+            }
+            "restore_last" => {
+                let detector = self.restore(None).ok_or_else(|| rpc_error(REQUEST_FAILED, "nothing to restore"))?;
+                Ok(json!({ "restored": detector }))
+            }
+            "explain" => {
+                let last = self.recent.lock().unwrap().back().cloned();
+                last.map(|d| json!(d)).ok_or_else(|| rpc_error(REQUEST_FAILED, "no decisions yet"))
+            }
+            "recent_events" => {
+                #[derive(Deserialize)]
+                #[serde(deny_unknown_fields)]
+                struct Params {
+                    #[serde(default = "default_limit")]
+                    limit: usize,
+                }
+                fn default_limit() -> usize {
+                    20
+                }
+                let p: Params = parse_params(params)?;
+                let recent = self.recent.lock().unwrap();
+                let events: Vec<&Decision> = recent.iter().skip(recent.len().saturating_sub(p.limit)).collect();
+                Ok(json!(events))
+            }
+            _ => Err(rpc_error(METHOD_NOT_FOUND, format!("unknown method: {}", method))),
+        }
+    }
+}
+
+/// Absent params count as `{}`.
+fn parse_params<T: serde::de::DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    let params = if params.is_null() { json!({}) } else { params };
+    serde_json::from_value(params).map_err(|e| rpc_error(INVALID_PARAMS, e.to_string()))
+}
+
+/// A connected control client.
+pub trait ControlStream: Read + Write + Send {}
+
+impl<T: Read + Write + Send> ControlStream for T {}
+
+/// Accepts control clients. Implementations only return clients owned by the agent's user.
+pub trait ControlListener: Send + 'static {
+    fn accept(&self) -> std::io::Result<Box<dyn ControlStream>>;
+
+    /// Where clients connect, for logs.
+    fn address(&self) -> String;
+}
+
+/// Default socket path: `control.sock` in the runtime directory.
+pub fn default_socket_path(system: bool) -> PathBuf {
+    crate::config::runtime_dir(system).join(SOCKET_NAME)
+}
+
+#[cfg(unix)]
+pub use unix::UnixSocketListener;
+
+#[cfg(unix)]
+mod unix {
+    use super::{ControlListener, ControlStream, READ_TIMEOUT};
+    use crate::config::create_private_dir;
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+    use std::os::unix::io::AsRawFd;
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::{Path, PathBuf};
+
+    pub struct UnixSocketListener {
+        listener: UnixListener,
+        path: PathBuf,
+    }
+
+    impl UnixSocketListener {
+        /// Bind the socket (0600) in a 0700 directory. A stale socket left by a crashed agent
+        /// is replaced; one that still answers means another agent is running.
+        pub fn bind(path: &Path) -> std::io::Result<Self> {
+            if let Some(parent) = path.parent() {
+                create_private_dir(parent)?;
+            }
+            if let Ok(meta) = std::fs::symlink_metadata(path) {
+                if !meta.file_type().is_socket() {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::AlreadyExists,
+                        format!("{:?} exists and is not a socket", path),
+                    ));
+                }
+                if UnixStream::connect(path).is_ok() {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::AddrInUse,
+                        format!("another agent is listening on {:?}", path),
+                    ));
+                }
+                std::fs::remove_file(path)?;
+            }
+            let listener = UnixListener::bind(path)?;
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+            Ok(Self { listener, path: path.to_path_buf() })
+        }
+    }
+
+    impl ControlListener for UnixSocketListener {
+        fn accept(&self) -> std::io::Result<Box<dyn ControlStream>> {
+            loop {
+                let (stream, _) = self.listener.accept()?;
+                // The file mode already keeps others out on Linux; not every platform enforces
+                // it on sockets, so check the peer too
+                match peer_uid(&stream) {
+                    Some(uid) if uid != unsafe { libc::geteuid() } && uid != 0 => {
+                        log::warn!("Rejected control connection from uid {}", uid);
+                        continue;
+                    }
+                    _ => {}
+                }
+                stream.set_read_timeout(Some(READ_TIMEOUT))?;
+                return Ok(Box::new(stream));
+            }
+        }
+
+        fn address(&self) -> String {
+            self.path.display().to_string()
+        }
+    }
+
+    impl Drop for UnixSocketListener {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn peer_uid(stream: &UnixStream) -> Option<u32> {
+        let mut cred = libc::ucred { pid: 0, uid: 0, gid: 0 };
+        let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+        let rc = unsafe {
+            libc::getsockopt(
+                stream.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_PEERCRED,
+                &mut cred as *mut libc::ucred as *mut libc::c_void,
+                &mut len,
+            )
+        };
+        (rc == 0).then_some(cred.uid)
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    fn peer_uid(stream: &UnixStream) -> Option<u32> {
+        let mut uid = 0;
+        let mut gid = 0;
+        let rc = unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) };
+        (rc == 0).then_some(uid)
+    }
+}
+
+/// Bind the platform's control endpoint at `path`.
+pub fn bind(path: &Path) -> std::io::Result<Box<dyn ControlListener>> {
+    #[cfg(unix)]
+    {
+        Ok(Box::new(UnixSocketListener::bind(path)?))
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "the control socket is not available on this platform"))
+    }
+}
+
+/// Connect to the control endpoint at `path`.
+pub fn connect(path: &Path) -> std::io::Result<Box<dyn ControlStream>> {
+    #[cfg(unix)]
+    {
+        let stream = std::os::unix::net::UnixStream::connect(path)?;
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        Ok(Box::new(stream))
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "the control socket is not available on this platform"))
+    }
+}
+
+/// Spawn the control server: answer requests on `listener` until the process exits.
+pub fn serve(listener: Box<dyn ControlListener>, control: Arc<AgentControl>) -> std::io::Result<JoinHandle<()>> {
+    log::info!("Control socket listening on {}", listener.address());
+    std::thread::Builder::new().name("control".to_string()).spawn(move || {
+        loop {
+            match listener.accept() {
+                Ok(stream) => {
+                    if let Err(e) = handle_connection(stream, &control) {
+                        log::debug!("Control connection failed: {}", e);
+                    }
+                }
+                Err(e) => {
+                    log::warn!("Control socket accept failed: {}", e);
+                    std::thread::sleep(Duration::from_millis(250));
+                }
+            }
+        }
+    })
+}
+
+/// Answer each request line with one response line until the client hangs up.
+fn handle_connection(mut stream: Box<dyn ControlStream>, control: &AgentControl) -> std::io::Result<()> {
+    let mut reader = BufReader::new(&mut stream);
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        let resp = match serde_json::from_str::<Request>(&line) {
+            Ok(req) => control.handle(req),
+            Err(e) => Response::new(Value::Null, Err(rpc_error(PARSE_ERROR, e.to_string()))),
+        };
+        let mut out = serde_json::to_string(&resp)?;
+        out.push('\n');
+        reader.get_mut().write_all(out.as_bytes())?;
+    }
+}
+
+/// Call `method` on the agent listening at `path`. Fails with a clear message when no agent
+/// is running.
+pub fn call(path: &Path, method: &str, params: Value) -> anyhow::Result<Value> {
+    let mut stream = connect(path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound | std::io::ErrorKind::ConnectionRefused => {
+            anyhow::anyhow!("the sentinel_pii agent is not running (no control socket at {:?})", path)
+        }
+        _ => anyhow::anyhow!("cannot reach the sentinel_pii agent at {:?}: {}", path, e),
+    })?;
+    let mut line = serde_json::to_string(&Request::new(1, method, params))?;
+    line.push('\n');
+    stream.write_all(line.as_bytes())?;
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply).context("reading the agent's reply")?;
+    let resp: Response = serde_json::from_str(&reply).context("parsing the agent's reply")?;
+    if let Some(e) = resp.error {
+        anyhow::bail!("{} failed: {}", method, e.message);
+    }
+    Ok(resp.result.unwrap_or(Value::Null))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::TelemetryConfig;
+
+    fn control(dir: &Path) -> AgentControl {
+        let telemetry = Arc::new(Telemetry::new(TelemetryConfig {
+            queue_file: dir.join("tele_queue.jsonl"),
+            state_dir: dir.to_path_buf(),
+            ..Default::default()
+        }));
+        AgentControl::new(Arc::new(Health::new(None)), telemetry)
+    }
+
+    fn call(c: &AgentControl, method: &str, params: Value) -> Result<Value, RpcError> {
+        let resp = c.handle(Request::new(7, method, params));
+        assert_eq!(resp.id, json!(7));
+        match resp.error {
+            Some(e) => Err(e),
+            None => Ok(resp.result.unwrap()),
+        }
+    }
+
+    #[test]
+    fn pause_and_resume() {
+        let dir = tempfile::tempdir().unwrap();
+        let c = control(dir.path());
+        assert!(!c.is_paused());
+        assert!(call(&c, "pause", json!({"minutes": 5, "justification": "demo"})).unwrap()["paused_until"].is_string());
+        assert!(c.is_paused());
+        assert_eq!(call(&c, "pause", json!({"minutes": 0, "justification": "demo"})).unwrap_err().code, REQUEST_FAILED);
+        assert_eq!(call(&c, "pause", json!({"minutes": 5})).unwrap_err().code, INVALID_PARAMS);
+        call(&c, "resume", Value::Null).unwrap();
+        assert!(!c.is_paused());
+
+        *c.paused_until.lock().unwrap() = Some(Utc::now() - chrono::Duration::seconds(1));
+        assert!(!c.is_paused());
+        assert!(c.status().paused_until.is_none());
+    }
+
+    #[test]
+    fn restore_hands_the_text_to_the_main_loop_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let c = control(dir.path());
+        assert_eq!(call(&c, "restore_last", Value::Null).unwrap_err().code, REQUEST_FAILED);
+        c.set_blocked("secret text", Detector::Stripe, "fp1");
+        assert_eq!(c.restore(Some("fp0")), None);
+        assert_eq!(call(&c, "restore_last", Value::Null).unwrap(), json!({"restored": "Stripe"}));
+        assert_eq!(c.take_restore().as_deref(), Some("secret text"));
+        assert_eq!(c.take_restore(), None);
+        assert!(call(&c, "restore_last", Value::Null).is_err());
+    }
+
+    #[test]
+    fn unknown_methods_and_bad_versions_are_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let c = control(dir.path());
+        assert_eq!(call(&c, "shutdown", Value::Null).unwrap_err().code, METHOD_NOT_FOUND);
+        let mut req = Request::new(1, "status", Value::Null);
+        req.jsonrpc = "1.0".to_string();
+        assert_eq!(c.handle(req).error.unwrap().code, INVALID_REQUEST);
+        assert!(!c.take_reload());
+        call(&c, "reload", Value::Null).unwrap();
+        assert!(c.take_reload());
+        assert!(!c.take_reload());
+    }
+}
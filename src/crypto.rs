@@ -0,0 +1,335 @@
+//! Optional at-rest encryption of telemetry queue records.
+//!
+//! Each event line is sealed with ChaCha20-Poly1305 under a key derived from a random 32 byte
+//! master key. The record keeps `prev_hash` in the clear, bound as associated data, so the
+//! hash chain (see `chain`) can still be verified without the key while any edit to either
+//! the ciphertext or its chain link fails decryption.
+//!
+//! The master key comes from a `KeyStore`: a 0600 key file next to the queue, or the OS keyring
+//! (macOS Keychain via `security`, Secret Service via `secret-tool`) with the key file as
+//! fallback when no keyring is available.
+
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::fs::OpenOptions;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Where the queue master key is kept.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum KeySource {
+    /// `{queue}.key` next to the queue, 0600.
+    #[default]
+    File,
+    /// OS keyring, falling back to the key file when no keyring is available.
+    Keyring,
+}
+
+/// Storage for the 32 byte queue master key.
+pub trait KeyStore: Send + Sync {
+    /// Short name for logs, e.g. "file" or "keyring".
+    fn name(&self) -> &'static str;
+
+    /// The stored key, or `None` if none has been created yet.
+    fn load(&self) -> std::io::Result<Option<Vec<u8>>>;
+
+    fn store(&self, key: &[u8]) -> std::io::Result<()>;
+}
+
+/// Hex key file created with 0600 permissions.
+pub struct FileKeyStore {
+    path: PathBuf,
+}
+
+impl FileKeyStore {
+    pub fn new(path: &Path) -> Self {
+        Self { path: path.to_path_buf() }
+    }
+}
+
+impl KeyStore for FileKeyStore {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    fn load(&self) -> std::io::Result<Option<Vec<u8>>> {
+        let mut f = match OpenOptions::new().read(true).open(&self.path) {
+            Ok(f) => f,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let mut s = String::new();
+        f.read_to_string(&mut s)?;
+        Ok(hex::decode(s.trim()).ok().filter(|k| !k.is_empty()))
+    }
+
+    fn store(&self, key: &[u8]) -> std::io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            crate::config::create_private_dir(parent)?;
+        }
+        let mut opts = OpenOptions::new();
+        opts.create(true).write(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            opts.mode(0o600);
+        }
+        let mut f = opts.open(&self.path)?;
+        writeln!(f, "{}", hex::encode(key))?;
+        f.sync_all()
+    }
+}
+
+/// OS keyring entry, accessed through the platform's command-line tool.
+pub struct OsKeyring {
+    service: String,
+    account: String,
+}
+
+impl OsKeyring {
+    pub fn new(service: &str, account: &str) -> Self {
+        Self { service: service.to_string(), account: account.to_string() }
+    }
+
+    /// Command that stores `key`, and what to write to its stdin. The key is only ever passed on
+    /// stdin: arguments are visible to every user in the process list.
+    #[cfg(target_os = "macos")]
+    fn store_command(&self, key: &[u8]) -> (Command, String) {
+        let mut cmd = Command::new("security");
+        // A trailing `-w` without a value makes `security` prompt for the password twice
+        cmd.args(["add-generic-password", "-U", "-s", &self.service, "-a", &self.account, "-w"]);
+        let key = hex::encode(key);
+        (cmd, format!("{}\n{}\n", key, key))
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    fn store_command(&self, key: &[u8]) -> (Command, String) {
+        let mut cmd = Command::new("secret-tool");
+        cmd.args(["store", "--label", "Sentinel telemetry queue key", "service", &self.service, "account", &self.account]);
+        (cmd, hex::encode(key))
+    }
+
+    fn run(cmd: &mut Command, input: Option<&str>) -> std::io::Result<String> {
+        cmd.stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null());
+        let mut child = cmd.spawn()?;
+        if let (Some(data), Some(mut stdin)) = (input, child.stdin.take()) {
+            stdin.write_all(data.as_bytes())?;
+        }
+        let out = child.wait_with_output()?;
+        if !out.status.success() {
+            return Err(std::io::Error::other(format!("keyring command exited with {}", out.status)));
+        }
+        Ok(String::from_utf8_lossy(&out.stdout).trim().to_string())
+    }
+}
+
+impl KeyStore for OsKeyring {
+    fn name(&self) -> &'static str {
+        "keyring"
+    }
+
+    #[cfg(target_os = "macos")]
+    fn load(&self) -> std::io::Result<Option<Vec<u8>>> {
+        let mut cmd = Command::new("security");
+        cmd.args(["find-generic-password", "-s", &self.service, "-a", &self.account, "-w"]);
+        // A missing item and a missing keychain both exit non-zero; treat them as "no key"
+        Ok(Self::run(&mut cmd, None).ok().and_then(|s| hex::decode(s).ok()))
+    }
+
+    fn store(&self, key: &[u8]) -> std::io::Result<()> {
+        let (mut cmd, input) = self.store_command(key);
+        Self::run(&mut cmd, Some(&input)).map(|_| ())
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    fn load(&self) -> std::io::Result<Option<Vec<u8>>> {
+        let mut cmd = Command::new("secret-tool");
+        cmd.args(["lookup", "service", &self.service, "account", &self.account]);
+        match Self::run(&mut cmd, None) {
+            Ok(s) => Ok(hex::decode(s).ok()),
+            // secret-tool exits 1 when the item does not exist
+            Err(e) if e.kind() == std::io::ErrorKind::Other => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Try `primary` and fall back to `fallback` when it fails, e.g. no keyring daemon on a server.
+pub struct FallbackKeyStore {
+    primary: Box<dyn KeyStore>,
+    fallback: Box<dyn KeyStore>,
+}
+
+impl FallbackKeyStore {
+    pub fn new(primary: Box<dyn KeyStore>, fallback: Box<dyn KeyStore>) -> Self {
+        Self { primary, fallback }
+    }
+}
+
+impl KeyStore for FallbackKeyStore {
+    fn name(&self) -> &'static str {
+        self.primary.name()
+    }
+
+    fn load(&self) -> std::io::Result<Option<Vec<u8>>> {
+        match self.primary.load() {
+            Ok(Some(k)) => return Ok(Some(k)),
+            Ok(None) => {}
+            Err(e) => log::debug!("{} key store unavailable: {}", self.primary.name(), e),
+        }
+        self.fallback.load()
+    }
+
+    fn store(&self, key: &[u8]) -> std::io::Result<()> {
+        match self.primary.store(key) {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                log::warn!("Cannot store queue key in {}, using {} instead: {}", self.primary.name(), self.fallback.name(), e);
+                self.fallback.store(key)
+            }
+        }
+    }
+}
+
+/// The key in `store`, or a new random 32 byte key, stored there first. `purpose` is for the log.
+pub fn load_or_create_key(store: &dyn KeyStore, purpose: &str) -> std::io::Result<Vec<u8>> {
+    if let Some(k) = store.load()? {
+        return Ok(k);
+    }
+    let mut key = vec![0u8; 32];
+    getrandom::getrandom(&mut key).map_err(std::io::Error::other)?;
+    store.store(&key)?;
+    log::info!("Created {} key ({} key store)", purpose, store.name());
+    Ok(key)
+}
+
+/// An encrypted queue line. `prev_hash` stays readable for chain verification and is
+/// authenticated as associated data.
+#[derive(Serialize, Deserialize, Debug)]
+struct SealedRecord {
+    sealed: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    prev_hash: Option<String>,
+    nonce: String,
+    ct: String,
+}
+
+/// True if the queue line is an encrypted record.
+pub fn is_sealed(line: &str) -> bool {
+    line.trim_start().starts_with("{\"sealed\"")
+}
+
+/// AEAD for queue records.
+pub struct QueueCipher {
+    aead: ChaCha20Poly1305,
+}
+
+impl QueueCipher {
+    /// Derive the record key from a master key.
+    pub fn from_master_key(master: &[u8]) -> Self {
+        let mut mac = <HmacSha256 as Mac>::new_from_slice(master).expect("HMAC accepts any key length");
+        mac.update(b"sentinel-queue-record-v1");
+        let key = mac.finalize().into_bytes();
+        Self { aead: ChaCha20Poly1305::new(Key::from_slice(&key)) }
+    }
+
+    /// Load the master key from `store`, creating and storing a random one if missing.
+    pub fn load_or_create(store: &dyn KeyStore) -> std::io::Result<Self> {
+        Ok(Self::from_master_key(&load_or_create_key(store, "telemetry queue encryption")?))
+    }
+
+    /// Load the master key from `store` without creating one.
+    pub fn load(store: &dyn KeyStore) -> std::io::Result<Option<Self>> {
+        Ok(store.load()?.map(|k| Self::from_master_key(&k)))
+    }
+
+    /// Encrypt a plaintext queue line (event JSON) linked to `prev_hash`.
+    pub fn seal(&self, plaintext: &str, prev_hash: Option<&str>) -> std::io::Result<String> {
+        let mut nonce = [0u8; 12];
+        getrandom::getrandom(&mut nonce).map_err(std::io::Error::other)?;
+        let ct = self
+            .aead
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload { msg: plaintext.as_bytes(), aad: prev_hash.unwrap_or("").as_bytes() },
+            )
+            .map_err(|_| std::io::Error::other("queue record encryption failed"))?;
+        let rec = SealedRecord {
+            sealed: 1,
+            prev_hash: prev_hash.map(|s| s.to_string()),
+            nonce: hex::encode(nonce),
+            ct: hex::encode(ct),
+        };
+        Ok(serde_json::to_string(&rec)?)
+    }
+
+    /// Decrypt a sealed queue line. Returns `None` if the line is not a valid sealed record or
+    /// fails authentication.
+    pub fn open(&self, line: &str) -> Option<String> {
+        let rec: SealedRecord = serde_json::from_str(line).ok()?;
+        let nonce = hex::decode(&rec.nonce).ok().filter(|n| n.len() == 12)?;
+        let ct = hex::decode(&rec.ct).ok()?;
+        let pt = self
+            .aead
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                Payload { msg: &ct, aad: rec.prev_hash.as_deref().unwrap_or("").as_bytes() },
+            )
+            .ok()?;
+        String::from_utf8(pt).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keyring_store_passes_the_key_on_stdin() {
+        let key = [0xabu8; 32];
+        let (cmd, input) = OsKeyring::new("sentinel", "test").store_command(&key);
+        let hex = hex::encode(key);
+        assert!(input.contains(&hex));
+        assert!(cmd.get_args().all(|a| !a.to_string_lossy().contains(&hex)));
+    }
+
+    #[test]
+    fn seal_roundtrip_and_tamper_detection() {
+        let c = QueueCipher::from_master_key(&[7u8; 32]);
+        let line = c.seal(r#"{"id":"a"}"#, Some("abc")).unwrap();
+        assert!(is_sealed(&line));
+        assert!(!line.contains("\"id\""));
+        assert_eq!(c.open(&line).as_deref(), Some(r#"{"id":"a"}"#));
+
+        // Relinking the record to another chain position breaks authentication
+        assert!(c.open(&line.replace("\"abc\"", "\"abd\"")).is_none());
+        assert!(QueueCipher::from_master_key(&[8u8; 32]).open(&line).is_none());
+    }
+
+    #[test]
+    fn file_key_store_creates_private_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FileKeyStore::new(&dir.path().join("queue.key"));
+        assert!(store.load().unwrap().is_none());
+        let a = QueueCipher::load_or_create(&store).unwrap();
+        let b = QueueCipher::load(&store).unwrap().unwrap();
+        let line = a.seal("x", None).unwrap();
+        assert_eq!(b.open(&line).as_deref(), Some("x"));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(dir.path().join("queue.key")).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+}
@@ -0,0 +1,222 @@
+//! Agent enrollment: a one-time code from the management server is exchanged for a per-device
+//! credential, which then authenticates telemetry uploads instead of a shared API key.
+//!
+//! - `POST {server}/api/agents/enroll` with `{code, key_id, public_key, machine_id,
+//!   agent_version}` enrolls the device and its signing key (see `signing`).
+//! - `POST {server}/api/agents/token` with `{grant_type: "refresh_token", device_id,
+//!   refresh_token}` renews the access token.
+//!
+//! Both answer `{device_id, access_token, refresh_token?, expires_in}`. A missing
+//! `refresh_token` keeps the current one. The credential is a 0600 JSON file in the state
+//! directory and is refreshed once it is within `REFRESH_MARGIN_SECS` of expiring.
+
+use crate::signing::AgentKey;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use reqwest::blocking::Client;
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Refresh the access token when it expires within this many seconds.
+pub const REFRESH_MARGIN_SECS: i64 = 300;
+
+/// Per-device credential issued at enrollment.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Credential {
+    /// Management server base URL the credential was issued by; refreshes go there.
+    pub server: String,
+    pub device_id: String,
+    pub access_token: String,
+    pub refresh_token: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl Credential {
+    pub fn needs_refresh(&self, now: DateTime<Utc>) -> bool {
+        now + chrono::Duration::seconds(REFRESH_MARGIN_SECS) >= self.expires_at
+    }
+}
+
+#[derive(Serialize)]
+struct EnrollRequest<'a> {
+    code: &'a str,
+    key_id: String,
+    /// Ed25519 public key, hex.
+    public_key: String,
+    machine_id: Option<String>,
+    agent_version: &'static str,
+}
+
+#[derive(Serialize)]
+struct RefreshRequest<'a> {
+    grant_type: &'static str,
+    device_id: &'a str,
+    refresh_token: &'a str,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    device_id: String,
+    access_token: String,
+    #[serde(default)]
+    refresh_token: Option<String>,
+    expires_in: i64,
+}
+
+fn endpoint(server: &str, path: &str) -> String {
+    format!("{}/{}", server.trim_end_matches('/'), path)
+}
+
+fn post_token<T: Serialize>(client: &Client, url: &str, body: &T) -> anyhow::Result<TokenResponse> {
+    let resp = client.post(url).json(body).send().with_context(|| format!("contacting {}", url))?;
+    let status = resp.status();
+    if !status.is_success() {
+        let detail = resp.text().unwrap_or_default();
+        anyhow::bail!("{} returned {}: {}", url, status, detail.trim());
+    }
+    resp.json().with_context(|| format!("invalid token response from {}", url))
+}
+
+/// Exchange a one-time enrollment `code` for a credential, enrolling `key` as the device's
+/// signing key.
+pub fn enroll(
+    client: &Client,
+    server: &str,
+    code: &str,
+    key: &AgentKey,
+    machine_id: Option<String>,
+) -> anyhow::Result<Credential> {
+    let req = EnrollRequest {
+        code,
+        key_id: key.key_id(),
+        public_key: hex::encode(key.public_key()),
+        machine_id,
+        agent_version: env!("CARGO_PKG_VERSION"),
+    };
+    let resp = post_token(client, &endpoint(server, "api/agents/enroll"), &req)?;
+    let refresh_token = resp.refresh_token.context("enrollment response has no refresh_token")?;
+    Ok(Credential {
+        server: server.trim_end_matches('/').to_string(),
+        device_id: resp.device_id,
+        access_token: resp.access_token,
+        refresh_token,
+        expires_at: Utc::now() + chrono::Duration::seconds(resp.expires_in),
+    })
+}
+
+/// Renew the access token of `cred`.
+pub fn refresh(client: &Client, cred: &Credential) -> anyhow::Result<Credential> {
+    let req = RefreshRequest {
+        grant_type: "refresh_token",
+        device_id: &cred.device_id,
+        refresh_token: &cred.refresh_token,
+    };
+    let resp = post_token(client, &endpoint(&cred.server, "api/agents/token"), &req)
+        .context("refreshing the device credential failed; re-enroll with `sentinel_pii enroll` if it was revoked")?;
+    Ok(Credential {
+        server: cred.server.clone(),
+        device_id: resp.device_id,
+        access_token: resp.access_token,
+        refresh_token: resp.refresh_token.unwrap_or_else(|| cred.refresh_token.clone()),
+        expires_at: Utc::now() + chrono::Duration::seconds(resp.expires_in),
+    })
+}
+
+/// The credential file. It is read on every use, so re-enrolling takes effect without a
+/// restart.
+pub struct CredentialStore {
+    path: PathBuf,
+}
+
+impl CredentialStore {
+    pub fn new(path: &Path) -> Self {
+        Self { path: path.to_path_buf() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The stored credential, or `None` if the device is not enrolled.
+    pub fn load(&self) -> anyhow::Result<Option<Credential>> {
+        match std::fs::read_to_string(&self.path) {
+            Ok(s) => Ok(Some(serde_json::from_str(&s).with_context(|| format!("parsing credential {:?}", self.path))?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("reading credential {:?}", self.path)),
+        }
+    }
+
+    /// Atomically replace the credential file (0600).
+    pub fn save(&self, cred: &Credential) -> std::io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            crate::config::create_private_dir(parent)?;
+        }
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+        {
+            let mut opts = OpenOptions::new();
+            opts.create(true).write(true).truncate(true);
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::OpenOptionsExt;
+                opts.mode(0o600);
+            }
+            let mut f = opts.open(&tmp)?;
+            f.write_all(serde_json::to_string_pretty(cred)?.as_bytes())?;
+            f.sync_all()?;
+        }
+        std::fs::rename(&tmp, &self.path)
+    }
+
+    /// Refresh and store the credential.
+    pub fn refresh(&self, client: &Client, cred: &Credential) -> anyhow::Result<Credential> {
+        let fresh = refresh(client, cred)?;
+        self.save(&fresh)?;
+        log::info!("Refreshed device credential for {}", fresh.device_id);
+        Ok(fresh)
+    }
+
+    /// Bearer token for uploads, refreshed first if it is about to expire. `None` when the
+    /// device is not enrolled.
+    pub fn access_token(&self, client: &Client) -> anyhow::Result<Option<String>> {
+        let Some(cred) = self.load()? else { return Ok(None) };
+        if !cred.needs_refresh(Utc::now()) {
+            return Ok(Some(cred.access_token));
+        }
+        Ok(Some(self.refresh(client, &cred)?.access_token))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn credential_file_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = CredentialStore::new(&dir.path().join("state").join("credential.json"));
+        assert_eq!(store.load().unwrap(), None);
+
+        let cred = Credential {
+            server: "https://mgmt.example.com".to_string(),
+            device_id: "dev-1".to_string(),
+            access_token: "a".to_string(),
+            refresh_token: "r".to_string(),
+            expires_at: Utc::now() + chrono::Duration::seconds(REFRESH_MARGIN_SECS + 60),
+        };
+        store.save(&cred).unwrap();
+        assert_eq!(store.load().unwrap(), Some(cred.clone()));
+        assert!(!cred.needs_refresh(Utc::now()));
+        assert!(cred.needs_refresh(Utc::now() + chrono::Duration::seconds(61)));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(store.path()).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+}
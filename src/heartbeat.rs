@@ -0,0 +1,122 @@
+//! Periodic agent health reports, so the dashboard can tell a quiet machine from a dead agent.
+//!
+//! A heartbeat is an ordinary `TelemetryEvent` with action `heartbeat` and a `Heartbeat`
+//! payload. It goes through the same queue, privacy controls, and upload path as decisions.
+
+use crate::policy_sync::PolicySync;
+use crate::telemetry::Telemetry;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Whether the agent can read the clipboard.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ClipboardStatus {
+    /// The last read succeeded, or the clipboard held no text.
+    Ok,
+    /// The last read failed.
+    Failing,
+}
+
+/// Health payload of a `heartbeat` event.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+pub struct Heartbeat {
+    /// Seconds since the agent started.
+    pub uptime_secs: u64,
+    /// Version of the remote policy in force, if any.
+    pub policy_version: Option<String>,
+    /// SHA-256 (hex) of the remote policy bundle in force, if any.
+    pub policy_hash: Option<String>,
+    /// Number of secret detectors compiled into the agent.
+    pub detector_count: usize,
+    pub clipboard: ClipboardStatus,
+    /// Whether the frontmost app can be determined on this platform; without it denylist
+    /// and allowlist rules cannot match.
+    pub active_app_supported: bool,
+}
+
+/// Live health state, updated by the main loop and sampled for each heartbeat.
+pub struct Health {
+    started: Instant,
+    clipboard_ok: AtomicBool,
+    policy: Option<Arc<PolicySync>>,
+}
+
+impl Health {
+    pub fn new(policy: Option<Arc<PolicySync>>) -> Self {
+        Self { started: Instant::now(), clipboard_ok: AtomicBool::new(true), policy }
+    }
+
+    /// Record the outcome of the latest clipboard read.
+    pub fn set_clipboard_ok(&self, ok: bool) {
+        self.clipboard_ok.store(ok, Ordering::Relaxed);
+    }
+
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    pub fn snapshot(&self) -> Heartbeat {
+        let policy = self.policy.as_ref().and_then(|p| p.current().map(|b| (b.version, p.current_hash())));
+        let (policy_version, policy_hash) = policy.unwrap_or_default();
+        Heartbeat {
+            uptime_secs: self.uptime().as_secs(),
+            policy_version,
+            policy_hash,
+            detector_count: crate::scanner::detector_names().len(),
+            clipboard: if self.clipboard_ok.load(Ordering::Relaxed) { ClipboardStatus::Ok } else { ClipboardStatus::Failing },
+            active_app_supported: crate::context::active_app_supported(),
+        }
+    }
+}
+
+/// Default time between heartbeats.
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(300);
+
+/// How often the heartbeat thread re-checks the `running` flag while waiting.
+const SHUTDOWN_POLL: Duration = Duration::from_millis(250);
+
+/// Spawn the heartbeat thread: queue one heartbeat right away, then one every `interval` until
+/// `running` is cleared. The uploader sends them with the next flush.
+pub fn spawn(
+    telemetry: Arc<Telemetry>,
+    health: Arc<Health>,
+    running: Arc<AtomicBool>,
+    interval: Duration,
+) -> std::io::Result<JoinHandle<()>> {
+    std::thread::Builder::new()
+        .name("heartbeat".to_string())
+        .spawn(move || {
+            while running.load(Ordering::SeqCst) {
+                if let Err(e) = telemetry.queue_event(telemetry.make_heartbeat(health.snapshot())) {
+                    log::warn!("Failed to queue heartbeat: {}", e);
+                }
+                let deadline = Instant::now() + interval;
+                while running.load(Ordering::SeqCst) && Instant::now() < deadline {
+                    std::thread::sleep(SHUTDOWN_POLL.min(deadline.saturating_duration_since(Instant::now())));
+                }
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot_reflects_state() {
+        let health = Health::new(None);
+        let hb = health.snapshot();
+        assert_eq!(hb.clipboard, ClipboardStatus::Ok);
+        assert_eq!(hb.detector_count, crate::scanner::detector_names().len());
+        assert_eq!(hb.policy_version, None);
+        assert_eq!(hb.policy_hash, None);
+
+        health.set_clipboard_ok(false);
+        assert_eq!(health.snapshot().clipboard, ClipboardStatus::Failing);
+    }
+}
@@ -1,3 +1,24 @@
+pub mod audit;
+pub mod bypass;
+pub mod chain;
+pub mod config;
+pub mod control;
+pub mod crypto;
+pub mod enrollment;
+pub mod heartbeat;
+pub mod machine_id;
+pub mod metrics;
+pub mod notify;
 pub mod scanner;
+pub mod signing;
 pub mod context;
+pub mod policy;
+pub mod policy_sync;
+pub mod privacy;
+pub mod random;
 pub mod telemetry;
+pub mod throttle;
+pub mod transport;
+pub mod uploader;
+pub mod user_allowlist;
+pub mod verifier;
@@ -0,0 +1,205 @@
+//! Pseudonymous machine identifier attached to telemetry events.
+//!
+//! The identifier is an HMAC-SHA256, keyed with an org-provided salt, over a per-machine source
+//! value and (optionally) the current rotation period. Without the salt the value cannot be
+//! linked back to a hostname, and with a rotation period two ids from different periods cannot
+//! be linked to each other either.
+
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// What the machine identifier is derived from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum MachineIdMode {
+    /// Send no machine identifier.
+    None,
+    /// Random id generated once per install and kept in the state directory.
+    #[default]
+    InstallId,
+    /// The hostname. Requires an org salt; see `MachineIdConfig::validate`.
+    HostnameHmac,
+    /// Device id provided by MDM (`mdm_device_id`). Requires an org salt, like `HostnameHmac`.
+    Mdm,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct MachineIdConfig {
+    pub mode: MachineIdMode,
+    /// Org-wide HMAC key. Keeps ids from different orgs unlinkable and hostnames unguessable.
+    pub salt: Option<String>,
+    /// Start a new id every N days; `None` keeps the id stable.
+    pub rotation_days: Option<u32>,
+    /// Device id for `Mdm` mode, e.g. the serial number or UDID pushed in a profile.
+    pub mdm_device_id: Option<String>,
+    /// File holding the per-install id for `InstallId` mode. `Telemetry` defaults it to
+    /// `install_id` in its state directory.
+    pub install_id_file: Option<PathBuf>,
+}
+
+impl MachineIdConfig {
+    /// Reject settings that leave the id guessable: an unsalted HMAC of a hostname or an MDM
+    /// device id is just a public hash of it.
+    pub fn validate(&self) -> Result<(), String> {
+        let mode = match self.mode {
+            MachineIdMode::HostnameHmac => "hostname-hmac",
+            MachineIdMode::Mdm => "mdm",
+            MachineIdMode::None | MachineIdMode::InstallId => return Ok(()),
+        };
+        if !self.has_salt() {
+            return Err(format!("machine id mode {} requires a salt (--machine-id-salt or telemetry.machine_id.salt)", mode));
+        }
+        Ok(())
+    }
+
+    /// Whether a non-empty org salt is configured.
+    pub fn has_salt(&self) -> bool {
+        self.salt.as_deref().is_some_and(|s| !s.is_empty())
+    }
+
+    /// The identifier for the current rotation period, or `None` if disabled or unavailable.
+    pub fn machine_id(&self) -> Option<String> {
+        self.source().map(|source| self.derive(&source, Utc::now().timestamp()))
+    }
+
+    /// The per-machine value the identifier is derived from, or `None` if disabled or
+    /// unavailable. Reads (or creates) the install id file, so callers keep the result.
+    pub fn source(&self) -> Option<String> {
+        Some(match self.mode {
+            MachineIdMode::None => return None,
+            MachineIdMode::InstallId => {
+                let path = self.install_id_file.as_deref()?;
+                match load_or_create_install_id(path) {
+                    Ok(id) => id,
+                    Err(e) => {
+                        log::warn!("Cannot read or create install id {:?}: {}", path, e);
+                        return None;
+                    }
+                }
+            }
+            MachineIdMode::HostnameHmac if !self.has_salt() => {
+                log::warn!("Machine id mode is hostname-hmac but no salt is configured; sending no machine id");
+                return None;
+            }
+            MachineIdMode::HostnameHmac => hostname::get().ok()?.to_str()?.to_string(),
+            MachineIdMode::Mdm if !self.has_salt() => {
+                log::warn!("Machine id mode is mdm but no salt is configured; sending no machine id");
+                return None;
+            }
+            MachineIdMode::Mdm => match self.mdm_device_id.as_deref().map(str::trim) {
+                Some(id) if !id.is_empty() => id.to_string(),
+                _ => {
+                    log::warn!("Machine id mode is mdm but no MDM device id is configured");
+                    return None;
+                }
+            },
+        })
+    }
+
+    /// HMAC of `source` for the rotation period containing `now` (unix seconds).
+    pub fn derive(&self, source: &str, now: i64) -> String {
+        let period = match self.rotation_days {
+            Some(days) if days > 0 => now.div_euclid(i64::from(days) * 86_400),
+            _ => 0,
+        };
+        let salt = self.salt.as_deref().unwrap_or("");
+        let mut mac = HmacSha256::new_from_slice(salt.as_bytes()).expect("HMAC accepts any key length");
+        mac.update(format!("sentinel-machine-id:v1:{}:{}", period, source).as_bytes());
+        hex::encode(mac.finalize().into_bytes())
+    }
+}
+
+/// Read the per-install id, creating a random one (0600) on first use.
+fn load_or_create_install_id(path: &Path) -> std::io::Result<String> {
+    if let Ok(s) = std::fs::read_to_string(path)
+        && !s.trim().is_empty()
+    {
+        return Ok(s.trim().to_string());
+    }
+    let mut raw = [0u8; 16];
+    getrandom::getrandom(&mut raw).map_err(std::io::Error::other)?;
+    let id = hex::encode(raw);
+    if let Some(parent) = path.parent() {
+        crate::config::create_private_dir(parent)?;
+    }
+    let mut opts = OpenOptions::new();
+    opts.create(true).write(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        opts.mode(0o600);
+    }
+    let mut f = opts.open(path)?;
+    writeln!(f, "{}", id)?;
+    Ok(id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cfg(mode: MachineIdMode, dir: &Path) -> MachineIdConfig {
+        MachineIdConfig {
+            mode,
+            salt: Some("org-salt".to_string()),
+            install_id_file: Some(dir.join("install_id")),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn salt_and_rotation_change_the_id() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut c = cfg(MachineIdMode::HostnameHmac, dir.path());
+        let a = c.derive("LAPTOP-JSMITH", 0);
+        assert_eq!(a.len(), 64);
+        assert_eq!(a, c.derive("LAPTOP-JSMITH", 1_000_000_000));
+
+        c.salt = Some("other-org".to_string());
+        assert_ne!(a, c.derive("LAPTOP-JSMITH", 0));
+
+        c.rotation_days = Some(7);
+        let week = 7 * 86_400;
+        assert_eq!(c.derive("h", week), c.derive("h", 2 * week - 1));
+        assert_ne!(c.derive("h", week), c.derive("h", 2 * week));
+    }
+
+    #[test]
+    fn hostname_hmac_and_mdm_require_a_salt() {
+        let dir = tempfile::tempdir().unwrap();
+        for mode in [MachineIdMode::HostnameHmac, MachineIdMode::Mdm] {
+            let mut c = cfg(mode, dir.path());
+            c.mdm_device_id = Some("C02XK0AAJGH5".to_string());
+            assert!(c.validate().is_ok());
+            c.salt = Some(String::new());
+            assert!(c.validate().is_err());
+            c.salt = None;
+            assert!(c.validate().is_err());
+            assert!(c.machine_id().is_none());
+        }
+        assert!(cfg(MachineIdMode::InstallId, dir.path()).validate().is_ok());
+    }
+
+    #[test]
+    fn modes() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(cfg(MachineIdMode::None, dir.path()).machine_id().is_none());
+
+        let install = cfg(MachineIdMode::InstallId, dir.path());
+        let id = install.machine_id().unwrap();
+        assert_eq!(install.machine_id().unwrap(), id);
+        assert!(dir.path().join("install_id").exists());
+
+        let mut mdm = cfg(MachineIdMode::Mdm, dir.path());
+        assert!(mdm.machine_id().is_none());
+        mdm.mdm_device_id = Some("C02XK0AAJGH5".to_string());
+        assert_eq!(mdm.machine_id().unwrap(), mdm.derive("C02XK0AAJGH5", 0));
+    }
+}
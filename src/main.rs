@@ -1,20 +1,33 @@
 use anyhow::Result;
 use arboard::Clipboard;
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
 use std::sync::Arc;
 use std::thread::sleep;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-mod scanner;
-mod context;
-mod telemetry;
-
-const REDACT_TEXT: &str = "[[ SENTINEL BLOCKED: Secret Detected ]]";
+use sentinel_pii::telemetry::Action;
+use sentinel_pii::{audit, bypass, config, context, control, crypto, heartbeat, metrics, notify, policy, policy_sync, scanner, telemetry, throttle, transport, uploader, user_allowlist, verifier};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about = "Sentinel PII - Phase 2: Context-aware Clip-Clear", long_about = None)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Config file (JSON). Default: `$XDG_CONFIG_HOME/sentinel/config.json`, or `/etc/sentinel/config.json` with `--system`. Command-line flags override it.
+    #[arg(long, global = true)]
+    config: Option<std::path::PathBuf>,
+
+    /// System mode: read `/etc/sentinel/config.json` and keep state in `/var/lib/sentinel` instead of per-user directories
+    #[arg(long, global = true, default_value_t = false)]
+    system: bool,
+
+    /// Local audit log path (default: audit.jsonl in the state directory). The audit log is kept regardless of telemetry settings.
+    #[arg(long, global = true)]
+    audit_log: Option<std::path::PathBuf>,
+
     /// Poll interval in milliseconds
     #[arg(long, default_value_t = 200)]
     interval: u64,
@@ -27,10 +40,22 @@ struct Args {
     #[arg(long, default_value_t = true)]
     notify: bool,
 
+    /// Seconds after a block notification during which further blocks of the same detector or in the same app are coalesced into one summary (default: 60)
+    #[arg(long)]
+    notify_cooldown: Option<u64>,
+
+    /// No notifications in this local-time range, e.g. `22:00-07:00`. Blocking is not affected.
+    #[arg(long)]
+    quiet_hours: Option<throttle::QuietHours>,
+
     /// Enable telemetry (opt-in). When enabled, `--telemetry-url` must be provided.
-    #[arg(long, default_value_t = false)]
+    #[arg(long, default_value_t = false, overrides_with = "no_telemetry")]
     telemetry: bool,
 
+    /// Disable telemetry, even if the config file enables it. The last of `--telemetry` and `--no-telemetry` wins.
+    #[arg(long, default_value_t = false, overrides_with = "telemetry")]
+    no_telemetry: bool,
+
     /// Telemetry ingest URL (HTTP/HTTPS)
     #[arg(long)]
     telemetry_url: Option<String>,
@@ -39,6 +64,138 @@ struct Args {
     #[arg(long)]
     telemetry_api_key: Option<String>,
 
+    /// Seconds between background telemetry uploads
+    #[arg(long, default_value_t = 60)]
+    telemetry_flush_interval: u64,
+
+    /// Upload early once this many telemetry events are queued
+    #[arg(long, default_value_t = 20)]
+    telemetry_flush_batch: usize,
+
+    /// Telemetry queue file (default: `telemetry_queue.jsonl` in the state directory)
+    #[arg(long, global = true)]
+    telemetry_queue_file: Option<std::path::PathBuf>,
+
+    /// Rotate the telemetry queue file once it exceeds this many bytes (default: 1000000)
+    #[arg(long)]
+    telemetry_max_queue_bytes: Option<u64>,
+
+    /// Drop queued telemetry events older than this many days (default: 30)
+    #[arg(long)]
+    telemetry_max_age_days: Option<u32>,
+
+    /// Cap on the total size of the telemetry queue across all segments (default: 10000000)
+    #[arg(long)]
+    telemetry_max_total_bytes: Option<u64>,
+
+    /// Encrypt queued telemetry events at rest (ChaCha20-Poly1305)
+    #[arg(long, default_value_t = false)]
+    telemetry_encrypt: bool,
+
+    /// Where to keep the queue encryption key (default: file, `{queue}.key` with 0600 permissions)
+    #[arg(long, value_enum)]
+    telemetry_key_source: Option<sentinel_pii::crypto::KeySource>,
+
+    /// How the machine identifier sent with telemetry is derived (default: install-id)
+    #[arg(long, value_enum)]
+    machine_id_mode: Option<sentinel_pii::machine_id::MachineIdMode>,
+
+    /// Org-provided salt (HMAC key) for the machine identifier
+    #[arg(long)]
+    machine_id_salt: Option<String>,
+
+    /// Derive a new machine identifier every N days to limit long-term linkability
+    #[arg(long)]
+    machine_id_rotation_days: Option<u32>,
+
+    /// Device id pushed by MDM, used with `--machine-id-mode mdm`
+    #[arg(long)]
+    mdm_device_id: Option<String>,
+
+    /// How much of the app name is sent with telemetry (default: raw)
+    #[arg(long, value_enum)]
+    telemetry_app_name: Option<sentinel_pii::privacy::AppNameLevel>,
+
+    /// Comma-separated per-action sampling rates, e.g. `detected_but_skipped=0.1`. Unlisted actions are always sent.
+    #[arg(long, value_delimiter = ',', value_parser = sentinel_pii::privacy::parse_sample_rate)]
+    telemetry_sample: Vec<(Action, f64)>,
+
+    /// Send a raw or hashed app name only after this many events carried it; rarer names are sent as their category (default: 0, off)
+    #[arg(long)]
+    telemetry_min_app_count: Option<u32>,
+
+    /// Comma-separated actions to send, e.g. `blocked,clipboard_write_failed` (default: all: blocked, allowed, detected_but_skipped, clipboard_write_failed)
+    #[arg(long, value_enum, value_delimiter = ',')]
+    telemetry_actions: Vec<Action>,
+
+    /// Round telemetry timestamps down to a multiple of this many seconds (default: 0, exact)
+    #[arg(long)]
+    telemetry_timestamp_granularity: Option<u64>,
+
+    /// PEM bundle of CAs trusted for the telemetry endpoint (replaces the built-in roots)
+    #[arg(long)]
+    telemetry_ca_bundle: Option<std::path::PathBuf>,
+
+    /// PEM client certificate for mutual TLS with the telemetry endpoint
+    #[arg(long)]
+    telemetry_client_cert: Option<std::path::PathBuf>,
+
+    /// PEM private key for `--telemetry-client-cert`
+    #[arg(long)]
+    telemetry_client_key: Option<std::path::PathBuf>,
+
+    /// PKCS#12 bundle with the client certificate and key, instead of the PEM files
+    #[arg(long)]
+    telemetry_client_pkcs12: Option<std::path::PathBuf>,
+
+    /// Password for `--telemetry-client-pkcs12`. Prefer the config file, which is not visible in the process list.
+    #[arg(long)]
+    telemetry_client_pkcs12_password: Option<String>,
+
+    /// Comma-separated SPKI SHA-256 pins (`sha256/<base64>` or hex); a certificate the server presents must match
+    #[arg(long, value_delimiter = ',')]
+    telemetry_pin_spki: Vec<String>,
+
+    /// Proxy URL for telemetry uploads (default: `HTTPS_PROXY`/`HTTP_PROXY` from the environment)
+    #[arg(long)]
+    telemetry_proxy: Option<String>,
+
+    /// Comma-separated hosts that bypass `--telemetry-proxy` (default: `NO_PROXY`)
+    #[arg(long)]
+    telemetry_no_proxy: Option<String>,
+
+    /// Seconds between heartbeat events (default: 300; 0 disables heartbeats)
+    #[arg(long)]
+    telemetry_heartbeat_interval: Option<u64>,
+
+    /// Telemetry events let through per action, detector, and app in each throttle window (default: 20; 0 disables throttling)
+    #[arg(long)]
+    telemetry_throttle_max_events: Option<u32>,
+
+    /// Telemetry throttle window in seconds (default: 60)
+    #[arg(long)]
+    telemetry_throttle_window: Option<u64>,
+
+    /// Remote policy endpoint (default: `/api/agents/policy` on the telemetry server)
+    #[arg(long)]
+    policy_url: Option<String>,
+
+    /// Management server's Ed25519 public key (hex) that signs policy bundles; enables remote policy
+    #[arg(long)]
+    policy_public_key: Option<String>,
+
+    /// Seconds between remote policy fetches (default: 300)
+    #[arg(long)]
+    policy_interval: Option<u64>,
+
+    /// Control socket path (default: `control.sock` in `$XDG_RUNTIME_DIR/sentinel`, or `/run/sentinel` in system mode)
+    #[arg(long, global = true)]
+    control_socket: Option<std::path::PathBuf>,
+
+    /// Serve Prometheus metrics on this loopback address, e.g. 127.0.0.1:9464 (default: off)
+    #[arg(long)]
+    metrics_addr: Option<std::net::SocketAddr>,
+
     /// Comma-separated denylist of app names (case-insensitive substring match). If empty, old behavior (always redact) applies.
     #[arg(long, value_delimiter = ',')]
     denylist: Vec<String>,
@@ -46,12 +203,231 @@ struct Args {
     /// Comma-separated allowlist of app names (case-insensitive substring match). If provided, matches allowlist and skips redaction.
     #[arg(long, value_delimiter = ',')]
     allowlist: Vec<String>,
+
+    /// Comma-separated detectors (e.g. `Slack,GitHub`) that only block when the match passes offline structural verification (checksum or token layout).
+    #[arg(long, value_delimiter = ',')]
+    verified_only: Vec<String>,
+
+    /// Opt-in live check of detected keys against provider APIs (AWS STS, Stripe) to flag active credentials. Rate-limited.
+    #[arg(long, default_value_t = false)]
+    live_verify: bool,
+
+    /// Minimum seconds between two live verification calls
+    #[arg(long, default_value_t = 10)]
+    live_verify_interval: u64,
+
+    /// Override the AWS STS base URL used for live verification
+    #[arg(long)]
+    aws_sts_url: Option<String>,
+
+    /// Override the Stripe API base URL used for live verification
+    #[arg(long)]
+    stripe_api_url: Option<String>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// List and filter the local audit log of detections and decisions
+    History {
+        /// Only show entries from the last N hours
+        #[arg(long)]
+        since_hours: Option<i64>,
+
+        /// Only show this secret type (e.g. AWS, Stripe)
+        #[arg(long = "type")]
+        secret_type: Option<String>,
+
+        /// Only show this action (e.g. blocked, detected_but_skipped)
+        #[arg(long)]
+        action: Option<String>,
+
+        /// Only show entries whose app name contains this substring
+        #[arg(long)]
+        app: Option<String>,
+
+        /// Show at most this many of the most recent entries
+        #[arg(long, default_value_t = 50)]
+        limit: usize,
+
+        /// Print raw JSON lines instead of a table
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
+
+    /// Print the JSON Schema of the telemetry upload envelope and events
+    Schema,
+
+    /// Enroll this device with the management server, exchanging a one-time code for a device credential
+    Enroll {
+        /// Management server base URL, e.g. https://mgmt.example.com
+        #[arg(long)]
+        server: String,
+
+        /// One-time enrollment code issued by the server
+        #[arg(long)]
+        code: String,
+    },
+
+    /// Print the agent's signing key id and Ed25519 public key (hex) for enrollment, creating the key if needed
+    PublicKey,
+
+    /// Show the running agent's counters and latencies, read from its metrics endpoint
+    Stats {
+        /// Metrics endpoint address (default: from `--metrics-addr`, the config file, or 127.0.0.1:9464)
+        #[arg(long)]
+        addr: Option<std::net::SocketAddr>,
+
+        /// Print the raw Prometheus text instead of a table
+        #[arg(long, default_value_t = false)]
+        raw: bool,
+    },
+
+    /// Talk to the running agent over its control socket
+    Ctl {
+        #[command(subcommand)]
+        command: CtlCommand,
+
+        /// Print the agent's raw JSON reply
+        #[arg(long, global = true, default_value_t = false)]
+        json: bool,
+    },
+
+    /// Verify the tamper-evident hash chain of the telemetry queue; exits non-zero on gaps or edits
+    VerifyLog {
+        /// Telemetry queue file (default: from `--telemetry-queue-file`, the config file, or the state directory)
+        #[arg(long)]
+        queue_file: Option<std::path::PathBuf>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum CtlCommand {
+    /// Show whether the agent is scanning, its health, and the last decision
+    Status,
+
+    /// Stop scanning for a while
+    Pause {
+        /// Minutes until scanning resumes by itself
+        #[arg(long, default_value_t = 15)]
+        minutes: u32,
+
+        /// Why the agent is paused; reported to admins
+        #[arg(long)]
+        reason: String,
+    },
+
+    /// Let secrets through for a while instead of blocking them, optionally only for one detector or app
+    Bypass {
+        /// Minutes until the bypass expires
+        #[arg(long, default_value_t = 15)]
+        minutes: u32,
+
+        /// Why the bypass is needed; reported to admins
+        #[arg(long)]
+        reason: String,
+
+        /// Only let this detector's findings through (e.g. AWS, Stripe)
+        #[arg(long)]
+        detector: Option<String>,
+
+        /// Only while an app matching this (case-insensitive substring) is frontmost
+        #[arg(long)]
+        app: Option<String>,
+    },
+
+    /// End a pause and all bypasses early
+    Resume,
+
+    /// Make the agent re-read its config file
+    Reload,
+
+    /// List the agent's latest decisions and why they were made
+    History {
+        /// Show at most this many decisions
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+
+    /// Put the last blocked clipboard contents back
+    Restore,
 }
 
 fn main() -> Result<()> {
     env_logger::init();
 
     let args = Args::parse();
+
+    let audit_cfg = audit::AuditConfig {
+        path: args.audit_log.clone().unwrap_or_else(|| config::state_dir(args.system).join("audit.jsonl")),
+        ..Default::default()
+    };
+    let audit_log = audit::AuditLog::new(audit_cfg);
+
+    if let Some(Command::History { since_hours, secret_type, action, app, limit, json }) = &args.command {
+        let filter = audit::AuditFilter {
+            since: since_hours.map(|h| chrono::Utc::now() - chrono::Duration::hours(h)),
+            secret_type: secret_type.clone(),
+            action: action.clone(),
+            app: app.clone(),
+            limit: Some(*limit),
+        };
+        return print_history(&audit_log, &filter, *json);
+    }
+
+    if let Some(Command::Schema) = &args.command {
+        println!("{}", serde_json::to_string_pretty(&telemetry::json_schema())?);
+        return Ok(());
+    }
+
+    let mut file_cfg = load_file_config(&args)?;
+
+    if let Some(Command::Enroll { server, code }) = &args.command {
+        let cfg = telemetry_config(&args, &file_cfg);
+        cfg.validate().map_err(anyhow::Error::msg)?;
+        let tele = telemetry::Telemetry::new(cfg);
+        let cred = tele.enroll(server, code)?;
+        println!("Enrolled as device {}; credential stored in {:?}", cred.device_id, tele.credentials().path());
+        return Ok(());
+    }
+
+    if let Some(Command::PublicKey) = &args.command {
+        let tele = telemetry::Telemetry::new(telemetry_config(&args, &file_cfg));
+        let key = tele.signing_key()?;
+        println!("key_id: {}", key.key_id());
+        println!("public_key: {}", hex::encode(key.public_key()));
+        return Ok(());
+    }
+
+    let metrics_addr = args.metrics_addr.or(file_cfg.metrics.addr);
+
+    if let Some(Command::Ctl { command, json }) = &args.command {
+        return run_ctl(&control_socket_path(&args, &file_cfg), command, *json);
+    }
+
+    if let Some(Command::Stats { addr, raw }) = &args.command {
+        let addr = match addr.or(metrics_addr) {
+            Some(a) => a,
+            None => metrics::DEFAULT_ADDR.parse()?,
+        };
+        return print_stats(addr, *raw);
+    }
+
+    if let Some(Command::VerifyLog { queue_file }) = &args.command {
+        let mut cfg = telemetry_config(&args, &file_cfg);
+        if let Some(q) = queue_file {
+            cfg.queue_file = q.clone();
+        }
+        let report = telemetry::Telemetry::new(cfg).verify_chain()?;
+        for issue in &report.issues {
+            println!("{}", issue);
+        }
+        if !report.is_ok() {
+            anyhow::bail!("telemetry log verification failed: {} issue(s)", report.issues.len());
+        }
+        println!("OK: {} lines ({} checkpoints) verified", report.lines, report.checkpoints);
+        return Ok(());
+    }
+
     log::info!("Starting sentinel_pii (interval={}ms, dry_run={})", args.interval, args.dry_run);
 
     let running = Arc::new(AtomicBool::new(true));
@@ -62,87 +438,279 @@ fn main() -> Result<()> {
 
     let mut clipboard = Clipboard::new()?;
 
+    // Remote policy: the cached bundle applies from the start, fetched updates while running
+    let policy_sync = match policy_sync_config(&args, &file_cfg) {
+        Some(c) => Some(Arc::new(policy_sync::PolicySync::new(c)?)),
+        None => None,
+    };
+    let mut policy_generation = policy_sync.as_ref().map_or(0, |s| s.generation());
+    let effective_cfg = with_policy(&file_cfg, policy_sync.as_deref());
+
     // Setup telemetry client
-    let tele_cfg = {
-        let mut c = crate::telemetry::TelemetryConfig::default();
-        c.enabled = args.telemetry;
-        c.url = args.telemetry_url.clone();
-        c.api_key = args.telemetry_api_key.clone();
-        c
+    let tele_cfg = telemetry_config(&args, &effective_cfg);
+    tele_cfg.validate().map_err(anyhow::Error::msg)?;
+    let user_allowlist = user_allowlist::UserAllowlist::new(config::state_dir(args.system).join("user_allowlist.json"));
+    let upload = tele_cfg.enabled && tele_cfg.url.is_some();
+    let heartbeat_interval = (tele_cfg.enabled && tele_cfg.heartbeat_interval_secs > 0)
+        .then(|| Duration::from_secs(tele_cfg.heartbeat_interval_secs));
+    let telemetry = Arc::new(telemetry::Telemetry::new(tele_cfg));
+    let health = Arc::new(heartbeat::Health::new(policy_sync.clone()));
+
+    // Background uploader: flushes on an interval or after N events, final flush on shutdown
+    let uploader_handle = if upload {
+        let cfg = uploader::UploaderConfig {
+            interval: Duration::from_secs(args.telemetry_flush_interval),
+            flush_after_events: args.telemetry_flush_batch,
+            ..Default::default()
+        };
+        Some(uploader::spawn(telemetry.clone(), running.clone(), cfg)?)
+    } else {
+        None
+    };
+
+    if let Some(sync) = &policy_sync {
+        policy_sync::spawn(sync.clone(), telemetry.clone(), running.clone())?;
+    }
+
+    if let Some(interval) = heartbeat_interval {
+        heartbeat::spawn(telemetry.clone(), health.clone(), running.clone(), interval)?;
+    }
+
+    if let Some(addr) = metrics_addr {
+        metrics::serve(addr, telemetry.clone())?;
+    }
+
+    // Control socket; the agent keeps protecting the clipboard if it cannot be bound
+    let control = Arc::new(control::AgentControl::new(health.clone(), telemetry.clone()));
+    control.set_bypass_policy(effective_cfg.bypass.clone());
+    let control_path = if file_cfg.control.enabled.unwrap_or(true) {
+        let path = control_socket_path(&args, &file_cfg);
+        match control::bind(&path) {
+            Ok(listener) => {
+                control::serve(listener, control.clone())?;
+                Some(path)
+            }
+            Err(e) => {
+                log::warn!("Control socket {:?} unavailable: {}", path, e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    let mut detection = detection_settings(&args, &effective_cfg, &user_allowlist);
+    let mut notify_throttle = throttle::NotifyThrottle::new(notify_config(&args, &file_cfg));
+
+    let live_verifier = {
+        let mut c = verifier::VerifierConfig {
+            enabled: args.live_verify,
+            min_interval: Duration::from_secs(args.live_verify_interval),
+            ..Default::default()
+        };
+        if let Some(u) = &args.aws_sts_url {
+            c.aws_sts_url = u.clone();
+        }
+        if let Some(u) = &args.stripe_api_url {
+            c.stripe_api_url = u.clone();
+        }
+        // Provider APIs are public hosts: the ingest CA, pins, and client certificate do not apply
+        let client = if args.live_verify {
+            transport::build_public_client(telemetry.transport())?
+        } else {
+            reqwest::blocking::Client::new()
+        };
+        verifier::LiveVerifier::new(&c, client)
     };
-    let telemetry = crate::telemetry::Telemetry::new(tele_cfg);
+    // Checks run on a worker so provider round trips never hold up the clipboard loop
+    let live_checks = if args.live_verify { Some(verifier::spawn::<PendingReport>(live_verifier)?) } else { None };
 
+    // Per-install key for finding fingerprints
+    let fingerprint_store = crypto::FileKeyStore::new(&config::state_dir(args.system).join("fingerprint.key"));
+    let fingerprint_key = crypto::load_or_create_key(&fingerprint_store, "fingerprint")?;
+    let recorder = Recorder { telemetry: &telemetry, audit_log: &audit_log, control: &control, fingerprint_key: &fingerprint_key };
     let mut last_clipboard: Option<String> = None;
+    // Answers from notification buttons
+    let (feedback_tx, feedback_rx) = mpsc::channel::<notify::Feedback>();
 
     while running.load(Ordering::SeqCst) {
+        if let Some(sync) = &policy_sync
+            && sync.generation() != policy_generation
+        {
+            policy_generation = sync.generation();
+            let cfg = with_policy(&file_cfg, Some(sync));
+            detection = detection_settings(&args, &cfg, &user_allowlist);
+            telemetry.set_actions(telemetry_config(&args, &cfg).actions);
+            control.set_bypass_policy(cfg.bypass.clone());
+        }
+
+        if control.take_reload() {
+            match load_file_config(&args) {
+                Ok(cfg) => {
+                    file_cfg = cfg;
+                    let cfg = with_policy(&file_cfg, policy_sync.as_deref());
+                    detection = detection_settings(&args, &cfg, &user_allowlist);
+                    telemetry.set_actions(telemetry_config(&args, &cfg).actions);
+                    control.set_bypass_policy(cfg.bypass.clone());
+                    notify_throttle = throttle::NotifyThrottle::new(notify_config(&args, &file_cfg));
+                    log::info!("Config reloaded");
+                }
+                Err(e) => log::error!("Reload failed; keeping the current config: {:#}", e),
+            }
+        }
+
+        while let Ok(feedback) = feedback_rx.try_recv() {
+            let notice = &feedback.notice;
+            match feedback.action {
+                notify::NotificationAction::RestoreOnce => {}
+                notify::NotificationAction::AlwaysAllow => {
+                    let cfg = with_policy(&file_cfg, policy_sync.as_deref());
+                    if !cfg.bypass.allowed() {
+                        log::warn!("Bypass disabled by policy; not adding to the user allowlist");
+                    } else if let Some(app) = &notice.app {
+                        match user_allowlist.add(app) {
+                            Ok(_) => {
+                                log::warn!("Added '{}' to the user allowlist {:?}", app, user_allowlist.path());
+                                detection = detection_settings(&args, &cfg, &user_allowlist);
+                            }
+                            Err(e) => log::error!("Failed to update the user allowlist {:?}: {:#}", user_allowlist.path(), e),
+                        }
+                    }
+                }
+                notify::NotificationAction::ReportFalsePositive => {
+                    log::info!("{} finding {} reported as a false positive", notice.detector, notice.fingerprint);
+                    let ev = telemetry.make_false_positive(notice.detector, notice.app.clone(), &notice.fingerprint);
+                    let rec = audit::AuditRecord { event: ev.clone(), fingerprint: notice.fingerprint.clone(), live: None };
+                    if let Err(e) = audit_log.append(&rec) {
+                        log::warn!("Failed to write audit log {:?}: {}", audit_log.path(), e);
+                    }
+                    control.record(rec);
+                    if let Err(e) = telemetry.queue_event(ev) {
+                        log::warn!("Failed to queue telemetry event: {}", e);
+                    }
+                }
+            }
+            // Every button also puts the contents back, if they are still the ones blocked
+            if control.restore(Some(&notice.fingerprint)).is_none() {
+                log::info!("Blocked contents no longer held; nothing to restore");
+            }
+        }
+
+        if args.notify {
+            for summary in notify_throttle.due(Instant::now(), chrono::Local::now().time()) {
+                notify::show_summary(&summary);
+            }
+        }
+
+        if let Some((_, results)) = &live_checks {
+            while let Ok(c) = results.try_recv() {
+                report(&recorder, c.tag.action, c.tag.rule, c.tag.active_app, &c.text, &c.finding, c.result);
+            }
+        }
+
+        if let Some(text) = control.take_restore() {
+            match clipboard.set_text(text.clone()) {
+                // Remembered as seen so the restored secret is not blocked again
+                Ok(()) => last_clipboard = Some(text),
+                Err(e) => log::error!("Failed to restore clipboard: {}", e),
+            }
+        }
+
+        if control.is_paused() {
+            sleep(Duration::from_millis(args.interval));
+            continue;
+        }
+
+        metrics::global().clipboard_polls.inc();
         match clipboard.get_text() {
             Ok(text) => {
+                health.set_clipboard_ok(true);
                 if last_clipboard.as_deref() != Some(&text) {
                     log::debug!("Clipboard changed: len={}", text.len());
                     last_clipboard = Some(text.clone());
 
-                    if let Some(secret_kind) = scanner::detect_secret_type(&text) {
-                        log::warn!("Secret detected in clipboard (type={})", secret_kind);
-
-                        // Build which denylist to use: if the user provided neither list, use a sensible default denylist
-                        let effective_denylist: Vec<String> = if args.denylist.is_empty() && args.allowlist.is_empty() {
-                            // Default unsafe targets: browsers and chat apps
-                            vec![
-                                "chrome".to_string(),
-                                "safari".to_string(),
-                                "firefox".to_string(),
-                                "slack".to_string(),
-                                "discord".to_string(),
-                                "chatgpt".to_string(),
-                                "teams".to_string(),
-                            ]
-                        } else {
-                            args.denylist.clone()
-                        };
+                    let started = Instant::now();
+                    let findings = scanner::scan(&text);
+                    metrics::global().scans.inc();
+                    metrics::global().scan_duration.observe(started.elapsed());
+                    for f in &findings {
+                        metrics::global().findings.inc(f.detector.as_str());
+                    }
+                    if let Some(finding) = detection.policy.first_blocking(&findings) {
+                        let secret_kind = finding.detector;
+                        log::warn!("Secret detected in clipboard (type={}, verified={})", secret_kind, finding.verified);
 
                         let active_app = context::get_active_app();
-                        let should_redact = context::should_redact(active_app.as_deref(), &effective_denylist, &args.allowlist);
+                        let should_redact = context::should_redact(active_app.as_deref(), &detection.effective_denylist, &detection.allowlist);
 
-                        if args.dry_run {
+                        let (action, rule) = if args.dry_run {
                             log::info!("dry-run: not overwriting clipboard (should_redact={})", should_redact);
+                            (Action::DetectedButSkipped, Some("dry-run"))
+                        } else if should_redact && control.bypass_covering(secret_kind, active_app.as_deref()).is_some() {
+                            log::info!("Bypass active; not blocking {} secret", secret_kind);
+                            (Action::Allowed, Some("bypass"))
                         } else if should_redact {
                             // Customize message to include secret type
                             let msg = format!("[[ SENTINEL BLOCKED: {} Secret Detected ]]", secret_kind);
+                            let fingerprint = scanner::fingerprint(&fingerprint_key, &text, finding);
+                            control.set_blocked(&text, secret_kind, &fingerprint);
 
                             if let Err(e) = clipboard.set_text(msg.clone()) {
                                 log::error!("Failed to overwrite clipboard: {}", e);
+                                (Action::ClipboardWriteFailed, Some("denylist-default"))
                             } else {
                                 log::info!("Clipboard overwritten with redaction: {}", secret_kind);
 
-                                if args.notify {
+                                if args.notify
+                                    && notify_throttle.on_block(secret_kind, active_app.as_deref(), Instant::now(), chrono::Local::now().time())
+                                {
                                     // Send a native notification to inform the user
-                                    let body = format!("A {} secret was detected in the clipboard and was blocked.", secret_kind);
-                                    if let Err(e) = notify_rust::Notification::new()
-                                        .summary("Sentinel: Paste Blocked")
-                                        .body(&body)
-                                        .show() {
-                                        log::error!("Failed to send notification: {}", e);
-                                    }
+                                    let notice = notify::BlockedNotice { detector: secret_kind, app: active_app.clone(), fingerprint };
+                                    notify::show_blocked(notice, feedback_tx.clone());
+                                } else if args.notify {
+                                    log::debug!("Notification for {} secret held back by cooldown or quiet hours", secret_kind);
+                                    metrics::global().notifications_suppressed.inc();
                                 }
 
-                                // Telemetry: record the block event (best-effort)
-                                let ev = telemetry.make_event(secret_kind, "blocked", active_app.clone(), Some("denylist-default".to_string()));
-                                if let Err(e) = telemetry.queue_event(ev) {
-                                    log::warn!("Failed to queue telemetry event: {}", e);
-                                }
+                                (Action::Blocked, Some("denylist-default"))
                             }
                         } else {
                             // Not redacting due to context
-                            if let Some(app) = active_app {
+                            if let Some(app) = &active_app {
                                 log::info!("Detected {} secret, but skipping redaction for active app '{}'", secret_kind, app);
                             } else {
                                 log::info!("Detected {} secret, but skipping redaction (active app unknown)", secret_kind);
                             }
+                            if context::is_allowlisted(active_app.as_deref(), &detection.allowlist) {
+                                (Action::Allowed, Some("allowlist"))
+                            } else {
+                                (Action::DetectedButSkipped, Some("context"))
+                            }
+                        };
+                        if let Some((checks, _)) = &live_checks {
+                            let check = verifier::LiveCheck {
+                                tag: PendingReport { action, rule, active_app },
+                                finding: finding.clone(),
+                                text: text.clone(),
+                                result: None,
+                            };
+                            if checks.send(check).is_err() {
+                                log::error!("Live verification thread is gone; not reporting {} decision", secret_kind);
+                            }
+                        } else {
+                            report(&recorder, action, rule, active_app, &text, finding, None);
                         }
+                    } else if let Some(finding) = detection.policy.first_skipped(&findings) {
+                        // No finding blocks, so every one of them was skipped; report the first
+                        log::info!("Ignoring {} unverified finding(s) per verified-only policy", findings.len());
+                        let active_app = context::get_active_app();
+                        report(&recorder, Action::DetectedButSkipped, Some("verified-only"), active_app, &text, finding, None);
                     }
                 }
             }
             Err(e) => {
+                // An empty or non-text clipboard is not a failure
+                health.set_clipboard_ok(matches!(e, arboard::Error::ContentNotAvailable));
                 log::debug!("Failed to read clipboard: {}", e);
             }
         }
@@ -151,5 +719,437 @@ fn main() -> Result<()> {
     }
 
     log::info!("Shutting down");
+    if let Some((checks, results)) = live_checks {
+        // Report the decisions still waiting for their live check
+        drop(checks);
+        for c in results {
+            report(&recorder, c.tag.action, c.tag.rule, c.tag.active_app, &c.text, &c.finding, c.result);
+        }
+    }
+    if let Some(p) = &control_path {
+        let _ = std::fs::remove_file(p);
+    }
+    if let Some(h) = uploader_handle {
+        telemetry.wake();
+        if h.join().is_err() {
+            log::error!("Telemetry uploader thread panicked");
+        }
+    }
     Ok(())
 }
+
+/// Notification cooldown and quiet hours from flags, else the config file.
+fn notify_config(args: &Args, file_cfg: &config::FileConfig) -> throttle::NotifyThrottleConfig {
+    let mut cfg = throttle::NotifyThrottleConfig::default();
+    file_cfg.apply_notifications(&mut cfg);
+    if let Some(v) = args.notify_cooldown {
+        cfg.cooldown = Duration::from_secs(v);
+    }
+    if args.quiet_hours.is_some() {
+        cfg.quiet_hours = args.quiet_hours;
+    }
+    cfg
+}
+
+/// The config file: an explicit `--config` must exist; the default location is optional.
+fn load_file_config(args: &Args) -> Result<config::FileConfig> {
+    match &args.config {
+        Some(p) => config::FileConfig::load(p),
+        None => config::FileConfig::load_optional(&config::default_config_path(args.system)),
+    }
+}
+
+/// Control socket path from the flag, else the config file, else the runtime directory.
+fn control_socket_path(args: &Args, file_cfg: &config::FileConfig) -> std::path::PathBuf {
+    args.control_socket
+        .clone()
+        .or_else(|| file_cfg.control.socket.clone())
+        .unwrap_or_else(|| control::default_socket_path(args.system))
+}
+
+/// App lists and detection policy in force.
+struct Detection {
+    allowlist: Vec<String>,
+    effective_denylist: Vec<String>,
+    policy: policy::Policy,
+}
+
+/// Detection settings from flags, else the (policy-overlaid) config file. The user allowlist
+/// is added on top unless the bypass policy is off; it does not change which denylist is used.
+fn detection_settings(args: &Args, file_cfg: &config::FileConfig, user_allowlist: &user_allowlist::UserAllowlist) -> Detection {
+    // Lists given on the command line replace the config file's
+    let pick = |cli: &Vec<String>, file: &Option<Vec<String>>| {
+        if cli.is_empty() { file.clone().unwrap_or_default() } else { cli.clone() }
+    };
+    let denylist = pick(&args.denylist, &file_cfg.denylist);
+    let mut allowlist = pick(&args.allowlist, &file_cfg.allowlist);
+    let effective_denylist = policy::effective_denylist(&denylist, &allowlist);
+    if file_cfg.bypass.allowed() {
+        match user_allowlist.load() {
+            Ok(user) => allowlist.extend(user),
+            Err(e) => log::warn!("Ignoring unreadable user allowlist {:?}: {:#}", user_allowlist.path(), e),
+        }
+    }
+    Detection {
+        effective_denylist,
+        allowlist,
+        policy: policy::Policy {
+            verified_only: policy::effective_verified_only(&args.verified_only, file_cfg.verified_only.as_deref()),
+        },
+    }
+}
+
+/// The config file with the current remote policy, if any, applied on top.
+fn with_policy(file_cfg: &config::FileConfig, sync: Option<&policy_sync::PolicySync>) -> config::FileConfig {
+    let mut cfg = file_cfg.clone();
+    if let Some(bundle) = sync.and_then(|s| s.current()) {
+        cfg.apply_policy(&bundle);
+    }
+    cfg
+}
+
+/// Remote policy settings from the config file and flags. `None` (remote policy off) without a
+/// server public key.
+fn policy_sync_config(args: &Args, file_cfg: &config::FileConfig) -> Option<policy_sync::PolicySyncConfig> {
+    let rp = &file_cfg.remote_policy;
+    let public_key = args.policy_public_key.clone().or_else(|| rp.public_key.clone())?;
+    let tele_cfg = telemetry_config(args, file_cfg);
+    let url = args
+        .policy_url
+        .clone()
+        .or_else(|| rp.url.clone())
+        .or_else(|| tele_cfg.url.as_deref().and_then(policy_sync::default_policy_url));
+    if url.is_none() {
+        log::warn!("No remote policy URL; using the cached policy only");
+    }
+    let interval = args.policy_interval.or(rp.interval_secs).map_or(policy_sync::DEFAULT_INTERVAL, Duration::from_secs);
+    Some(policy_sync::PolicySyncConfig {
+        url,
+        public_key,
+        interval,
+        cache_file: config::state_dir(args.system).join("policy_cache.json"),
+    })
+}
+
+/// Telemetry settings: defaults (queue in the state directory), then the config file, then flags.
+fn telemetry_config(args: &Args, file_cfg: &config::FileConfig) -> telemetry::TelemetryConfig {
+    let mut cfg = telemetry::TelemetryConfig {
+        queue_file: config::state_dir(args.system).join("telemetry_queue.jsonl"),
+        state_dir: config::state_dir(args.system),
+        ..Default::default()
+    };
+    file_cfg.apply_telemetry(&mut cfg);
+    if args.telemetry {
+        cfg.enabled = true;
+    } else if args.no_telemetry {
+        cfg.enabled = false;
+    }
+    if args.telemetry_url.is_some() {
+        cfg.url = args.telemetry_url.clone();
+    }
+    if args.telemetry_api_key.is_some() {
+        cfg.api_key = args.telemetry_api_key.clone();
+    }
+    if let Some(q) = &args.telemetry_queue_file {
+        cfg.queue_file = q.clone();
+    }
+    if let Some(v) = args.telemetry_max_queue_bytes {
+        cfg.max_queue_bytes = v;
+    }
+    if let Some(v) = args.telemetry_max_age_days {
+        cfg.max_age_days = v;
+    }
+    if let Some(v) = args.telemetry_max_total_bytes {
+        cfg.max_total_bytes = v;
+    }
+    if args.telemetry_encrypt {
+        cfg.encrypt = true;
+    }
+    if let Some(v) = args.telemetry_key_source {
+        cfg.key_source = v;
+    }
+    if let Some(v) = args.machine_id_mode {
+        cfg.machine_id.mode = v;
+    }
+    if args.machine_id_salt.is_some() {
+        cfg.machine_id.salt = args.machine_id_salt.clone();
+    }
+    if args.machine_id_rotation_days.is_some() {
+        cfg.machine_id.rotation_days = args.machine_id_rotation_days;
+    }
+    if args.mdm_device_id.is_some() {
+        cfg.machine_id.mdm_device_id = args.mdm_device_id.clone();
+    }
+    if let Some(v) = args.telemetry_app_name {
+        cfg.privacy.app_name = v;
+    }
+    if let Some(v) = args.telemetry_min_app_count {
+        cfg.privacy.min_app_count = v;
+    }
+    if !args.telemetry_sample.is_empty() {
+        cfg.privacy.sample_rates = args.telemetry_sample.iter().cloned().collect();
+    }
+    if let Some(v) = args.telemetry_throttle_max_events {
+        cfg.throttle.max_events = v;
+    }
+    if let Some(v) = args.telemetry_throttle_window {
+        cfg.throttle.window = Duration::from_secs(v);
+    }
+    if let Some(v) = args.telemetry_timestamp_granularity {
+        cfg.privacy.timestamp_granularity_secs = v;
+    }
+    if !args.telemetry_actions.is_empty() {
+        cfg.actions = args.telemetry_actions.clone();
+    }
+    if args.telemetry_ca_bundle.is_some() {
+        cfg.transport.ca_bundle = args.telemetry_ca_bundle.clone();
+    }
+    if args.telemetry_client_cert.is_some() {
+        cfg.transport.client_cert = args.telemetry_client_cert.clone();
+    }
+    if args.telemetry_client_key.is_some() {
+        cfg.transport.client_key = args.telemetry_client_key.clone();
+    }
+    if args.telemetry_client_pkcs12.is_some() {
+        cfg.transport.client_pkcs12 = args.telemetry_client_pkcs12.clone();
+    }
+    if args.telemetry_client_pkcs12_password.is_some() {
+        cfg.transport.client_pkcs12_password = args.telemetry_client_pkcs12_password.clone();
+    }
+    if args.telemetry_proxy.is_some() {
+        cfg.transport.proxy = args.telemetry_proxy.clone();
+    }
+    if args.telemetry_no_proxy.is_some() {
+        cfg.transport.no_proxy = args.telemetry_no_proxy.clone();
+    }
+    if !args.telemetry_pin_spki.is_empty() {
+        cfg.transport.pinned_spki = args.telemetry_pin_spki.clone();
+    }
+    if let Some(v) = args.telemetry_heartbeat_interval {
+        cfg.heartbeat_interval_secs = v;
+    }
+    cfg
+}
+
+/// Where decisions are recorded.
+struct Recorder<'a> {
+    telemetry: &'a telemetry::Telemetry,
+    audit_log: &'a audit::AuditLog,
+    control: &'a control::AgentControl,
+    fingerprint_key: &'a [u8],
+}
+
+/// A decision waiting for its live check before it is reported.
+struct PendingReport {
+    action: Action,
+    rule: Option<&'static str>,
+    active_app: Option<String>,
+}
+
+/// Record a decision in the audit log and queue it for telemetry (best-effort). Which actions
+/// are actually sent is up to `TelemetryConfig::actions`.
+fn report(
+    recorder: &Recorder,
+    action: Action,
+    rule: Option<&str>,
+    active_app: Option<String>,
+    text: &str,
+    finding: &scanner::Finding,
+    live: Option<verifier::Verification>,
+) {
+    metrics::global().decisions.inc(action.as_str());
+    let ev = recorder.telemetry.make_event(finding.detector, action, active_app, rule.map(str::to_string));
+    recorder.control.record(record_audit(recorder, &ev, text, finding, live));
+    if let Err(e) = recorder.telemetry.queue_event(ev) {
+        log::warn!("Failed to queue telemetry event: {}", e);
+    }
+}
+
+/// Append a decision to the local audit log (best-effort) and return the record. Only the
+/// finding fingerprint is recorded, never the secret.
+fn record_audit(
+    recorder: &Recorder,
+    ev: &telemetry::TelemetryEvent,
+    text: &str,
+    finding: &scanner::Finding,
+    live: Option<verifier::Verification>,
+) -> audit::AuditRecord {
+    let rec = audit::AuditRecord {
+        event: ev.clone(),
+        fingerprint: scanner::fingerprint(recorder.fingerprint_key, text, finding),
+        live,
+    };
+    if let Err(e) = recorder.audit_log.append(&rec) {
+        log::warn!("Failed to write audit log {:?}: {}", recorder.audit_log.path(), e);
+    }
+    rec
+}
+
+fn print_history(audit_log: &audit::AuditLog, filter: &audit::AuditFilter, json: bool) -> Result<()> {
+    let records = audit_log.query(filter)?;
+    if json {
+        for r in &records {
+            println!("{}", serde_json::to_string(r)?);
+        }
+        return Ok(());
+    }
+    if records.is_empty() {
+        println!("No audit entries in {:?}", audit_log.path());
+        return Ok(());
+    }
+    println!("{:<25}  {:<8}  {:<22}  {:<20}  {:<16}", "TIMESTAMP", "TYPE", "ACTION", "APP", "FINGERPRINT");
+    for r in &records {
+        println!(
+            "{:<25}  {:<8}  {:<22}  {:<20}  {:<16}",
+            r.event.timestamp.to_rfc3339(),
+            r.event.secret_type.map_or("-", |d| d.as_str()),
+            r.event.action,
+            r.event.app_name.as_deref().unwrap_or("-"),
+            r.fingerprint
+        );
+    }
+    Ok(())
+}
+
+fn print_stats(addr: std::net::SocketAddr, raw: bool) -> Result<()> {
+    let text = metrics::scrape(addr)?;
+    if raw {
+        print!("{}", text);
+        return Ok(());
+    }
+    println!("{:<64}  {:>12}", "METRIC", "VALUE");
+    // Buckets are only useful to Prometheus; sums and counts give the mean
+    for (name, value) in metrics::parse_samples(&text) {
+        if !name.contains("_bucket{") {
+            println!("{:<64}  {:>12}", name, value);
+        }
+    }
+    Ok(())
+}
+
+fn run_ctl(socket: &std::path::Path, command: &CtlCommand, json: bool) -> Result<()> {
+    let (method, params) = match command {
+        CtlCommand::Status => ("status", serde_json::Value::Null),
+        CtlCommand::Pause { minutes, reason } => ("pause", serde_json::json!({ "minutes": minutes, "justification": reason })),
+        CtlCommand::Bypass { minutes, reason, detector, app } => (
+            "bypass",
+            serde_json::json!({ "minutes": minutes, "justification": reason, "detector": detector, "app": app }),
+        ),
+        CtlCommand::Resume => ("resume", serde_json::Value::Null),
+        CtlCommand::Reload => ("reload", serde_json::Value::Null),
+        CtlCommand::History { limit } => ("recent_events", serde_json::json!({ "limit": limit })),
+        CtlCommand::Restore => ("restore_last", serde_json::Value::Null),
+    };
+    let result = control::call(socket, method, params)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&result)?);
+        return Ok(());
+    }
+    match command {
+        CtlCommand::Status => print_status(&serde_json::from_value(result)?),
+        CtlCommand::Pause { .. } => println!("Scanning paused until {}", result["paused_until"].as_str().unwrap_or("?")),
+        CtlCommand::Bypass { .. } => {
+            let b: bypass::Bypass = serde_json::from_value(result)?;
+            println!(
+                "Bypass active until {} (detector: {}, app: {})",
+                b.expires_at.to_rfc3339(),
+                b.detector.map_or("any", |d| d.as_str()),
+                b.app.as_deref().unwrap_or("any")
+            );
+        }
+        CtlCommand::Resume => println!("Scanning resumed"),
+        CtlCommand::Reload => println!("Reload scheduled; the agent applies it on its next poll"),
+        CtlCommand::History { .. } => print_decisions(&serde_json::from_value::<Vec<control::Decision>>(result)?),
+        CtlCommand::Restore => {
+            println!("Restored the blocked {} secret to the clipboard", result["restored"].as_str().unwrap_or("?"))
+        }
+    }
+    Ok(())
+}
+
+fn print_status(status: &control::Status) {
+    let uptime = status.health.uptime_secs;
+    println!(
+        "Agent:      running (pid {}, version {}, up {}h{:02}m)",
+        status.pid,
+        status.version,
+        uptime / 3600,
+        uptime / 60 % 60
+    );
+    match status.paused_until {
+        Some(t) => println!("Scanning:   paused until {}", t.to_rfc3339()),
+        None => println!("Scanning:   active"),
+    }
+    for b in &status.bypasses {
+        println!(
+            "Bypass:     until {} (detector: {}, app: {}): {}",
+            b.expires_at.to_rfc3339(),
+            b.detector.map_or("any", |d| d.as_str()),
+            b.app.as_deref().unwrap_or("any"),
+            b.justification
+        );
+    }
+    let clipboard = match status.health.clipboard {
+        heartbeat::ClipboardStatus::Ok => "ok",
+        heartbeat::ClipboardStatus::Failing => "failing",
+    };
+    println!("Clipboard:  {}", clipboard);
+    match (&status.health.policy_version, &status.health.policy_hash) {
+        (None, None) => println!("Policy:     none"),
+        (v, h) => println!(
+            "Policy:     {} ({})",
+            v.as_deref().unwrap_or("unversioned"),
+            h.as_deref().map_or("-", |h| &h[..h.len().min(12)])
+        ),
+    }
+    println!("Telemetry:  {} event(s) pending", status.telemetry_pending);
+    match &status.last_decision {
+        Some(d) => println!(
+            "Last:       {} {} {} in {}: {}",
+            d.record.event.timestamp.to_rfc3339(),
+            d.record.event.secret_type.map_or("-", |t| t.as_str()),
+            d.record.event.action,
+            d.record.event.app_name.as_deref().unwrap_or("unknown app"),
+            d.reason
+        ),
+        None => println!("Last:       no decisions yet"),
+    }
+}
+
+fn print_decisions(decisions: &[control::Decision]) {
+    if decisions.is_empty() {
+        println!("No decisions since the agent started");
+        return;
+    }
+    println!("{:<25}  {:<8}  {:<22}  {:<20}  REASON", "TIMESTAMP", "TYPE", "ACTION", "APP");
+    for d in decisions {
+        println!(
+            "{:<25}  {:<8}  {:<22}  {:<20}  {}",
+            d.record.event.timestamp.to_rfc3339(),
+            d.record.event.secret_type.map_or("-", |t| t.as_str()),
+            d.record.event.action,
+            d.record.event.app_name.as_deref().unwrap_or("-"),
+            d.reason
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn telemetry_flags_override_the_config_file() {
+        let enabled: config::FileConfig = serde_json::from_str(r#"{"telemetry": {"enabled": true}}"#).unwrap();
+        let disabled = config::FileConfig::default();
+        let cfg = |argv: &[&str], file: &config::FileConfig| telemetry_config(&Args::parse_from(argv), file).enabled;
+
+        assert!(cfg(&["sentinel_pii"], &enabled));
+        assert!(!cfg(&["sentinel_pii"], &disabled));
+        assert!(!cfg(&["sentinel_pii", "--no-telemetry"], &enabled));
+        assert!(cfg(&["sentinel_pii", "--telemetry"], &disabled));
+        // The last flag wins
+        assert!(!cfg(&["sentinel_pii", "--telemetry", "--no-telemetry"], &enabled));
+        assert!(cfg(&["sentinel_pii", "--no-telemetry", "--telemetry"], &disabled));
+    }
+}
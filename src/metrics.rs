@@ -0,0 +1,305 @@
+//! Process-wide counters and histograms, exposed in the Prometheus text format on an opt-in
+//! localhost endpoint (`--metrics-addr`) and read back by `sentinel_pii stats`.
+//!
+//! Metrics live in one static registry (`global`), so the scanner loop, the telemetry queue,
+//! and the uploader can record without passing a handle around.
+
+use crate::telemetry::Telemetry;
+use once_cell::sync::Lazy;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Default address of the metrics endpoint.
+pub const DEFAULT_ADDR: &str = "127.0.0.1:9464";
+
+#[derive(Default, Debug)]
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    pub fn inc(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Default, Debug)]
+pub struct Gauge(AtomicU64);
+
+impl Gauge {
+    pub fn set(&self, v: u64) {
+        self.0.store(v, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Counter with one label, e.g. `detector`.
+#[derive(Default, Debug)]
+pub struct LabeledCounter(Mutex<BTreeMap<&'static str, u64>>);
+
+impl LabeledCounter {
+    pub fn inc(&self, label: &'static str) {
+        *self.0.lock().unwrap().entry(label).or_default() += 1;
+    }
+
+    pub fn get(&self, label: &str) -> u64 {
+        self.0.lock().unwrap().get(label).copied().unwrap_or(0)
+    }
+}
+
+/// Histogram of durations with fixed bucket bounds in seconds.
+#[derive(Debug)]
+pub struct Histogram {
+    bounds: &'static [f64],
+    /// Non-cumulative count per bucket; the last slot is `+Inf`.
+    buckets: Vec<AtomicU64>,
+    sum_nanos: AtomicU64,
+    count: AtomicU64,
+}
+
+impl Histogram {
+    pub fn new(bounds: &'static [f64]) -> Self {
+        Self {
+            bounds,
+            buckets: (0..=bounds.len()).map(|_| AtomicU64::new(0)).collect(),
+            sum_nanos: AtomicU64::new(0),
+            count: AtomicU64::new(0),
+        }
+    }
+
+    pub fn observe(&self, d: Duration) {
+        let secs = d.as_secs_f64();
+        let i = self.bounds.iter().position(|b| secs <= *b).unwrap_or(self.bounds.len());
+        self.buckets[i].fetch_add(1, Ordering::Relaxed);
+        self.sum_nanos.fetch_add(d.as_nanos().min(u64::MAX as u128) as u64, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+}
+
+/// Scan latency buckets: 10µs to 100ms.
+const SCAN_BUCKETS: &[f64] = &[0.00001, 0.00005, 0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1];
+/// Upload latency buckets: 10ms to 30s (the request timeout).
+const FLUSH_BUCKETS: &[f64] = &[0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+#[derive(Debug)]
+pub struct Metrics {
+    pub clipboard_polls: Counter,
+    pub scans: Counter,
+    pub scan_duration: Histogram,
+    /// Findings by detector.
+    pub findings: LabeledCounter,
+    /// Decisions by action.
+    pub decisions: LabeledCounter,
+    /// Events queued since the last successful flush, sampled when metrics are rendered.
+    pub queue_depth: Gauge,
+    pub flushes: Counter,
+    pub flush_failures: Counter,
+    pub flush_duration: Histogram,
+    pub rotations: Counter,
+    /// Telemetry events dropped by the throttle.
+    pub telemetry_throttled: Counter,
+    /// Telemetry events the server rejected, moved to the dead-letter file.
+    pub telemetry_rejected: Counter,
+    /// Block notifications held back by cooldowns or quiet hours.
+    pub notifications_suppressed: Counter,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            clipboard_polls: Counter::default(),
+            scans: Counter::default(),
+            scan_duration: Histogram::new(SCAN_BUCKETS),
+            findings: LabeledCounter::default(),
+            decisions: LabeledCounter::default(),
+            queue_depth: Gauge::default(),
+            flushes: Counter::default(),
+            flush_failures: Counter::default(),
+            flush_duration: Histogram::new(FLUSH_BUCKETS),
+            rotations: Counter::default(),
+            telemetry_throttled: Counter::default(),
+            telemetry_rejected: Counter::default(),
+            notifications_suppressed: Counter::default(),
+        }
+    }
+}
+
+static METRICS: Lazy<Metrics> = Lazy::new(Metrics::default);
+
+/// The process-wide registry.
+pub fn global() -> &'static Metrics {
+    &METRICS
+}
+
+impl Metrics {
+    /// Prometheus text exposition (format 0.0.4).
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        counter(&mut out, "sentinel_clipboard_polls_total", "Clipboard reads.", self.clipboard_polls.get());
+        counter(&mut out, "sentinel_scans_total", "Clipboard contents scanned for secrets.", self.scans.get());
+        histogram(&mut out, "sentinel_scan_duration_seconds", "Time to scan clipboard contents.", &self.scan_duration);
+        labeled(&mut out, "sentinel_findings_total", "Secrets found, by detector.", "detector", &self.findings);
+        labeled(&mut out, "sentinel_decisions_total", "Decisions on detected secrets, by action.", "action", &self.decisions);
+        header(&mut out, "sentinel_telemetry_queue_depth", "Telemetry events waiting for upload.", "gauge");
+        let _ = writeln!(out, "sentinel_telemetry_queue_depth {}", self.queue_depth.get());
+        counter(&mut out, "sentinel_telemetry_flushes_total", "Telemetry upload attempts.", self.flushes.get());
+        counter(&mut out, "sentinel_telemetry_flush_failures_total", "Failed telemetry uploads.", self.flush_failures.get());
+        histogram(&mut out, "sentinel_telemetry_flush_duration_seconds", "Time to upload the telemetry queue.", &self.flush_duration);
+        counter(&mut out, "sentinel_telemetry_rotations_total", "Telemetry queue file rotations.", self.rotations.get());
+        counter(&mut out, "sentinel_telemetry_throttled_total", "Telemetry events dropped by the throttle.", self.telemetry_throttled.get());
+        counter(&mut out, "sentinel_telemetry_rejected_total", "Telemetry events rejected by the server and dead-lettered.", self.telemetry_rejected.get());
+        counter(&mut out, "sentinel_notifications_suppressed_total", "Block notifications held back by cooldowns or quiet hours.", self.notifications_suppressed.get());
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn counter(out: &mut String, name: &str, help: &str, value: u64) {
+    header(out, name, help, "counter");
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+fn labeled(out: &mut String, name: &str, help: &str, label: &str, c: &LabeledCounter) {
+    header(out, name, help, "counter");
+    for (value, n) in c.0.lock().unwrap().iter() {
+        let _ = writeln!(out, "{}{{{}=\"{}\"}} {}", name, label, value, n);
+    }
+}
+
+fn histogram(out: &mut String, name: &str, help: &str, h: &Histogram) {
+    header(out, name, help, "histogram");
+    let mut cumulative = 0;
+    for (i, bucket) in h.buckets.iter().enumerate() {
+        cumulative += bucket.load(Ordering::Relaxed);
+        let le = h.bounds.get(i).map_or("+Inf".to_string(), |b| b.to_string());
+        let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, le, cumulative);
+    }
+    let _ = writeln!(out, "{}_sum {}", name, h.sum_nanos.load(Ordering::Relaxed) as f64 / 1e9);
+    let _ = writeln!(out, "{}_count {}", name, h.count());
+}
+
+/// Samples of a text exposition as `(name{labels}, value)`, in order. Comments and malformed
+/// lines are skipped.
+pub fn parse_samples(text: &str) -> Vec<(String, f64)> {
+    text.lines()
+        .filter(|l| !l.starts_with('#'))
+        .filter_map(|l| {
+            let (name, value) = l.rsplit_once(' ')?;
+            Some((name.to_string(), value.parse().ok()?))
+        })
+        .collect()
+}
+
+/// Serve `GET /metrics` on `addr`, which must be a loopback address. The queue depth is
+/// sampled from `telemetry` on every scrape. Returns the bound address (useful with port 0).
+pub fn serve(addr: SocketAddr, telemetry: Arc<Telemetry>) -> std::io::Result<SocketAddr> {
+    if !addr.ip().is_loopback() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("metrics endpoint must listen on a loopback address, not {}", addr),
+        ));
+    }
+    let listener = TcpListener::bind(addr)?;
+    let local = listener.local_addr()?;
+    std::thread::Builder::new().name("metrics".to_string()).spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else { continue };
+            global().queue_depth.set(telemetry.pending() as u64);
+            if let Err(e) = respond(stream) {
+                log::debug!("Metrics request failed: {}", e);
+            }
+        }
+    })?;
+    log::info!("Serving metrics on http://{}/metrics", local);
+    Ok(local)
+}
+
+/// Fetch the metrics of a running agent from its endpoint at `addr`.
+pub fn scrape(addr: SocketAddr) -> anyhow::Result<String> {
+    let client = reqwest::blocking::Client::builder().timeout(Duration::from_secs(5)).build()?;
+    let resp = client.get(format!("http://{}/metrics", addr)).send().map_err(|e| {
+        anyhow::anyhow!("no metrics endpoint at {} ({}); is the agent running with --metrics-addr?", addr, e)
+    })?;
+    if !resp.status().is_success() {
+        anyhow::bail!("metrics endpoint at {} returned non-success status: {}", addr, resp.status());
+    }
+    Ok(resp.text()?)
+}
+
+fn respond(mut stream: TcpStream) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Drain the headers
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line == "\r\n" || line == "\n" {
+            break;
+        }
+    }
+    let mut parts = request_line.split_whitespace();
+    let (status, content_type, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", "text/plain; version=0.0.4", global().render()),
+        (Some("GET"), _) => ("404 Not Found", "text/plain", "not found\n".to_string()),
+        _ => ("405 Method Not Allowed", "text/plain", "method not allowed\n".to_string()),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_prometheus_text() {
+        let m = Metrics::default();
+        m.scans.inc();
+        m.scan_duration.observe(Duration::from_micros(30));
+        m.scan_duration.observe(Duration::from_secs(1));
+        m.findings.inc("AWS");
+        m.findings.inc("AWS");
+        m.decisions.inc("blocked");
+        m.queue_depth.set(4);
+        let text = m.render();
+
+        assert!(text.contains("# TYPE sentinel_scan_duration_seconds histogram\n"));
+        assert!(text.contains("sentinel_scan_duration_seconds_bucket{le=\"0.00005\"} 1\n"));
+        assert!(text.contains("sentinel_scan_duration_seconds_bucket{le=\"0.1\"} 1\n"));
+        assert!(text.contains("sentinel_scan_duration_seconds_bucket{le=\"+Inf\"} 2\n"));
+        assert!(text.contains("sentinel_scan_duration_seconds_count 2\n"));
+
+        let samples: BTreeMap<String, f64> = parse_samples(&text).into_iter().collect();
+        assert_eq!(samples["sentinel_scans_total"], 1.0);
+        assert_eq!(samples["sentinel_findings_total{detector=\"AWS\"}"], 2.0);
+        assert_eq!(samples["sentinel_decisions_total{action=\"blocked\"}"], 1.0);
+        assert_eq!(samples["sentinel_telemetry_queue_depth"], 4.0);
+        assert!((samples["sentinel_scan_duration_seconds_sum"] - 1.00003).abs() < 1e-9);
+    }
+}
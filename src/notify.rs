@@ -0,0 +1,141 @@
+//! Desktop notifications for blocked secrets. Where the notification server supports actions
+//! (freedesktop notifications over D-Bus), they carry buttons whose answers are sent back to
+//! the main loop as `Feedback`. Elsewhere they stay plain text.
+
+use crate::scanner::Detector;
+use crate::throttle::Summary;
+use std::sync::mpsc::Sender;
+
+/// Buttons on a "Paste Blocked" notification.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NotificationAction {
+    /// Put the blocked contents back once.
+    RestoreOnce,
+    /// Add the app to the user's allowlist (see `user_allowlist`) and restore.
+    AlwaysAllow,
+    /// Report the finding as not a secret and restore.
+    ReportFalsePositive,
+}
+
+impl NotificationAction {
+    pub const ALL: [NotificationAction; 3] =
+        [NotificationAction::RestoreOnce, NotificationAction::AlwaysAllow, NotificationAction::ReportFalsePositive];
+
+    /// Action key sent over D-Bus.
+    pub fn id(&self) -> &'static str {
+        match self {
+            NotificationAction::RestoreOnce => "restore_once",
+            NotificationAction::AlwaysAllow => "always_allow",
+            NotificationAction::ReportFalsePositive => "report_false_positive",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            NotificationAction::RestoreOnce => "Restore & allow once",
+            NotificationAction::AlwaysAllow => "Always allow in this app",
+            NotificationAction::ReportFalsePositive => "Report false positive",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|a| a.id() == id)
+    }
+}
+
+/// The blocked finding a notification is about.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockedNotice {
+    pub detector: Detector,
+    pub app: Option<String>,
+    /// See `scanner::fingerprint`.
+    pub fingerprint: String,
+}
+
+impl BlockedNotice {
+    /// Buttons offered: "Always allow" needs a known app.
+    pub fn actions(&self) -> Vec<NotificationAction> {
+        NotificationAction::ALL
+            .into_iter()
+            .filter(|a| *a != NotificationAction::AlwaysAllow || self.app.is_some())
+            .collect()
+    }
+}
+
+/// A button the user clicked.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Feedback {
+    pub action: NotificationAction,
+    pub notice: BlockedNotice,
+}
+
+/// Whether notifications on this platform can carry buttons.
+pub fn actions_supported() -> bool {
+    cfg!(all(unix, not(target_os = "macos")))
+}
+
+/// Show the "Paste Blocked" notification. With action support, a thread waits for the user's
+/// choice and sends it to `feedback`; closing the notification sends nothing.
+pub fn show_blocked(notice: BlockedNotice, feedback: Sender<Feedback>) {
+    let body = format!("A {} secret was detected in the clipboard and was blocked.", notice.detector);
+    let mut n = notify_rust::Notification::new();
+    n.summary("Sentinel: Paste Blocked").body(&body);
+
+    #[cfg(all(unix, not(target_os = "macos")))]
+    {
+        for a in notice.actions() {
+            n.action(a.id(), a.label());
+        }
+        let handle = match n.show() {
+            Ok(h) => h,
+            Err(e) => {
+                log::error!("Failed to send notification: {}", e);
+                return;
+            }
+        };
+        let spawned = std::thread::Builder::new().name("notification".to_string()).spawn(move || {
+            handle.wait_for_action(|id| {
+                if let Some(action) = NotificationAction::from_id(id) {
+                    let _ = feedback.send(Feedback { action, notice });
+                }
+            });
+        });
+        if let Err(e) = spawned {
+            log::error!("Failed to wait for notification actions: {}", e);
+        }
+    }
+
+    #[cfg(not(all(unix, not(target_os = "macos"))))]
+    {
+        let _ = feedback;
+        if let Err(e) = n.show() {
+            log::error!("Failed to send notification: {}", e);
+        }
+    }
+}
+
+/// Show a summary of blocks the cooldown held back. It has no buttons: the blocked contents
+/// are not kept for each of them.
+pub fn show_summary(summary: &Summary) {
+    if let Err(e) = notify_rust::Notification::new().summary("Sentinel: Pastes Blocked").body(&summary.message()).show() {
+        log::error!("Failed to send notification: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn action_ids_roundtrip_and_always_allow_needs_an_app() {
+        for a in NotificationAction::ALL {
+            assert_eq!(NotificationAction::from_id(a.id()), Some(a));
+        }
+        assert_eq!(NotificationAction::from_id("__closed"), None);
+
+        let mut notice = BlockedNotice { detector: Detector::Aws, app: Some("Slack".to_string()), fingerprint: "ab".to_string() };
+        assert_eq!(notice.actions().len(), 3);
+        notice.app = None;
+        assert!(!notice.actions().contains(&NotificationAction::AlwaysAllow));
+    }
+}
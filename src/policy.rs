@@ -0,0 +1,114 @@
+use crate::scanner::Finding;
+
+/// Detection policy applied to scanner findings before any redaction decision is made.
+#[derive(Clone, Debug, Default)]
+pub struct Policy {
+    /// Detectors (case-insensitive names) that should only block when the finding passed
+    /// offline structural verification. Useful for noisy detectors with loose patterns.
+    pub verified_only: Vec<String>,
+}
+
+impl Policy {
+    /// Whether a single finding is eligible to trigger a block under this policy.
+    pub fn should_block(&self, finding: &Finding) -> bool {
+        if finding.verified {
+            return true;
+        }
+        !self
+            .verified_only
+            .iter()
+            .any(|d| d.eq_ignore_ascii_case(finding.detector.as_str()))
+    }
+
+    /// Returns the first finding that is eligible to trigger a block, if any.
+    pub fn first_blocking<'a>(&self, findings: &'a [Finding]) -> Option<&'a Finding> {
+        findings.iter().find(|f| self.should_block(f))
+    }
+
+    /// Returns the first finding this policy lets through without a block, if any.
+    pub fn first_skipped<'a>(&self, findings: &'a [Finding]) -> Option<&'a Finding> {
+        findings.iter().find(|f| !self.should_block(f))
+    }
+}
+
+/// Default unsafe targets used when the user provided neither a denylist nor an allowlist:
+/// browsers and chat apps.
+pub fn default_denylist() -> Vec<String> {
+    ["chrome", "safari", "firefox", "slack", "discord", "chatgpt", "teams"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect()
+}
+
+/// Detectors that only block verified matches when no `verified_only` list is configured.
+/// The loose Slack pattern also matches ordinary `xox`-prefixed text.
+pub fn default_verified_only() -> Vec<String> {
+    vec!["Slack".to_string()]
+}
+
+/// Build which detectors are verified-only: the command line's list, else the config file's
+/// (an empty one turns the filter off), else the default.
+pub fn effective_verified_only(cli: &[String], file: Option<&[String]>) -> Vec<String> {
+    if !cli.is_empty() {
+        cli.to_vec()
+    } else {
+        file.map_or_else(default_verified_only, <[String]>::to_vec)
+    }
+}
+
+/// Build which denylist to use: if the user provided neither list, use the default denylist.
+pub fn effective_denylist(denylist: &[String], allowlist: &[String]) -> Vec<String> {
+    if denylist.is_empty() && allowlist.is_empty() {
+        default_denylist()
+    } else {
+        denylist.to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::Detector;
+
+    fn finding(detector: Detector, verified: bool) -> Finding {
+        Finding { detector, start: 0, end: 1, verified }
+    }
+
+    #[test]
+    fn verified_only_filters_unverified_matches() {
+        let p = Policy { verified_only: vec!["slack".to_string()] };
+        assert!(!p.should_block(&finding(Detector::Slack, false)));
+        assert!(p.should_block(&finding(Detector::Slack, true)));
+        // Other detectors are unaffected
+        assert!(p.should_block(&finding(Detector::Stripe, false)));
+    }
+
+    #[test]
+    fn first_blocking_skips_filtered_findings() {
+        let p = Policy { verified_only: vec!["GitHub".to_string()] };
+        let findings = vec![finding(Detector::GitHub, false), finding(Detector::Aws, false)];
+        assert_eq!(p.first_blocking(&findings).map(|f| f.detector), Some(Detector::Aws));
+        // The skipped finding is the filtered one, not simply the first
+        let findings = vec![finding(Detector::Aws, false), finding(Detector::GitHub, false)];
+        assert_eq!(p.first_skipped(&findings).map(|f| f.detector), Some(Detector::GitHub));
+    }
+
+    #[test]
+    fn unverified_slack_matches_do_not_block_by_default() {
+        let p = Policy { verified_only: effective_verified_only(&[], None) };
+        assert!(!p.should_block(&finding(Detector::Slack, false)));
+        assert!(p.should_block(&finding(Detector::Slack, true)));
+
+        // A list from the command line or the config file replaces the default
+        let github = vec!["GitHub".to_string()];
+        assert_eq!(effective_verified_only(&github, Some(&[])), github);
+        assert_eq!(effective_verified_only(&[], Some(&github)), github);
+        assert!(effective_verified_only(&[], Some(&[])).is_empty());
+    }
+
+    #[test]
+    fn default_denylist_only_when_both_lists_empty() {
+        assert!(!effective_denylist(&[], &[]).is_empty());
+        assert!(effective_denylist(&[], &["code".to_string()]).is_empty());
+    }
+}
@@ -0,0 +1,223 @@
+//! Remote policy distribution: the agent periodically pulls a signed policy bundle from the
+//! management server and applies it on top of the config file (see
+//! `FileConfig::apply_policy`). Command-line flags still take precedence.
+//!
+//! The bundle is the response body of `GET {url}`, signed by the server (see
+//! `signing::verify_policy`). Requests carry `If-None-Match` with the last ETag, so an
+//! unchanged policy costs a 304. Every verified bundle is cached on disk and re-verified on
+//! startup, so the last known-good policy stays in force while the server is unreachable. A
+//! bundle that fails verification or parsing is never applied or cached.
+
+use crate::bypass::BypassPolicy;
+use crate::config::create_private_dir;
+use crate::signing::{self, HEADER_POLICY_SIGNATURE};
+use crate::telemetry::{Action, Telemetry};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use reqwest::blocking::Client;
+use reqwest::header::{ETAG, IF_NONE_MATCH};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Settings distributed by the management server. Fields that are set replace the config
+/// file's; unknown fields are rejected, like in the config file.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct PolicyBundle {
+    /// Server-assigned version, for logs.
+    pub version: Option<String>,
+    pub denylist: Option<Vec<String>>,
+    pub allowlist: Option<Vec<String>>,
+    pub verified_only: Option<Vec<String>>,
+    /// Telemetry actions that are sent.
+    pub actions: Option<Vec<Action>>,
+    /// Limits on user bypasses; fields that are set replace the file's.
+    pub bypass: Option<BypassPolicy>,
+}
+
+#[derive(Clone, Debug)]
+pub struct PolicySyncConfig {
+    /// Policy endpoint. Without it only the cached policy is used.
+    pub url: Option<String>,
+    /// The management server's Ed25519 public key, hex.
+    pub public_key: String,
+    pub interval: Duration,
+    /// Last known-good bundle, with its ETag and signature.
+    pub cache_file: PathBuf,
+}
+
+/// Default poll interval.
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(300);
+
+/// The policy endpoint on the server that receives telemetry: `/api/agents/policy` on the
+/// ingest URL's origin.
+pub fn default_policy_url(ingest_url: &str) -> Option<String> {
+    let url = reqwest::Url::parse(ingest_url).ok()?;
+    url.join("/api/agents/policy").ok().map(String::from)
+}
+
+/// On-disk form of the last known-good bundle. The raw body is kept so the signature can be
+/// checked again when it is loaded.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct CachedPolicy {
+    etag: Option<String>,
+    signature: String,
+    body: String,
+    fetched_at: DateTime<Utc>,
+}
+
+struct Active {
+    cached: CachedPolicy,
+    bundle: PolicyBundle,
+}
+
+pub struct PolicySync {
+    cfg: PolicySyncConfig,
+    public_key: [u8; 32],
+    active: RwLock<Option<Active>>,
+    /// Bumped whenever a different bundle is applied, so callers can cheaply notice changes.
+    generation: AtomicU64,
+}
+
+impl PolicySync {
+    /// Parse the server key and load the cached policy, if it still verifies.
+    pub fn new(cfg: PolicySyncConfig) -> anyhow::Result<Self> {
+        let public_key = signing::parse_public_key(&cfg.public_key).map_err(anyhow::Error::msg)?;
+        let sync = Self { cfg, public_key, active: RwLock::new(None), generation: AtomicU64::new(0) };
+        match sync.load_cache() {
+            Ok(Some(active)) => {
+                log::info!("Using cached policy {}", active.bundle.version.as_deref().unwrap_or("(unversioned)"));
+                *sync.active.write().unwrap() = Some(active);
+                sync.generation.store(1, Ordering::SeqCst);
+            }
+            Ok(None) => {}
+            Err(e) => log::warn!("Ignoring cached policy {:?}: {:#}", sync.cfg.cache_file, e),
+        }
+        Ok(sync)
+    }
+
+    /// The policy in force, if any.
+    pub fn current(&self) -> Option<PolicyBundle> {
+        self.active.read().unwrap().as_ref().map(|a| a.bundle.clone())
+    }
+
+    /// SHA-256 (hex) of the policy body in force, if any.
+    pub fn current_hash(&self) -> Option<String> {
+        self.active.read().unwrap().as_ref().map(|a| hex::encode(Sha256::digest(a.cached.body.as_bytes())))
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    /// Fetch the policy once. Returns true if a different bundle was applied.
+    pub fn fetch_once(&self, client: &Client, token: Option<&str>) -> anyhow::Result<bool> {
+        let Some(url) = &self.cfg.url else {
+            return Ok(false);
+        };
+        let mut req = client.get(url);
+        if let Some(etag) = self.active.read().unwrap().as_ref().and_then(|a| a.cached.etag.clone()) {
+            req = req.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(t) = token {
+            req = req.header("Authorization", format!("Bearer {}", t));
+        }
+        let resp = req.send().with_context(|| format!("fetching policy from {}", url))?;
+        if resp.status() == reqwest::StatusCode::NOT_MODIFIED {
+            log::debug!("Policy unchanged");
+            return Ok(false);
+        }
+        if !resp.status().is_success() {
+            anyhow::bail!("policy endpoint returned non-success status: {}", resp.status());
+        }
+        let header = |name| resp.headers().get(name).and_then(|v| v.to_str().ok()).map(str::to_string);
+        let etag = header(ETAG.as_str());
+        let signature = header(HEADER_POLICY_SIGNATURE).context("policy response is not signed")?;
+        let body = resp.text()?;
+        let bundle = self.verify(&body, &signature)?;
+
+        let cached = CachedPolicy { etag, signature, body, fetched_at: Utc::now() };
+        let changed = self.active.read().unwrap().as_ref().is_none_or(|a| a.cached.body != cached.body);
+        self.write_cache(&cached)?;
+        if changed {
+            log::info!("Applying policy {}", bundle.version.as_deref().unwrap_or("(unversioned)"));
+        }
+        *self.active.write().unwrap() = Some(Active { cached, bundle });
+        if changed {
+            self.generation.fetch_add(1, Ordering::SeqCst);
+        }
+        Ok(changed)
+    }
+
+    fn verify(&self, body: &str, signature: &str) -> anyhow::Result<PolicyBundle> {
+        signing::verify_policy(&self.public_key, body.as_bytes(), signature)
+            .map_err(|e| anyhow::anyhow!("policy signature rejected: {}", e))?;
+        serde_json::from_str(body).context("invalid policy bundle")
+    }
+
+    fn load_cache(&self) -> anyhow::Result<Option<Active>> {
+        let data = match std::fs::read_to_string(&self.cfg.cache_file) {
+            Ok(d) => d,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let cached: CachedPolicy = serde_json::from_str(&data)?;
+        let bundle = self.verify(&cached.body, &cached.signature)?;
+        Ok(Some(Active { cached, bundle }))
+    }
+
+    /// Atomically replace the cache file (0600).
+    fn write_cache(&self, cached: &CachedPolicy) -> std::io::Result<()> {
+        let path = &self.cfg.cache_file;
+        if let Some(parent) = path.parent() {
+            create_private_dir(parent)?;
+        }
+        let mut tmp = path.clone().into_os_string();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+        {
+            let mut opts = OpenOptions::new();
+            opts.create(true).write(true).truncate(true);
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::OpenOptionsExt;
+                opts.mode(0o600);
+            }
+            let mut f = opts.open(&tmp)?;
+            f.write_all(serde_json::to_string(cached)?.as_bytes())?;
+            f.sync_all()?;
+        }
+        std::fs::rename(&tmp, path)
+    }
+}
+
+/// How often the poller re-checks the `running` flag while waiting.
+const SHUTDOWN_POLL: Duration = Duration::from_millis(250);
+
+/// Spawn the policy poller: fetch right away, then every `interval` until `running` is cleared.
+/// Requests use the telemetry transport settings and credentials.
+pub fn spawn(sync: Arc<PolicySync>, telemetry: Arc<Telemetry>, running: Arc<AtomicBool>) -> std::io::Result<JoinHandle<()>> {
+    std::thread::Builder::new()
+        .name("policy-sync".to_string())
+        .spawn(move || {
+            while running.load(Ordering::SeqCst) {
+                let result = telemetry
+                    .client()
+                    .and_then(|client| sync.fetch_once(&client, telemetry.bearer_token(&client)?.as_deref()));
+                if let Err(e) = result {
+                    log::warn!("Policy fetch failed; keeping the last known-good policy: {:#}", e);
+                }
+                let deadline = Instant::now() + sync.cfg.interval;
+                while running.load(Ordering::SeqCst) && Instant::now() < deadline {
+                    std::thread::sleep(SHUTDOWN_POLL.min(deadline.saturating_duration_since(Instant::now())));
+                }
+            }
+        })
+}
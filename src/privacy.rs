@@ -0,0 +1,219 @@
+//! Client-side privacy controls applied to telemetry events before they are queued.
+
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+use std::collections::HashMap;
+
+use crate::telemetry::{Action, TelemetryEvent};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// How much of the frontmost app name leaves the machine.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum AppNameLevel {
+    /// The app name as reported by the OS.
+    #[default]
+    Raw,
+    /// A coarse category: browser, chat, ide, terminal, or other.
+    Category,
+    /// A salted hash, so admins can count distinct apps without seeing names. Requires the
+    /// machine id salt.
+    Hash,
+    /// No app name.
+    Drop,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct PrivacyConfig {
+    pub app_name: AppNameLevel,
+    /// With `Raw` or `Hash`, an app name is sent only once this many events have carried it;
+    /// rarer names are sent as their category, so a one-off name (a window title, a niche
+    /// tool) cannot single out a user. 0 turns the minimum off.
+    pub min_app_count: u32,
+    /// Fraction (0.0..=1.0) of events to keep per action; actions not listed are always kept.
+    pub sample_rates: HashMap<Action, f64>,
+    /// Round timestamps down to a multiple of this many seconds; 0 keeps them exact.
+    pub timestamp_granularity_secs: u64,
+}
+
+/// Apps tracked for `min_app_count`. Names beyond this many are treated as rare.
+const MAX_COUNTED_APPS: usize = 1024;
+
+/// Events seen per app name since the agent started, for `PrivacyConfig::min_app_count`.
+#[derive(Debug, Default)]
+pub struct AppNameCounts(HashMap<String, u32>);
+
+impl AppNameCounts {
+    /// Count one more event for `app_name` and return the new total (0 if it is not tracked).
+    fn record(&mut self, app_name: &str) -> u32 {
+        let key = app_name.to_lowercase();
+        if !self.0.contains_key(&key) && self.0.len() >= MAX_COUNTED_APPS {
+            return 0;
+        }
+        let n = self.0.entry(key).or_default();
+        *n = n.saturating_add(1);
+        *n
+    }
+}
+
+// Matched in order, so terminals win over IDEs for names like "Warp"
+const CATEGORIES: &[(&str, &[&str])] = &[
+    ("terminal", &["terminal", "iterm", "alacritty", "kitty", "wezterm", "konsole", "powershell", "cmd", "warp", "hyper"]),
+    ("ide", &["code", "visual studio", "intellij", "pycharm", "webstorm", "goland", "clion", "rider", "xcode", "android studio", "sublime", "vim", "emacs", "zed", "cursor"]),
+    ("chat", &["slack", "discord", "teams", "zoom", "telegram", "whatsapp", "signal", "messages", "mattermost", "element", "chatgpt"]),
+    ("browser", &["chrome", "firefox", "safari", "edge", "brave", "opera", "arc", "vivaldi", "chromium"]),
+];
+
+/// Coarse category of an app name. Single-word patterns match whole words (ignoring a trailing
+/// version number, as in "iTerm2"), so "Arc" does not match "Archive Utility".
+pub fn app_category(app_name: &str) -> &'static str {
+    let lower = app_name.to_lowercase();
+    let words: Vec<&str> = lower
+        .split(|c: char| !c.is_alphanumeric())
+        .map(|w| w.trim_end_matches(|c: char| c.is_ascii_digit()))
+        .filter(|w| !w.is_empty())
+        .collect();
+    let hit = |p: &str| if p.contains(' ') { lower.contains(p) } else { words.contains(&p) };
+    CATEGORIES
+        .iter()
+        .find(|(_, patterns)| patterns.iter().any(|p| hit(p)))
+        .map(|(cat, _)| *cat)
+        .unwrap_or("other")
+}
+
+/// Salted hash of an app name: 16 hex chars of HMAC-SHA256 over the lowercased name.
+pub fn hash_app_name(app_name: &str, salt: &str) -> String {
+    let mut mac = HmacSha256::new_from_slice(salt.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(b"sentinel-app:");
+    mac.update(app_name.to_lowercase().as_bytes());
+    hex::encode(&mac.finalize().into_bytes()[..8])
+}
+
+/// Round a timestamp down to a multiple of `granularity_secs`.
+pub fn round_timestamp(ts: DateTime<Utc>, granularity_secs: u64) -> DateTime<Utc> {
+    if granularity_secs == 0 {
+        return ts;
+    }
+    let g = granularity_secs as i64;
+    let secs = ts.timestamp().div_euclid(g) * g;
+    DateTime::<Utc>::from_timestamp(secs, 0).unwrap_or(ts)
+}
+
+impl PrivacyConfig {
+    /// Apply sampling, app-name generalization, and timestamp rounding. Returns `None` when
+    /// the event is sampled out. `salt` keys the app-name hash; `counts` tracks app names for
+    /// `min_app_count`.
+    pub fn apply(&self, mut event: TelemetryEvent, salt: &str, counts: &mut AppNameCounts) -> Option<TelemetryEvent> {
+        if let Some(rate) = self.sample_rates.get(&event.action)
+            && crate::random::unit() >= rate.clamp(0.0, 1.0)
+        {
+            return None;
+        }
+        let rare = |name: &str, counts: &mut AppNameCounts| self.min_app_count > 0 && counts.record(name) < self.min_app_count;
+        event.app_name = match (self.app_name, event.app_name.take()) {
+            (_, None) | (AppNameLevel::Drop, _) => None,
+            (AppNameLevel::Category, Some(name)) => Some(app_category(&name).to_string()),
+            (AppNameLevel::Raw | AppNameLevel::Hash, Some(name)) if rare(&name, counts) => Some(app_category(&name).to_string()),
+            (AppNameLevel::Raw, Some(name)) => Some(name),
+            // Unsalted, the hash of a known app name is public; `TelemetryConfig::validate` rejects this
+            (AppNameLevel::Hash, Some(_)) if salt.is_empty() => None,
+            (AppNameLevel::Hash, Some(name)) => Some(hash_app_name(&name, salt)),
+        };
+        event.timestamp = round_timestamp(event.timestamp, self.timestamp_granularity_secs);
+        Some(event)
+    }
+}
+
+/// Parse `action=rate` pairs, e.g. `detected_but_skipped=0.1`.
+pub fn parse_sample_rate(s: &str) -> Result<(Action, f64), String> {
+    let (action, rate) = s.split_once('=').ok_or_else(|| format!("expected ACTION=RATE, got '{}'", s))?;
+    let action = <Action as clap::ValueEnum>::from_str(action.trim(), false).map_err(|_| {
+        let known: Vec<&str> = Action::ALL.iter().map(Action::as_str).collect();
+        format!("unknown action '{}' (expected one of {})", action.trim(), known.join(", "))
+    })?;
+    let rate: f64 = rate.trim().parse().map_err(|e| format!("invalid rate in '{}': {}", s, e))?;
+    if !(0.0..=1.0).contains(&rate) {
+        return Err(format!("rate must be between 0 and 1, got {}", rate));
+    }
+    Ok((action, rate))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::Detector;
+    use crate::telemetry::{Action, SCHEMA_VERSION};
+
+    fn event(action: Action, app: &str) -> TelemetryEvent {
+        TelemetryEvent {
+            schema_version: SCHEMA_VERSION,
+            event_id: "e".to_string(),
+            timestamp: "2025-03-04T05:06:07.891Z".parse().unwrap(),
+            secret_type: Some(Detector::Aws),
+            action,
+            app_name: Some(app.to_string()),
+            rule: None,
+            machine_id_hashed: None,
+            agent_version: "0.1.0".to_string(),
+            heartbeat: None,
+            bypass: None,
+            fingerprint: None,
+            prev_hash: None,
+        }
+    }
+
+    #[test]
+    fn app_name_levels() {
+        let mut p = PrivacyConfig { app_name: AppNameLevel::Category, ..Default::default() };
+        let cat = |p: &PrivacyConfig, app: &str| p.apply(event(Action::Blocked, app), "s", &mut AppNameCounts::default()).unwrap().app_name;
+        assert_eq!(cat(&p, "Google Chrome").as_deref(), Some("browser"));
+        assert_eq!(cat(&p, "Slack").as_deref(), Some("chat"));
+        assert_eq!(cat(&p, "Visual Studio Code").as_deref(), Some("ide"));
+        assert_eq!(cat(&p, "iTerm2").as_deref(), Some("terminal"));
+        assert_eq!(cat(&p, "Budget.xlsx - Numbers").as_deref(), Some("other"));
+        assert_eq!(cat(&p, "Archive Utility").as_deref(), Some("other"));
+
+        p.app_name = AppNameLevel::Hash;
+        let h = cat(&p, "Slack").unwrap();
+        assert_eq!(h.len(), 16);
+        assert_eq!(h, hash_app_name("slack", "s"));
+        assert_ne!(h, hash_app_name("slack", "other-salt"));
+
+        assert!(p.apply(event(Action::Blocked, "Slack"), "", &mut AppNameCounts::default()).unwrap().app_name.is_none());
+
+        p.app_name = AppNameLevel::Drop;
+        assert!(cat(&p, "Slack").is_none());
+    }
+
+    #[test]
+    fn rare_app_names_fall_back_to_their_category() {
+        let p = PrivacyConfig { min_app_count: 3, ..Default::default() };
+        let mut counts = AppNameCounts::default();
+        let mut app = |name: &str| p.apply(event(Action::Blocked, name), "s", &mut counts).unwrap().app_name.unwrap();
+        assert_eq!(app("Slack"), "chat");
+        assert_eq!(app("slack"), "chat");
+        assert_eq!(app("Slack"), "Slack");
+        assert_eq!(app("Q3 layoffs.docx - Word"), "other");
+    }
+
+    #[test]
+    fn sampling_and_rounding() {
+        let p = PrivacyConfig {
+            sample_rates: [(Action::DetectedButSkipped, 0.0), (Action::Blocked, 1.0)].into(),
+            timestamp_granularity_secs: 3600,
+            ..Default::default()
+        };
+        let mut counts = AppNameCounts::default();
+        assert!(p.apply(event(Action::DetectedButSkipped, "x"), "", &mut counts).is_none());
+        let kept = p.apply(event(Action::Blocked, "x"), "", &mut counts).unwrap();
+        assert_eq!(kept.timestamp.to_rfc3339(), "2025-03-04T05:00:00+00:00");
+
+        assert_eq!(parse_sample_rate("blocked=0.25"), Ok((Action::Blocked, 0.25)));
+        assert!(parse_sample_rate("blocked=2").is_err());
+        assert!(parse_sample_rate("blocked").is_err());
+        assert!(parse_sample_rate("blokced=0.5").unwrap_err().contains("unknown action"));
+    }
+}
@@ -0,0 +1,10 @@
+//! Randomness from the OS RNG, shared by telemetry sampling and retry jitter.
+
+/// Uniform random number in [0, 1). Returns 0.5 if the OS RNG is unavailable.
+pub fn unit() -> f64 {
+    let mut buf = [0u8; 8];
+    if getrandom::getrandom(&mut buf).is_err() {
+        return 0.5;
+    }
+    (u64::from_le_bytes(buf) >> 11) as f64 / (1u64 << 53) as f64
+}
@@ -0,0 +1,302 @@
+//! Per-agent Ed25519 keys and detached signatures over upload batches.
+//!
+//! Each agent generates a keypair on first use and keeps the private key in a `KeyStore` (see
+//! `crypto`). Every upload carries four headers:
+//!
+//! - `X-Sentinel-Key-Id`: first 16 bytes of SHA-256 over the public key, hex
+//! - `X-Sentinel-Timestamp`: unix seconds when the request was signed
+//! - `X-Sentinel-Nonce`: 16 random bytes, hex
+//! - `X-Sentinel-Signature`: Ed25519 signature, hex, over
+//!   `sentinel-batch-v1\n{key_id}\n{timestamp}\n{nonce}\n` followed by the raw body
+//!
+//! The ingest side looks the key id up among enrolled public keys and calls `verify_batch`,
+//! which checks the signature, the clock window, and (through `ReplayGuard`) nonce reuse.
+//!
+//! In the other direction, policy bundles from the management server carry
+//! `X-Sentinel-Policy-Signature`: the server's Ed25519 signature, hex, over
+//! `sentinel-policy-v1\n` followed by the raw body (see `policy_sync`).
+
+use crate::crypto::KeyStore;
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+pub const HEADER_KEY_ID: &str = "X-Sentinel-Key-Id";
+pub const HEADER_TIMESTAMP: &str = "X-Sentinel-Timestamp";
+pub const HEADER_NONCE: &str = "X-Sentinel-Nonce";
+pub const HEADER_SIGNATURE: &str = "X-Sentinel-Signature";
+pub const HEADER_POLICY_SIGNATURE: &str = "X-Sentinel-Policy-Signature";
+
+const SIGNATURE_CONTEXT: &str = "sentinel-batch-v1";
+const POLICY_CONTEXT: &str = "sentinel-policy-v1";
+
+/// Default accepted clock difference between agent and server, in seconds.
+pub const DEFAULT_MAX_SKEW_SECS: i64 = 300;
+
+/// The agent's Ed25519 signing key.
+pub struct AgentKey {
+    key: SigningKey,
+}
+
+impl AgentKey {
+    pub fn from_seed(seed: &[u8; 32]) -> Self {
+        Self { key: SigningKey::from_bytes(seed) }
+    }
+
+    /// Load the key from `store`, generating and storing a new one on first use.
+    pub fn load_or_create(store: &dyn KeyStore) -> std::io::Result<Self> {
+        if let Some(seed) = store.load()? {
+            let seed: [u8; 32] = seed
+                .try_into()
+                .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidData, "agent signing key is not 32 bytes"))?;
+            return Ok(Self::from_seed(&seed));
+        }
+        let mut seed = [0u8; 32];
+        getrandom::getrandom(&mut seed).map_err(std::io::Error::other)?;
+        store.store(&seed)?;
+        let key = Self::from_seed(&seed);
+        log::info!("Created agent signing key {} ({} key store)", key.key_id(), store.name());
+        Ok(key)
+    }
+
+    pub fn public_key(&self) -> [u8; 32] {
+        self.key.verifying_key().to_bytes()
+    }
+
+    pub fn key_id(&self) -> String {
+        key_id(&self.public_key())
+    }
+
+    /// Sign `body` for sending at `timestamp` (unix seconds) with a fresh nonce.
+    pub fn sign_batch(&self, body: &[u8], timestamp: i64) -> std::io::Result<BatchSignature> {
+        let mut nonce = [0u8; 16];
+        getrandom::getrandom(&mut nonce).map_err(std::io::Error::other)?;
+        let key_id = self.key_id();
+        let nonce = hex::encode(nonce);
+        let sig = self.key.sign(&signed_message(&key_id, timestamp, &nonce, body));
+        Ok(BatchSignature { key_id, timestamp, nonce, signature: hex::encode(sig.to_bytes()) })
+    }
+}
+
+/// Identifier of a public key: hex of the first 16 bytes of its SHA-256.
+pub fn key_id(public_key: &[u8; 32]) -> String {
+    hex::encode(&Sha256::digest(public_key)[..16])
+}
+
+/// The signature headers of one request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BatchSignature {
+    pub key_id: String,
+    pub timestamp: i64,
+    pub nonce: String,
+    pub signature: String,
+}
+
+impl BatchSignature {
+    pub fn headers(&self) -> [(&'static str, String); 4] {
+        [
+            (HEADER_KEY_ID, self.key_id.clone()),
+            (HEADER_TIMESTAMP, self.timestamp.to_string()),
+            (HEADER_NONCE, self.nonce.clone()),
+            (HEADER_SIGNATURE, self.signature.clone()),
+        ]
+    }
+
+    /// Read the signature headers with `header` (a case-insensitive lookup by name). Returns
+    /// `None` if any is missing or the timestamp is not a number.
+    pub fn from_headers(header: impl Fn(&str) -> Option<String>) -> Option<Self> {
+        Some(Self {
+            key_id: header(HEADER_KEY_ID)?,
+            timestamp: header(HEADER_TIMESTAMP)?.trim().parse().ok()?,
+            nonce: header(HEADER_NONCE)?,
+            signature: header(HEADER_SIGNATURE)?,
+        })
+    }
+}
+
+fn signed_message(key_id: &str, timestamp: i64, nonce: &str, body: &[u8]) -> Vec<u8> {
+    let mut msg = format!("{}\n{}\n{}\n{}\n", SIGNATURE_CONTEXT, key_id, timestamp, nonce).into_bytes();
+    msg.extend_from_slice(body);
+    msg
+}
+
+/// Why a signed batch was rejected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SignatureError {
+    /// A header is not in the expected format.
+    Malformed,
+    /// The key id does not belong to the given public key.
+    WrongKey,
+    /// The signature does not match the body and headers.
+    BadSignature,
+    /// The timestamp is outside the accepted clock window.
+    Expired,
+    /// The nonce was already used within the window.
+    Replayed,
+}
+
+impl std::fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            SignatureError::Malformed => "malformed signature headers",
+            SignatureError::WrongKey => "key id does not match the public key",
+            SignatureError::BadSignature => "invalid signature",
+            SignatureError::Expired => "timestamp outside the accepted window",
+            SignatureError::Replayed => "nonce already used",
+        })
+    }
+}
+
+impl std::error::Error for SignatureError {}
+
+/// Remembers nonces for the length of the clock window, so a captured request cannot be
+/// replayed while its timestamp is still accepted. One guard should cover all agents.
+#[derive(Debug)]
+pub struct ReplayGuard {
+    max_skew_secs: i64,
+    seen: HashMap<(String, String), i64>,
+}
+
+impl ReplayGuard {
+    pub fn new(max_skew_secs: i64) -> Self {
+        Self { max_skew_secs, seen: HashMap::new() }
+    }
+
+    pub fn max_skew_secs(&self) -> i64 {
+        self.max_skew_secs
+    }
+
+    /// Record a nonce; false if it was already seen. Entries older than the window are dropped.
+    fn check_and_record(&mut self, key_id: &str, nonce: &str, timestamp: i64, now: i64) -> bool {
+        let window = self.max_skew_secs;
+        self.seen.retain(|_, ts| (now - *ts).abs() <= window);
+        self.seen.insert((key_id.to_string(), nonce.to_string()), timestamp).is_none()
+    }
+}
+
+impl Default for ReplayGuard {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_SKEW_SECS)
+    }
+}
+
+/// Verify a signed batch from the agent whose enrolled public key is `public_key`. `now` is the
+/// server's unix time. The nonce is only recorded once the signature checks out, so forged
+/// requests cannot fill the replay cache.
+pub fn verify_batch(
+    public_key: &[u8; 32],
+    body: &[u8],
+    sig: &BatchSignature,
+    now: i64,
+    replay: &mut ReplayGuard,
+) -> Result<(), SignatureError> {
+    if sig.key_id != key_id(public_key) {
+        return Err(SignatureError::WrongKey);
+    }
+    let nonce = hex::decode(&sig.nonce).map_err(|_| SignatureError::Malformed)?;
+    let raw: [u8; 64] = hex::decode(&sig.signature)
+        .ok()
+        .and_then(|s| s.try_into().ok())
+        .ok_or(SignatureError::Malformed)?;
+    if nonce.len() < 16 {
+        return Err(SignatureError::Malformed);
+    }
+    if (now - sig.timestamp).abs() > replay.max_skew_secs() {
+        return Err(SignatureError::Expired);
+    }
+    let key = VerifyingKey::from_bytes(public_key).map_err(|_| SignatureError::WrongKey)?;
+    key.verify_strict(&signed_message(&sig.key_id, sig.timestamp, &sig.nonce, body), &Signature::from_bytes(&raw))
+        .map_err(|_| SignatureError::BadSignature)?;
+    if !replay.check_and_record(&sig.key_id, &sig.nonce, sig.timestamp, now) {
+        return Err(SignatureError::Replayed);
+    }
+    Ok(())
+}
+
+fn policy_message(body: &[u8]) -> Vec<u8> {
+    let mut msg = format!("{}\n", POLICY_CONTEXT).into_bytes();
+    msg.extend_from_slice(body);
+    msg
+}
+
+/// Sign a policy bundle with the management server's key seed, hex. For server tooling and tests.
+pub fn sign_policy(seed: &[u8; 32], body: &[u8]) -> String {
+    hex::encode(SigningKey::from_bytes(seed).sign(&policy_message(body)).to_bytes())
+}
+
+/// Verify the hex `signature` of a policy bundle against the management server's public key.
+pub fn verify_policy(public_key: &[u8; 32], body: &[u8], signature: &str) -> Result<(), SignatureError> {
+    let raw: [u8; 64] = hex::decode(signature.trim())
+        .ok()
+        .and_then(|s| s.try_into().ok())
+        .ok_or(SignatureError::Malformed)?;
+    let key = VerifyingKey::from_bytes(public_key).map_err(|_| SignatureError::WrongKey)?;
+    key.verify_strict(&policy_message(body), &Signature::from_bytes(&raw))
+        .map_err(|_| SignatureError::BadSignature)
+}
+
+/// Parse a hex Ed25519 public key.
+pub fn parse_public_key(hex_key: &str) -> Result<[u8; 32], String> {
+    hex::decode(hex_key.trim())
+        .ok()
+        .and_then(|k| k.try_into().ok())
+        .ok_or_else(|| format!("'{}' is not a hex Ed25519 public key (64 hex digits)", hex_key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sign_and_verify() {
+        let key = AgentKey::from_seed(&[3u8; 32]);
+        let pk = key.public_key();
+        let body = br#"{"schema_version":2,"events":[]}"#;
+        let sig = key.sign_batch(body, 1_000).unwrap();
+        let mut guard = ReplayGuard::new(300);
+
+        assert_eq!(verify_batch(&pk, body, &sig, 1_100, &mut guard), Ok(()));
+        assert_eq!(verify_batch(&pk, body, &sig, 1_100, &mut guard), Err(SignatureError::Replayed));
+
+        let fresh = key.sign_batch(body, 1_000).unwrap();
+        assert_ne!(fresh.nonce, sig.nonce);
+        assert_eq!(verify_batch(&pk, b"{}", &fresh, 1_000, &mut guard), Err(SignatureError::BadSignature));
+        assert_eq!(verify_batch(&pk, body, &fresh, 1_301, &mut guard), Err(SignatureError::Expired));
+        let shifted = BatchSignature { timestamp: 1_001, ..fresh.clone() };
+        assert_eq!(verify_batch(&pk, body, &shifted, 1_000, &mut guard), Err(SignatureError::BadSignature));
+        let other = AgentKey::from_seed(&[4u8; 32]).public_key();
+        assert_eq!(verify_batch(&other, body, &fresh, 1_000, &mut guard), Err(SignatureError::WrongKey));
+        assert_eq!(verify_batch(&pk, body, &fresh, 1_000, &mut guard), Ok(()));
+
+        let headers: HashMap<String, String> =
+            fresh.headers().into_iter().map(|(k, v)| (k.to_ascii_lowercase(), v)).collect();
+        let parsed = BatchSignature::from_headers(|name| headers.get(&name.to_ascii_lowercase()).cloned());
+        assert_eq!(parsed, Some(fresh));
+    }
+
+    #[test]
+    fn policy_signatures() {
+        let seed = [9u8; 32];
+        let pk = AgentKey::from_seed(&seed).public_key();
+        let body = br#"{"version":"1"}"#;
+        let sig = sign_policy(&seed, body);
+        assert_eq!(verify_policy(&pk, body, &sig), Ok(()));
+        assert_eq!(verify_policy(&pk, br#"{"version":"2"}"#, &sig), Err(SignatureError::BadSignature));
+        assert_eq!(verify_policy(&pk, body, "zz"), Err(SignatureError::Malformed));
+        // A batch signature over the same bytes is not a policy signature
+        let batch = hex::encode(AgentKey::from_seed(&seed).key.sign(body).to_bytes());
+        assert_eq!(verify_policy(&pk, body, &batch), Err(SignatureError::BadSignature));
+        assert_eq!(parse_public_key(&hex::encode(pk)), Ok(pk));
+        assert!(parse_public_key("abcd").is_err());
+    }
+
+    #[test]
+    fn key_is_created_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = crate::crypto::FileKeyStore::new(&dir.path().join("agent_signing.key"));
+        let a = AgentKey::load_or_create(&store).unwrap();
+        let b = AgentKey::load_or_create(&store).unwrap();
+        assert_eq!(a.public_key(), b.public_key());
+        assert_eq!(a.key_id().len(), 32);
+    }
+}
@@ -1,98 +1,497 @@
-use crate::scanner;
-use chrono::Utc;
+use crate::chain;
+use crate::crypto::{self, KeySource, QueueCipher};
+use crate::enrollment::{Credential, CredentialStore};
+use crate::bypass::{Bypass, BypassGrant};
+use crate::heartbeat::Heartbeat;
+use crate::machine_id::MachineIdConfig;
+use crate::privacy::{AppNameCounts, AppNameLevel, PrivacyConfig};
+use crate::scanner::Detector;
+use crate::signing::AgentKey;
+use crate::throttle::{RateLimiter, TelemetryThrottleConfig};
+use crate::transport::TransportConfig;
+use chrono::{DateTime, Utc};
+use once_cell::sync::OnceCell;
 use reqwest::blocking::Client;
-use serde::Serialize;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::fs::{File, OpenOptions};
 use std::io::{BufRead, BufReader, Seek, SeekFrom, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Condvar, Mutex, RwLock};
+use std::time::{Duration, Instant};
 use uuid::Uuid;
 
+/// Version of the `EventBatch` envelope and event schema sent to the ingest endpoint.
+///
+/// 2: typed `action`/`secret_type` enums, RFC 3339 UTC timestamps, per-event `schema_version`.
+/// 3: `heartbeat` action and payload; `secret_type` is absent on events that are not about a
+/// finding.
+/// 4: `bypass_granted` action and `bypass` payload.
+/// 5: `false_positive_reported` action and `fingerprint`.
+pub const SCHEMA_VERSION: u32 = 5;
+
+/// Outcome of a detection, or another agent event, reported as `TelemetryEvent::action`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum Action {
+    /// The clipboard was overwritten with a redaction notice.
+    Blocked,
+    /// Not redacted because the frontmost app is on the allowlist (`rule` `allowlist`) or a
+    /// user bypass was active (`bypass`).
+    Allowed,
+    /// Detected but left alone; `rule` says why: `dry-run`, `context` (app not on
+    /// the denylist), or `verified-only` (finding failed structural verification).
+    DetectedButSkipped,
+    /// Redaction was attempted but writing the clipboard failed.
+    ClipboardWriteFailed,
+    /// Periodic agent health report; the event carries `heartbeat` and no `secret_type`.
+    Heartbeat,
+    /// The user bypassed or paused the agent (see `bypass`); the event carries `bypass`, and
+    /// `secret_type` and `app_name` when the bypass is limited to them.
+    BypassGranted,
+    /// The user reported a blocked finding as not being a secret; the event carries
+    /// `fingerprint`.
+    FalsePositiveReported,
+}
+
+impl Action {
+    pub const ALL: [Action; 7] = [
+        Action::Blocked,
+        Action::Allowed,
+        Action::DetectedButSkipped,
+        Action::ClipboardWriteFailed,
+        Action::Heartbeat,
+        Action::BypassGranted,
+        Action::FalsePositiveReported,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Action::Blocked => "blocked",
+            Action::Allowed => "allowed",
+            Action::DetectedButSkipped => "detected_but_skipped",
+            Action::ClipboardWriteFailed => "clipboard_write_failed",
+            Action::Heartbeat => "heartbeat",
+            Action::BypassGranted => "bypass_granted",
+            Action::FalsePositiveReported => "false_positive_reported",
+        }
+    }
+}
+
+impl std::fmt::Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct TelemetryConfig {
     pub url: Option<String>,
     pub api_key: Option<String>,
+    /// Active queue file; rotated segments, cursors, and chain files live next to it.
     pub queue_file: PathBuf,
+    /// Private (0700) directory for agent state that is not part of the queue: the install id,
+    /// the signing key, and the device credential.
+    pub state_dir: PathBuf,
     pub enabled: bool,
+    /// Rotate the active queue file once it grows beyond this many bytes.
+    pub max_queue_bytes: u64,
+    /// Queued events older than this are pruned, delivered or not.
+    pub max_age_days: u32,
+    /// Encrypt queued events at rest (see `crypto`).
+    pub encrypt: bool,
+    /// Where the queue encryption key is kept.
+    pub key_source: KeySource,
+    /// How `machine_id_hashed` and the batch `agent_id` are derived.
+    pub machine_id: MachineIdConfig,
+    /// App-name generalization, sampling, and timestamp rounding applied before queueing.
+    pub privacy: PrivacyConfig,
+    /// Actions that are sent; events with any other action are dropped before queueing.
+    pub actions: Vec<Action>,
+    /// TLS (CA bundle, client certificate, SPKI pins) and proxy settings for uploads.
+    pub transport: TransportConfig,
+    /// Seconds between heartbeat events (see `heartbeat`); 0 turns them off.
+    pub heartbeat_interval_secs: u64,
+    /// Maximum number of events per upload request.
+    pub max_batch_events: usize,
+    /// Maximum serialized size of the events in one upload request.
+    pub max_batch_bytes: usize,
+    /// Cap on the on-disk size of the whole queue (rotated segments plus the active file).
+    /// Once exceeded, the oldest events are dropped first.
+    pub max_total_bytes: u64,
+    /// Per-key rate limit on queued events (see `throttle`).
+    pub throttle: TelemetryThrottleConfig,
 }
 
 impl Default for TelemetryConfig {
     fn default() -> Self {
-        let mut q = std::env::temp_dir();
-        q.push("sentinel_telemetry_queue.jsonl");
         Self {
             url: None,
             api_key: None,
-            queue_file: q,
+            queue_file: crate::config::state_dir(false).join("telemetry_queue.jsonl"),
+            state_dir: crate::config::state_dir(false),
             enabled: false,
+            max_queue_bytes: 1_000_000,
+            max_age_days: 30,
+            encrypt: false,
+            key_source: KeySource::File,
+            machine_id: MachineIdConfig::default(),
+            privacy: PrivacyConfig::default(),
+            actions: Action::ALL.to_vec(),
+            transport: TransportConfig::default(),
+            heartbeat_interval_secs: crate::heartbeat::DEFAULT_INTERVAL.as_secs(),
+            max_batch_events: 100,
+            max_batch_bytes: 256 * 1024,
+            max_total_bytes: 10_000_000,
+            throttle: TelemetryThrottleConfig::default(),
         }
     }
 }
 
-#[derive(Serialize, Debug)]
+impl TelemetryConfig {
+    /// Reject combinations that would weaken the pseudonymization promised in the docs.
+    pub fn validate(&self) -> Result<(), String> {
+        self.machine_id.validate()?;
+        if self.privacy.app_name == AppNameLevel::Hash && !self.machine_id.has_salt() {
+            return Err("telemetry app name level hash requires a salt (--machine-id-salt or telemetry.machine_id.salt)".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
 pub struct TelemetryEvent {
+    /// Schema the event was written with. Events queued before the field existed are read as
+    /// the current version; anything that parses here fits it.
+    #[serde(default = "current_schema_version")]
+    pub schema_version: u32,
+    /// Unique event id; sent as `id` to match the dashboard's `telemetry_events` primary key.
+    #[serde(rename = "id", alias = "event_id")]
     pub event_id: String,
-    pub timestamp: String,
-    pub secret_type: String,
-    pub action: String,
+    /// When the decision was made (RFC 3339, UTC).
+    pub timestamp: DateTime<Utc>,
+    /// Detector that matched; absent on heartbeats and unscoped bypasses.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret_type: Option<Detector>,
+    pub action: Action,
+    /// Frontmost app, after the configured app-name privacy level.
     pub app_name: Option<String>,
+    /// Rule that led to the decision, e.g. `denylist-default` or `dry-run`.
     pub rule: Option<String>,
+    /// Salted machine identifier (see `machine_id`).
     pub machine_id_hashed: Option<String>,
     pub agent_version: String,
+    /// Agent health, on `heartbeat` events (see `heartbeat`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub heartbeat: Option<Heartbeat>,
+    /// Bypass details, on `bypass_granted` events (see `bypass`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bypass: Option<BypassGrant>,
+    /// Finding fingerprint (see `scanner::fingerprint`), on `false_positive_reported` events.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<String>,
+    /// SHA-256 of the previous queue line (see `chain`). Set when the event is queued.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prev_hash: Option<String>,
+}
+
+fn current_schema_version() -> u32 {
+    SCHEMA_VERSION
+}
+
+/// Upload envelope: one request carries a chunk of queued events.
+#[derive(Serialize, JsonSchema, Debug)]
+pub struct EventBatch<'a> {
+    pub schema_version: u32,
+    pub agent_id: Option<String>,
+    pub events: &'a [TelemetryEvent],
+}
+
+/// JSON Schema (draft 7) of the upload envelope, with `TelemetryEvent` under `definitions`.
+/// Printed by `sentinel_pii schema` for generating ingest-side validators. A copy is checked in
+/// as `dashboard/lib/event-schema.json`.
+pub fn json_schema() -> serde_json::Value {
+    serde_json::to_value(schemars::schema_for!(EventBatch<'static>)).expect("JSON Schema serializes")
+}
+
+/// Per-batch acknowledgement returned by the ingest endpoint.
+#[derive(Deserialize, Debug, Default)]
+pub struct BatchAck {
+    pub accepted: Vec<String>,
+    #[serde(default)]
+    pub rejected: Vec<RejectedEvent>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct RejectedEvent {
+    /// Empty when the server could not read an id from the event.
+    #[serde(default)]
+    pub id: String,
+    /// Position of the event in the batch.
+    #[serde(default)]
+    pub index: Option<usize>,
+    #[serde(default)]
+    pub error: String,
+}
+
+/// A line of the dead-letter file: an event the server rejected, or a queue line the agent
+/// could not read (kept as written).
+#[derive(Serialize)]
+struct RejectedRecord<'a> {
+    rejected_at: chrono::DateTime<Utc>,
+    error: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    event: Option<&'a TelemetryEvent>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    line: Option<&'a str>,
+}
+
+/// Split events into chunks of at most `max_events` events and roughly `max_bytes` of
+/// serialized JSON. A single oversized event still gets its own chunk.
+pub fn chunk_events(events: &[TelemetryEvent], max_events: usize, max_bytes: usize) -> Vec<&[TelemetryEvent]> {
+    let max_events = max_events.max(1);
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    let mut bytes = 0;
+    for (i, ev) in events.iter().enumerate() {
+        // +1 for the separating comma
+        let size = serde_json::to_vec(ev).map(|v| v.len()).unwrap_or(0) + 1;
+        if i > start && (i - start >= max_events || bytes + size > max_bytes) {
+            chunks.push(&events[start..i]);
+            start = i;
+            bytes = 0;
+        }
+        bytes += size;
+    }
+    if start < events.len() {
+        chunks.push(&events[start..]);
+    }
+    chunks
 }
 
 pub struct Telemetry {
     cfg: TelemetryConfig,
+    /// Serializes every mutation of the queue files (append, cursor commit, rotate, prune).
+    /// Held only for local file I/O, never across network requests.
+    io_lock: Mutex<()>,
+    /// Bumped whenever queue files are rewritten, renamed, or deleted, so an in-flight flush can
+    /// tell that the offsets it read are stale. Modified only under `io_lock`.
+    layout: AtomicU64,
+    /// Queue record cipher, loaded on first use. `None` when encryption is off and no key exists.
+    cipher: OnceCell<Option<QueueCipher>>,
+    /// What the machine id is derived from, read once at construction (see `MachineIdConfig::source`).
+    machine_source: Option<String>,
+    /// Key for chain checkpoints and the head, loaded (or created) on first use.
+    chain_key: OnceCell<chain::ChainKey>,
+    /// Agent signing key, loaded (or created) on first use.
+    signing_key: OnceCell<AgentKey>,
+    /// Actions that are queued; starts as `cfg.actions` and follows remote policy updates.
+    actions: RwLock<Vec<Action>>,
+    /// Events queued since the last successful flush, used to wake the background uploader.
+    pending: Mutex<usize>,
+    pending_cv: Condvar,
+    /// Events per (action, detector, app) in the current throttle window.
+    throttle: Mutex<RateLimiter<ThrottleKey>>,
+    /// App names seen so far, for `PrivacyConfig::min_app_count`.
+    app_counts: Mutex<AppNameCounts>,
+}
+
+/// Events are throttled per action, detector, and app.
+type ThrottleKey = (Action, Option<Detector>, Option<String>);
+
+/// An undelivered event read from a queue segment, with the byte offset just past its line.
+struct QueuedEvent {
+    event: TelemetryEvent,
+    end: u64,
+}
+
+/// A queue line that could not be decrypted or does not match the event schema.
+struct UnreadableLine {
+    line: String,
+    error: String,
+    end: u64,
+}
+
+/// Result of reading a segment from its committed cursor.
+struct UndeliveredRead {
+    events: Vec<QueuedEvent>,
+    unreadable: Vec<UnreadableLine>,
+    /// Offset just past the last complete line read (checkpoints and bad lines included).
+    scanned_end: u64,
 }
 
 impl Telemetry {
-    pub fn new(cfg: TelemetryConfig) -> Self {
-        Self { cfg }
+    pub fn new(mut cfg: TelemetryConfig) -> Self {
+        if cfg.machine_id.install_id_file.is_none() {
+            cfg.machine_id.install_id_file = Some(cfg.state_dir.join("install_id"));
+        }
+        Self {
+            machine_source: cfg.machine_id.source(),
+            actions: RwLock::new(cfg.actions.clone()),
+            throttle: Mutex::new(RateLimiter::new(cfg.throttle.window, cfg.throttle.max_events)),
+            cfg,
+            io_lock: Mutex::new(()),
+            layout: AtomicU64::new(0),
+            cipher: OnceCell::new(),
+            chain_key: OnceCell::new(),
+            signing_key: OnceCell::new(),
+            pending: Mutex::new(0),
+            pending_cv: Condvar::new(),
+            app_counts: Mutex::new(AppNameCounts::default()),
+        }
     }
 
-    /// Queue an event to local file for later upload.
+    /// Number of events queued since the last successful flush.
+    pub fn pending(&self) -> usize {
+        *self.pending.lock().unwrap()
+    }
+
+    /// Block until at least `threshold` events are pending or `timeout` elapses.
+    /// Returns true if the threshold was reached.
+    pub fn wait_for_pending(&self, threshold: usize, timeout: Duration) -> bool {
+        let guard = self.pending.lock().unwrap();
+        let (guard, _) = self
+            .pending_cv
+            .wait_timeout_while(guard, timeout, |n| *n < threshold)
+            .unwrap();
+        *guard >= threshold
+    }
+
+    /// Wake anyone blocked in `wait_for_pending`, e.g. on shutdown.
+    pub fn wake(&self) {
+        self.pending_cv.notify_all();
+    }
+
+    /// Replace the actions that are queued, e.g. when a new remote policy is applied.
+    pub fn set_actions(&self, actions: Vec<Action>) {
+        *self.actions.write().unwrap() = actions;
+    }
+
+    /// Queue an event to local file for later upload. In order: events whose action is not
+    /// selected in `actions` are dropped; privacy controls (sampling, app-name generalization,
+    /// timestamp rounding) are applied, so nothing finer-grained ever reaches the disk; then the
+    /// throttle counts the event under its generalized app name, so sampled-out events do not
+    /// use up the budget. The event is linked into the hash chain and fsynced before this
+    /// returns, so a queued event survives a crash.
     pub fn queue_event(&self, event: TelemetryEvent) -> std::io::Result<()> {
         if !self.cfg.enabled {
             log::debug!("Telemetry disabled; not queueing event");
             return Ok(());
         }
 
-        // Ensure directory exists
+        if !self.actions.read().unwrap().contains(&event.action) {
+            log::debug!("Telemetry action {} not selected; not queueing event", event.action);
+            return Ok(());
+        }
+
+        let salt = self.cfg.machine_id.salt.as_deref().unwrap_or("");
+        let Some(event) = self.cfg.privacy.apply(event, salt, &mut self.app_counts.lock().unwrap()) else {
+            log::debug!("Telemetry event sampled out");
+            return Ok(());
+        };
+
+        if !self.admit(&event) {
+            log::debug!("Telemetry event throttled ({} {:?})", event.action, event.secret_type);
+            crate::metrics::global().telemetry_throttled.inc();
+            return Ok(());
+        }
+
+        // Ensure directory exists, private to this user
         if let Some(parent) = self.cfg.queue_file.parent() {
-            std::fs::create_dir_all(parent)?;
+            crate::config::create_private_dir(parent)?;
         }
 
-        // Create file with restrictive permissions when possible
-        #[cfg(unix)]
         {
-            use std::os::unix::fs::OpenOptionsExt;
-            let mut f = OpenOptions::new()
-                .create(true)
-                .append(true)
-                .mode(0o600)
-                .open(&self.cfg.queue_file)?;
+            let _guard = self.io_lock.lock().unwrap();
+            self.append_event(event)?;
 
-            let line = serde_json::to_string(&event)?;
-            writeln!(f, "{}", line)?;
+            // Rotate if file too large
+            self.rotate_locked()?;
+
+            // Prune old and already-delivered events
+            self.prune_locked()?;
+
+            // Keep the queue as a whole under its disk cap
+            self.enforce_cap_locked()?;
         }
 
-        #[cfg(not(unix))]
-        {
-            let mut f = OpenOptions::new().create(true).append(true).open(&self.cfg.queue_file)?;
-            let line = serde_json::to_string(&event)?;
-            writeln!(f, "{}", line)?;
+        *self.pending.lock().unwrap() += 1;
+        self.pending_cv.notify_all();
+        Ok(())
+    }
+
+    /// Append one chained event line and fsync it. Caller holds `io_lock`.
+    fn append_event(&self, mut event: TelemetryEvent) -> std::io::Result<()> {
+        let key = self.chain_key()?;
+        let mut head = self.chain_head();
+        event.prev_hash = head.last_hash.clone();
+        let json = serde_json::to_string(&event)?;
+        let line = match self.cipher()? {
+            Some(c) if self.cfg.encrypt => c.seal(&json, event.prev_hash.as_deref())?,
+            _ => json,
+        };
+
+        let mut f = open_append(&self.cfg.queue_file)?;
+        // A crash mid-append can leave a torn last line; terminate it so it stays a separate
+        // (skipped) line instead of swallowing this event
+        if !ends_with_newline(&self.cfg.queue_file)? {
+            writeln!(f)?;
         }
+        writeln!(f, "{}", line)?;
+        f.sync_data()?;
 
-        // Rotate if file too large
-        self.rotate_if_needed()?;
+        head.last_hash = Some(chain::line_hash(&line));
+        chain::write_head(&self.chain_head_path(), key, head)
+    }
 
-        // Prune old events
-        self.prune_old_events()?;
+    /// Dead-letter file for events the server rejected and queue lines that cannot be read:
+    /// `{queue}.rejected`, one JSON line each with the reason. Sealed like queue records when
+    /// encryption is on.
+    pub fn rejected_file(&self) -> PathBuf {
+        self.cfg.queue_file.with_extension("rejected")
+    }
 
+    /// Append a record to the dead-letter file. Caller holds `io_lock`.
+    fn dead_letter(&self, record: &RejectedRecord<'_>) -> std::io::Result<()> {
+        let json = serde_json::to_string(record)?;
+        let line = match self.cipher()? {
+            Some(c) if self.cfg.encrypt => c.seal(&json, None)?,
+            _ => json,
+        };
+        let mut f = open_append(&self.rejected_file())?;
+        writeln!(f, "{}", line)?;
+        f.sync_data()?;
+        crate::metrics::global().telemetry_rejected.inc();
+        Ok(())
+    }
+
+    /// Move unreadable lines the cursor is about to pass (those ending at or before `end`) to
+    /// the dead-letter file, so they are reported once instead of skipped on every read. Caller
+    /// holds `io_lock`.
+    fn dead_letter_unreadable(&self, segment: &Path, unreadable: &[UnreadableLine], end: u64) -> std::io::Result<()> {
+        for u in unreadable.iter().filter(|u| u.end <= end) {
+            log::warn!("Moving unreadable telemetry queue line in {:?} to the dead-letter file: {}", segment, u.error);
+            self.dead_letter(&RejectedRecord { rejected_at: Utc::now(), error: &u.error, event: None, line: Some(&u.line) })?;
+        }
         Ok(())
     }
 
     /// Attempt a single flush of queued events to the configured telemetry URL.
+    ///
+    /// Events after the committed cursor are uploaded as versioned `EventBatch` envelopes,
+    /// chunked by `max_batch_events` and `max_batch_bytes`, with each event's id serving as the
+    /// server-side idempotency key. The cursor only advances after the server answered, so a
+    /// crash mid-send re-sends rather than loses events (at-least-once). Events the server
+    /// neither accepted nor rejected are re-queued at the tail, and if that was all of them the
+    /// flush fails so the uploader backs off. Rejected ones are moved to the dead-letter file
+    /// (see `rejected_file`), since sending them again would not change the answer; so are queue
+    /// lines that cannot be decrypted or parsed. Rotated segments are flushed oldest-first before
+    /// the active file, and a failure stops the flush so newer events never overtake older ones.
     pub fn flush_once(&self) -> anyhow::Result<()> {
         if !self.cfg.enabled {
             log::debug!("Telemetry disabled; not flushing");
@@ -106,182 +505,980 @@ impl Telemetry {
                 return Ok(());
             }
         };
+        let metrics = crate::metrics::global();
+        let started = Instant::now();
+        metrics.flushes.inc();
+        let result = self.client().and_then(|client| {
+            let mut segments = self.rotated_segments();
+            segments.push(self.cfg.queue_file.clone());
+            for segment in &segments {
+                // Later segments wait until every older one is delivered, preserving order
+                self.flush_segment(&client, &url, segment)?;
+            }
+            Ok(())
+        });
+        metrics.flush_duration.observe(started.elapsed());
+        if result.is_err() {
+            metrics.flush_failures.inc();
+        }
+        result
+    }
 
-        // Read lines and parse JSON values into a vector
-        let f = OpenOptions::new().read(true).open(&self.cfg.queue_file)?;
-        let reader = BufReader::new(f);
-        let mut events: Vec<serde_json::Value> = Vec::new();
-        for line in reader.lines() {
-            let line = line?;
-            if line.trim().is_empty() {
-                continue;
+    /// Upload the undelivered events of one queue segment and commit its cursor.
+    fn flush_segment(&self, client: &Client, url: &str, segment: &Path) -> anyhow::Result<()> {
+        let (read, layout) = {
+            let _guard = self.io_lock.lock().unwrap();
+            let read = match self.read_undelivered(segment)? {
+                Some(r) => r,
+                None => {
+                    log::debug!("Telemetry queue segment {:?} is gone; nothing to flush", segment);
+                    return Ok(());
+                }
+            };
+            if read.events.is_empty() {
+                log::debug!("No telemetry events to flush in {:?}", segment);
+                self.dead_letter_unreadable(segment, &read.unreadable, read.scanned_end)?;
+                self.commit_cursor(segment, read.scanned_end)?;
+                return Ok(());
+            }
+            (read, self.layout.load(Ordering::SeqCst))
+        };
+
+        let events: Vec<TelemetryEvent> = read.events.iter().map(|q| q.event.clone()).collect();
+        let agent_id = self.machine_id();
+        let mut accepted: HashSet<String> = HashSet::new();
+        // Rejected event id -> server's reason
+        let mut rejected: HashMap<String, String> = HashMap::new();
+        let mut sent = 0usize;
+        let mut result = Ok(());
+
+        for chunk in chunk_events(&events, self.cfg.max_batch_events, self.cfg.max_batch_bytes) {
+            let batch = EventBatch {
+                schema_version: SCHEMA_VERSION,
+                agent_id: agent_id.clone(),
+                events: chunk,
+            };
+            match self.send_batch(client, url, &batch) {
+                Ok(ack) => {
+                    accepted.extend(ack.accepted);
+                    rejected.extend(ack.rejected.into_iter().map(|r| (r.id, r.error)));
+                    sent += chunk.len();
+                }
+                Err(e) => {
+                    // Stop at the first failed chunk; later chunks are retried with it
+                    result = Err(e);
+                    break;
+                }
             }
-            let v: serde_json::Value = serde_json::from_str(&line)?;
-            events.push(v);
         }
 
-        if events.is_empty() {
-            log::debug!("No telemetry events to flush");
-            return Ok(());
+        if sent > 0 {
+            let _guard = self.io_lock.lock().unwrap();
+            if self.layout.load(Ordering::SeqCst) != layout {
+                // Rotated, pruned, or capped while sending: the offsets are stale, so leave the
+                // cursor alone and let the next flush re-send (the server dedupes by id)
+                log::debug!("Telemetry queue {:?} changed during upload; cursor not advanced", segment);
+                return result;
+            }
+            let answered = &read.events[..sent];
+            let mut dead: HashSet<&str> = HashSet::new();
+            for q in answered.iter().filter(|q| !accepted.contains(&q.event.event_id)) {
+                let id = q.event.event_id.as_str();
+                match rejected.get(id) {
+                    // A duplicate of an event already dead-lettered is dropped
+                    Some(error) if dead.insert(id) => self.dead_letter(&RejectedRecord {
+                        rejected_at: Utc::now(),
+                        error,
+                        event: Some(&q.event),
+                        line: None,
+                    })?,
+                    Some(_) => {}
+                    None => self.append_event(q.event.clone())?,
+                }
+            }
+            // Everything up to the last answered event is done, including trailing checkpoints
+            let end = if sent == read.events.len() { read.scanned_end } else { answered[sent - 1].end };
+            self.dead_letter_unreadable(segment, &read.unreadable, end)?;
+            self.commit_cursor(segment, end)?;
+            let mut pending = self.pending.lock().unwrap();
+            *pending = pending.saturating_sub(accepted.len() + dead.len());
+            log::info!("Telemetry flushed {} of {} events from {:?}", accepted.len(), events.len(), segment);
+            if accepted.is_empty() && dead.is_empty() && result.is_ok() {
+                // No progress: the events are back at the tail and an immediate retry would
+                // spin, so report a failure and let the uploader back off
+                result = Err(anyhow::anyhow!("Telemetry endpoint acknowledged none of the {} events sent", sent));
+            }
+        }
+        result
+    }
+
+    /// POST one batch and return the server's answer, limited to events in the batch. Rejections
+    /// reported by position are resolved to the event's id.
+    fn send_batch(&self, client: &Client, url: &str, batch: &EventBatch<'_>) -> anyhow::Result<BatchAck> {
+        let sent: Vec<String> = batch.events.iter().map(|e| e.event_id.clone()).collect();
+        let idempotency_key = hex::encode(Sha256::digest(sent.join(",").as_bytes()));
+
+        let body = serde_json::to_vec(batch)?;
+        let credentials = self.credentials();
+        let token = self.bearer_token(client)?;
+
+        let mut resp = self.post_batch(client, url, &idempotency_key, &body, token.as_deref())?;
+        if resp.status() == reqwest::StatusCode::UNAUTHORIZED
+            && self.cfg.api_key.is_none()
+            && let Some(cred) = credentials.load()?
+        {
+            // The server may have revoked the token before it expired; refresh once and retry
+            let cred = credentials.refresh(client, &cred)?;
+            resp = self.post_batch(client, url, &idempotency_key, &body, Some(&cred.access_token))?;
+        }
+        if !resp.status().is_success() {
+            anyhow::bail!("Telemetry endpoint returned non-success status: {}", resp.status());
         }
+        let body = resp.text().unwrap_or_default();
+        Ok(match serde_json::from_str::<BatchAck>(&body) {
+            Ok(mut ack) => {
+                for r in &mut ack.rejected {
+                    if r.id.is_empty()
+                        && let Some(id) = r.index.and_then(|i| sent.get(i))
+                    {
+                        r.id = id.clone();
+                    }
+                    log::warn!("Telemetry event {} rejected by server: {}", r.id, r.error);
+                }
+                ack.accepted.retain(|id| sent.contains(id));
+                ack.rejected.retain(|r| sent.contains(&r.id));
+                ack
+            }
+            // Servers that don't send per-event acks accept the whole batch on 2xx
+            Err(_) => BatchAck { accepted: sent, rejected: Vec::new() },
+        })
+    }
+
+    /// Send one serialized batch, signed with a fresh timestamp and nonce.
+    fn post_batch(
+        &self,
+        client: &Client,
+        url: &str,
+        idempotency_key: &str,
+        body: &[u8],
+        token: Option<&str>,
+    ) -> anyhow::Result<reqwest::blocking::Response> {
+        let mut req = client
+            .post(url)
+            .header("Idempotency-Key", idempotency_key)
+            .header(reqwest::header::CONTENT_TYPE, "application/json");
+        // Fails closed: an agent that cannot sign does not upload unsigned batches
+        let sig = self.signing_key()?.sign_batch(body, Utc::now().timestamp())?;
+        for (name, value) in sig.headers() {
+            req = req.header(name, value);
+        }
+        if let Some(t) = token {
+            req = req.header("Authorization", format!("Bearer {}", t));
+        }
+        Ok(req.body(body.to_vec()).send()?)
+    }
 
-        let client = Client::new();
-        let mut req = client.post(&url).json(&events);
-        if let Some(ref k) = self.cfg.api_key {
-            req = req.header("Authorization", format!("Bearer {}", k));
+    /// Read events after the committed cursor of `segment`. Only complete lines are returned,
+    /// so a line torn by a crash mid-append is left for the next read. Caller holds `io_lock`.
+    fn read_undelivered(&self, segment: &Path) -> std::io::Result<Option<UndeliveredRead>> {
+        let mut f = match OpenOptions::new().read(true).open(segment) {
+            Ok(f) => f,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let len = f.metadata()?.len();
+        let mut offset = read_cursor(segment);
+        if offset > len {
+            log::warn!("Telemetry cursor {} beyond end of {:?}; rescanning from start", offset, segment);
+            offset = 0;
         }
+        f.seek(SeekFrom::Start(offset))?;
 
-        let resp = req.send()?;
-        if resp.status().is_success() {
-            // Truncate the queue file
-            let mut f = File::create(&self.cfg.queue_file)?;
-            f.set_len(0)?;
-            log::info!("Telemetry flushed {} events", events.len());
-        } else {
-            log::warn!("Telemetry endpoint returned non-200: {}", resp.status());
+        let mut reader = BufReader::new(f);
+        let mut events = Vec::new();
+        let mut unreadable = Vec::new();
+        let mut buf = Vec::new();
+        loop {
+            buf.clear();
+            let n = reader.read_until(b'\n', &mut buf)?;
+            if n == 0 || buf.last() != Some(&b'\n') {
+                break;
+            }
+            offset += n as u64;
+            let line = String::from_utf8_lossy(&buf);
+            let line = line.trim();
+            if line.is_empty() || chain::parse_checkpoint(line).is_some() {
+                continue;
+            }
+            let Some(json) = self.open_line(line) else {
+                log::debug!("Skipping telemetry queue line that cannot be decrypted");
+                unreadable.push(UnreadableLine { line: line.to_string(), error: "cannot be decrypted".to_string(), end: offset });
+                continue;
+            };
+            match serde_json::from_str::<TelemetryEvent>(&json) {
+                Ok(event) => events.push(QueuedEvent { event, end: offset }),
+                Err(e) => {
+                    log::debug!("Skipping unparseable telemetry queue line: {}", e);
+                    unreadable.push(UnreadableLine { line: json.into_owned(), error: e.to_string(), end: offset });
+                }
+            }
         }
+        Ok(Some(UndeliveredRead { events, unreadable, scanned_end: offset }))
+    }
 
+    /// Durably advance the cursor of `segment`; once everything is delivered the segment is
+    /// compacted (the active file) or deleted (a rotated segment). The cursor is reset before the
+    /// file shrinks, so a crash in between re-sends delivered events instead of skipping
+    /// undelivered ones. Caller holds `io_lock`.
+    fn commit_cursor(&self, segment: &Path, offset: u64) -> std::io::Result<()> {
+        let len = std::fs::metadata(segment).map(|m| m.len()).unwrap_or(0);
+        if offset < len || segment != self.cfg.queue_file {
+            write_cursor(segment, offset)?;
+            // Drop the delivered prefix (or whole rotated segments) now rather than on the next append
+            return self.prune_locked();
+        }
+        write_cursor(segment, 0)?;
+        self.layout.fetch_add(1, Ordering::SeqCst);
+        let f = OpenOptions::new().write(true).open(segment)?;
+        f.set_len(0)?;
+        f.sync_all()?;
+        // The next queued event continues the chain from the last delivered line
+        let mut head = self.chain_head();
+        head.base_hash = head.last_hash.clone();
+        chain::write_head(&self.chain_head_path(), self.chain_key()?, head)?;
+        *self.pending.lock().unwrap() = 0;
         Ok(())
     }
 
     pub fn make_event(
         &self,
-        secret_type: &str,
-        action: &str,
+        secret_type: Detector,
+        action: Action,
         app_name: Option<String>,
         rule: Option<String>,
     ) -> TelemetryEvent {
         TelemetryEvent {
+            schema_version: SCHEMA_VERSION,
             event_id: Uuid::new_v4().to_string(),
-            timestamp: Utc::now().to_rfc3339(),
-            secret_type: secret_type.to_string(),
-            action: action.to_string(),
+            timestamp: Utc::now(),
+            secret_type: Some(secret_type),
+            action,
             app_name,
             rule,
-            machine_id_hashed: Self::machine_hash(),
+            machine_id_hashed: self.machine_id(),
             agent_version: env!("CARGO_PKG_VERSION").to_string(),
+            heartbeat: None,
+            bypass: None,
+            fingerprint: None,
+            prev_hash: None,
         }
     }
 
-    fn machine_hash() -> Option<String> {
-        if let Ok(name) = hostname::get() {
-            if let Some(s) = name.to_str() {
-                let mut hasher = Sha256::new();
-                hasher.update(s.as_bytes());
-                let res = hasher.finalize();
-                return Some(hex::encode(res));
+    /// A `heartbeat` event carrying `health`.
+    pub fn make_heartbeat(&self, health: Heartbeat) -> TelemetryEvent {
+        TelemetryEvent {
+            schema_version: SCHEMA_VERSION,
+            event_id: Uuid::new_v4().to_string(),
+            timestamp: Utc::now(),
+            secret_type: None,
+            action: Action::Heartbeat,
+            app_name: None,
+            rule: None,
+            machine_id_hashed: self.machine_id(),
+            agent_version: env!("CARGO_PKG_VERSION").to_string(),
+            heartbeat: Some(health),
+            bypass: None,
+            fingerprint: None,
+            prev_hash: None,
+        }
+    }
+
+    /// A `bypass_granted` event for `bypass`; `rule` is `bypass` or `pause`.
+    pub fn make_bypass_event(&self, bypass: &Bypass, rule: &str) -> TelemetryEvent {
+        TelemetryEvent {
+            schema_version: SCHEMA_VERSION,
+            event_id: Uuid::new_v4().to_string(),
+            timestamp: Utc::now(),
+            secret_type: bypass.detector,
+            action: Action::BypassGranted,
+            app_name: bypass.app.clone(),
+            rule: Some(rule.to_string()),
+            machine_id_hashed: self.machine_id(),
+            agent_version: env!("CARGO_PKG_VERSION").to_string(),
+            heartbeat: None,
+            bypass: Some(bypass.grant()),
+            fingerprint: None,
+            prev_hash: None,
+        }
+    }
+
+    /// A `false_positive_reported` event for the blocked finding with this fingerprint.
+    pub fn make_false_positive(&self, secret_type: Detector, app_name: Option<String>, fingerprint: &str) -> TelemetryEvent {
+        TelemetryEvent {
+            fingerprint: Some(fingerprint.to_string()),
+            ..self.make_event(secret_type, Action::FalsePositiveReported, app_name, Some("user-report".to_string()))
+        }
+    }
+
+    /// Throttle check for `queue_event`. Heartbeats and bypass grants are never throttled: the
+    /// former come at a fixed interval, the latter are kept for accountability.
+    fn admit(&self, event: &TelemetryEvent) -> bool {
+        if self.cfg.throttle.max_events == 0 || matches!(event.action, Action::Heartbeat | Action::BypassGranted) {
+            return true;
+        }
+        let now = Instant::now();
+        let mut throttle = self.throttle.lock().unwrap();
+        for ((action, detector, app), n) in throttle.ended(now) {
+            log::warn!(
+                "Dropped {} {} telemetry event(s) (type={}, app={}) over the limit of {} per {}s",
+                n,
+                action,
+                detector.map_or("-", |d| d.as_str()),
+                app.as_deref().unwrap_or("-"),
+                self.cfg.throttle.max_events,
+                self.cfg.throttle.window.as_secs()
+            );
+        }
+        throttle.admit((event.action, event.secret_type, event.app_name.clone()), now)
+    }
+
+    /// Salted, optionally rotating machine identifier (see `machine_id`).
+    pub fn machine_id(&self) -> Option<String> {
+        let source = self.machine_source.as_deref()?;
+        Some(self.cfg.machine_id.derive(source, Utc::now().timestamp()))
+    }
+
+    /// Key store for the queue encryption key: `{queue}.key` (0600), or the OS keyring with
+    /// that file as fallback.
+    fn key_store(&self) -> Box<dyn crypto::KeyStore> {
+        let file = Box::new(crypto::FileKeyStore::new(&self.cfg.queue_file.with_extension("key")));
+        match self.cfg.key_source {
+            KeySource::File => file,
+            KeySource::Keyring => Box::new(crypto::FallbackKeyStore::new(
+                Box::new(crypto::OsKeyring::new("sentinel", "telemetry-queue")),
+                file,
+            )),
+        }
+    }
+
+    /// Key store for the agent signing key: `agent_signing.key` in the state directory (0600),
+    /// or the OS keyring with that file as fallback.
+    fn signing_key_store(&self) -> Box<dyn crypto::KeyStore> {
+        let file = Box::new(crypto::FileKeyStore::new(&self.cfg.state_dir.join("agent_signing.key")));
+        match self.cfg.key_source {
+            KeySource::File => file,
+            KeySource::Keyring => Box::new(crypto::FallbackKeyStore::new(
+                Box::new(crypto::OsKeyring::new("sentinel", "agent-signing-key")),
+                file,
+            )),
+        }
+    }
+
+    /// The agent signing key, generated on first use. Its public key is what the ingest
+    /// server enrolls (`sentinel_pii public-key`).
+    pub fn signing_key(&self) -> std::io::Result<&AgentKey> {
+        self.signing_key.get_or_try_init(|| AgentKey::load_or_create(self.signing_key_store().as_ref()))
+    }
+
+    /// HTTP client for the management and ingest servers, with the configured transport settings.
+    pub fn client(&self) -> anyhow::Result<Client> {
+        crate::transport::build_client(&self.cfg.transport)
+    }
+
+    /// Bearer token for requests to the server: the configured API key, otherwise the enrolled
+    /// device credential (refreshed if it is about to expire). `None` if neither exists.
+    pub fn bearer_token(&self, client: &Client) -> anyhow::Result<Option<String>> {
+        match &self.cfg.api_key {
+            Some(k) => Ok(Some(k.clone())),
+            None => self.credentials().access_token(client),
+        }
+    }
+
+    /// Device credential from `sentinel_pii enroll`: `credential.json` in the state directory.
+    pub fn credentials(&self) -> CredentialStore {
+        CredentialStore::new(&self.cfg.state_dir.join("credential.json"))
+    }
+
+    /// Enroll this device with the management server at `server` using a one-time `code`,
+    /// registering the signing key and storing the issued credential.
+    pub fn enroll(&self, server: &str, code: &str) -> anyhow::Result<Credential> {
+        let client = self.client()?;
+        let cred = crate::enrollment::enroll(&client, server, code, self.signing_key()?, self.machine_id())?;
+        self.credentials().save(&cred)?;
+        log::info!("Enrolled as device {}", cred.device_id);
+        Ok(cred)
+    }
+
+    /// The record cipher; created (with a new key) only when encryption is enabled, so a queue
+    /// written with encryption on stays readable after it is turned off.
+    fn cipher(&self) -> std::io::Result<Option<&QueueCipher>> {
+        self.cipher
+            .get_or_try_init(|| {
+                let store = self.key_store();
+                if self.cfg.encrypt {
+                    QueueCipher::load_or_create(store.as_ref()).map(Some)
+                } else {
+                    QueueCipher::load(store.as_ref())
+                }
+            })
+            .map(|c| c.as_ref())
+    }
+
+    /// Plaintext JSON of a queue line, decrypting sealed records. `None` if it cannot be
+    /// decrypted (no key, wrong key, or tampered).
+    fn open_line<'a>(&self, line: &'a str) -> Option<Cow<'a, str>> {
+        if !crypto::is_sealed(line) {
+            return Some(Cow::Borrowed(line));
+        }
+        match self.cipher() {
+            Ok(Some(c)) => c.open(line).map(Cow::Owned),
+            Ok(None) => None,
+            Err(e) => {
+                log::warn!("Cannot load telemetry queue key: {}", e);
+                None
             }
         }
-        None
     }
 
-    /// Rotate the queue file if it exceeds MAX_QUEUE_BYTES. Rotation renames the current file
+    fn chain_key_path(&self) -> PathBuf {
+        self.cfg.queue_file.with_extension("chainkey")
+    }
+
+    fn chain_head_path(&self) -> PathBuf {
+        self.cfg.queue_file.with_extension("chain")
+    }
+
+    /// Chain signing key: derived from the queue master key when that is kept in the OS
+    /// keyring, otherwise `{queue}.chainkey` (0600).
+    fn chain_key(&self) -> std::io::Result<&chain::ChainKey> {
+        self.chain_key.get_or_try_init(|| match self.cfg.key_source {
+            KeySource::Keyring => {
+                let master = crypto::load_or_create_key(self.key_store().as_ref(), "telemetry queue encryption")?;
+                Ok(chain::ChainKey::from_master_key(&master))
+            }
+            KeySource::File => chain::ChainKey::load_or_create(&self.chain_key_path()),
+        })
+    }
+
+    fn chain_head(&self) -> chain::ChainHead {
+        chain::read_head(&self.chain_head_path()).unwrap_or_default()
+    }
+
+    /// Undelivered events across all segments, oldest first. Lines that cannot be decrypted or
+    /// do not match the event schema are skipped; the next flush moves them to the dead-letter
+    /// file.
+    pub fn queued_events(&self) -> std::io::Result<Vec<TelemetryEvent>> {
+        let _guard = self.io_lock.lock().unwrap();
+        let mut events = Vec::new();
+        for segment in self.rotated_segments().into_iter().chain(std::iter::once(self.cfg.queue_file.clone())) {
+            if let Some(read) = self.read_undelivered(&segment)? {
+                events.extend(read.events.into_iter().map(|q| q.event));
+            }
+        }
+        Ok(events)
+    }
+
+    /// Rotated queue segments (`{queue}.{timestamp}.old`), oldest first.
+    pub fn rotated_segments(&self) -> Vec<PathBuf> {
+        let q = &self.cfg.queue_file;
+        let dir = match q.parent() {
+            Some(d) if !d.as_os_str().is_empty() => d.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let stem = match q.file_stem() {
+            Some(s) => format!("{}.", s.to_string_lossy()),
+            None => return Vec::new(),
+        };
+        let mut out: Vec<PathBuf> = match std::fs::read_dir(&dir) {
+            Ok(rd) => rd
+                .filter_map(|e| e.ok())
+                .map(|e| e.path())
+                .filter(|p| {
+                    p.file_name()
+                        .map(|n| n.to_string_lossy())
+                        .is_some_and(|n| n.starts_with(&stem) && n.ends_with(".old"))
+                })
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        out.sort();
+        out
+    }
+
+    /// Verify the hash chain across rotated segments and the active queue file.
+    pub fn verify_chain(&self) -> std::io::Result<chain::VerifyReport> {
+        chain::verify(&self.rotated_segments(), &self.cfg.queue_file, &self.chain_head_path(), self.chain_key()?)
+    }
+
+    pub fn queue_file(&self) -> &Path {
+        &self.cfg.queue_file
+    }
+
+    pub fn transport(&self) -> &TransportConfig {
+        &self.cfg.transport
+    }
+
+    /// Rotate the queue file if it exceeds `max_queue_bytes`. Rotation renames the current file
     /// to `{queue_file}.{timestamp}.old` and creates a new empty queue file with restrictive
     /// permissions when possible.
     pub fn rotate_if_needed(&self) -> std::io::Result<()> {
-        const MAX_QUEUE_BYTES: u64 = 1_000_000; // 1 MB
+        let _guard = self.io_lock.lock().unwrap();
+        self.rotate_locked()
+    }
 
+    fn rotate_locked(&self) -> std::io::Result<()> {
         let meta = match std::fs::metadata(&self.cfg.queue_file) {
             Ok(m) => m,
             Err(_) => return Ok(()), // nothing to rotate
         };
 
-        if meta.len() <= MAX_QUEUE_BYTES {
+        if meta.len() <= self.cfg.max_queue_bytes {
             return Ok(());
         }
 
-        let ts = Utc::now().format("%Y%m%d%H%M%S").to_string();
+        let key = self.chain_key()?;
+        let mut head = self.chain_head();
+        if self.rotated_segments().is_empty() {
+            head.segments_base = head.base_hash.clone();
+        }
+
+        // Nanoseconds keep names unique and in order when several rotations land in one second
+        let ts = Utc::now().format("%Y%m%d%H%M%S%9f").to_string();
         let mut rotated = self.cfg.queue_file.clone();
         rotated.set_extension(format!("{}.old", ts));
+        // The delivery cursor travels with its segment; moved first so a crash in between
+        // only causes re-sends
+        self.layout.fetch_add(1, Ordering::SeqCst);
+        let cursor = cursor_path(&self.cfg.queue_file);
+        if cursor.exists() {
+            std::fs::rename(&cursor, cursor_path(&rotated))?;
+        }
         std::fs::rename(&self.cfg.queue_file, &rotated)?;
+        crate::metrics::global().rotations.inc();
+
+        // Start the new segment with a signed checkpoint that links back to the rotated one
+        let segment = rotated.file_name().map(|n| n.to_string_lossy().to_string());
+        let cp = chain::checkpoint_line(key, "rotate", head.last_hash.clone(), head.last_hash.clone(), 0, segment);
 
-        // Create new empty file with secure perms
+        // Create new file with secure perms
         #[cfg(unix)]
         {
             use std::os::unix::fs::OpenOptionsExt;
-            OpenOptions::new().create(true).write(true).mode(0o600).open(&self.cfg.queue_file)?;
+            let mut f = OpenOptions::new().create(true).write(true).truncate(true).mode(0o600).open(&self.cfg.queue_file)?;
+            writeln!(f, "{}", cp)?;
         }
         #[cfg(not(unix))]
         {
-            OpenOptions::new().create(true).write(true).open(&self.cfg.queue_file)?;
+            let mut f = OpenOptions::new().create(true).write(true).truncate(true).open(&self.cfg.queue_file)?;
+            writeln!(f, "{}", cp)?;
         }
 
+        head.base_hash = head.last_hash.clone();
+        chain::write_head(&self.chain_head_path(), key, head)?;
+
         log::info!("Rotated telemetry queue to {:?}", rotated);
         Ok(())
     }
 
-    /// Prune events older than MAX_AGE_DAYS from the queue file. This reads all events and
-    /// re-writes only recent events back into the queue file atomically.
+    /// Prune events older than `max_age_days` from the queue, along with events before each
+    /// segment's committed cursor (already delivered). Removed runs are replaced by signed
+    /// checkpoints and the file is rewritten atomically; rotated segments left without events
+    /// are deleted, oldest first.
     pub fn prune_old_events(&self) -> std::io::Result<()> {
-        const MAX_AGE_DAYS: i64 = 30;
-        let cutoff = Utc::now() - chrono::Duration::days(MAX_AGE_DAYS);
+        let _guard = self.io_lock.lock().unwrap();
+        self.prune_locked()
+    }
+
+    fn prune_locked(&self) -> std::io::Result<()> {
+        let cutoff = Utc::now() - chrono::Duration::days(self.cfg.max_age_days.into());
+
+        let mut deletable = true;
+        for segment in self.rotated_segments() {
+            // Most appends leave rotated segments untouched; only rewrite one that has delivered
+            // lines or starts with an expired event
+            let cursor = read_cursor(&segment);
+            let kept = match first_event_time(&segment, |l| self.open_line(l).map(Cow::into_owned))? {
+                None => 0,
+                Some(t) if cursor == 0 && t.is_none_or(|t| t >= cutoff) => 1,
+                Some(_) => self.prune_segment(&segment, cutoff)?,
+            };
+            if kept > 0 {
+                deletable = false;
+            } else if deletable {
+                self.delete_oldest_segment(&segment)?;
+            }
+        }
+        self.prune_segment(&self.cfg.queue_file, cutoff)?;
+        Ok(())
+    }
+
+    /// Drop delivered and expired lines from one segment. Returns the number of events kept.
+    fn prune_segment(&self, segment: &Path, cutoff: chrono::DateTime<Utc>) -> std::io::Result<usize> {
+        let cursor = read_cursor(segment);
+        let (kept, dropped) = self.remove_lines(segment, |line, end| {
+            if end <= cursor {
+                return Some("flush");
+            }
+            // If we can't parse timestamp, keep by default
+            if self.open_line(line).and_then(|l| event_time(&l)).is_some_and(|t| t < cutoff) {
+                return Some("prune");
+            }
+            None
+        })?;
+        if dropped > 0 {
+            log::info!("Pruned telemetry queue {:?}; dropped {} and kept {} events", segment, dropped, kept);
+        }
+        Ok(kept)
+    }
+
+    /// Delete the oldest rotated segment and its cursor. The head's `segments_base` moves to the
+    /// end of the deleted segment so the chain still verifies from the next one. Caller holds
+    /// `io_lock`.
+    fn delete_oldest_segment(&self, segment: &Path) -> std::io::Result<()> {
+        let mut head = self.chain_head();
+        let mut end = head.segments_base.clone();
+        if let Ok(f) = File::open(segment) {
+            for line in BufReader::new(f).lines() {
+                let line = line?;
+                if !line.trim().is_empty() {
+                    end = chain::next_hash(&line);
+                }
+            }
+        }
+        head.segments_base = end;
+        chain::write_head(&self.chain_head_path(), self.chain_key()?, head)?;
+
+        self.layout.fetch_add(1, Ordering::SeqCst);
+        let cursor = cursor_path(segment);
+        if cursor.exists() {
+            std::fs::remove_file(&cursor)?;
+        }
+        std::fs::remove_file(segment)?;
+        log::info!("Removed telemetry queue segment {:?}", segment);
+        Ok(())
+    }
+
+    /// Enforce `max_total_bytes` across rotated segments and the active file by dropping whole
+    /// segments, oldest first, then the oldest lines of the active file (replaced by a "cap"
+    /// checkpoint). Caller holds `io_lock`.
+    fn enforce_cap_locked(&self) -> std::io::Result<()> {
+        let cap = self.cfg.max_total_bytes;
+        let size = |p: &Path| std::fs::metadata(p).map(|m| m.len()).unwrap_or(0);
+        let segments = self.rotated_segments();
+        let mut total: u64 = segments.iter().map(|p| size(p)).sum::<u64>() + size(&self.cfg.queue_file);
+        if total <= cap {
+            return Ok(());
+        }
+
+        for segment in &segments {
+            if total <= cap {
+                return Ok(());
+            }
+            let lost = match self.read_undelivered(segment)? {
+                Some(r) => r.events.len(),
+                None => 0,
+            };
+            total -= size(segment);
+            self.delete_oldest_segment(segment)?;
+            if lost > 0 {
+                log::warn!("Telemetry queue over its {} byte cap; dropped {} undelivered events", cap, lost);
+                let mut pending = self.pending.lock().unwrap();
+                *pending = pending.saturating_sub(lost);
+            }
+        }
+        if total <= cap {
+            return Ok(());
+        }
+
+        // Leave room for the checkpoint line that replaces the dropped run
+        const CHECKPOINT_RESERVE: u64 = 512;
+        let excess = total - cap + CHECKPOINT_RESERVE;
+        let cursor = read_cursor(&self.cfg.queue_file);
+        let mut lost = 0usize;
+        let (_, dropped) = self.remove_lines(&self.cfg.queue_file, |line, end| {
+            if end <= cursor {
+                return Some("flush");
+            }
+            let start = end - line.len() as u64 - 1;
+            if start < excess {
+                if chain::parse_checkpoint(line).is_none() {
+                    lost += 1;
+                }
+                return Some("cap");
+            }
+            None
+        })?;
+        if dropped > 0 && lost > 0 {
+            log::warn!("Telemetry queue over its {} byte cap; dropped {} undelivered events", cap, lost);
+            let mut pending = self.pending.lock().unwrap();
+            *pending = pending.saturating_sub(lost);
+        }
+        Ok(())
+    }
 
-        let f = match OpenOptions::new().read(true).open(&self.cfg.queue_file) {
+    /// Rewrite `segment` without the lines for which `drop(line, end_offset)` returns a reason.
+    /// Each removed run is replaced by a signed checkpoint with that reason so the chain stays
+    /// verifiable. The cursor is reset before the atomic rename (temp file + fsync), since every
+    /// line it covered is removed. Returns (kept events, dropped lines). Caller holds `io_lock`.
+    fn remove_lines(
+        &self,
+        segment: &Path,
+        mut drop: impl FnMut(&str, u64) -> Option<&'static str>,
+    ) -> std::io::Result<(usize, usize)> {
+        let f = match OpenOptions::new().read(true).open(segment) {
             Ok(f) => f,
-            Err(_) => return Ok(()),
+            Err(_) => return Ok((0, 0)),
         };
 
-        let reader = BufReader::new(f);
+        let mut reader = BufReader::new(f);
         let mut keep: Vec<String> = Vec::new();
+        let mut kept_events = 0usize;
+        let mut dropped = 0usize;
+
+        let mut running: Option<Option<String>> = None;
+        // Current removed run: (reason, prev hash, length)
+        let mut run: Option<(&'static str, Option<String>, usize)> = None;
+        let mut offset = 0u64;
+        let mut buf = Vec::new();
 
-        for line in reader.lines() {
-            let line = line?;
+        loop {
+            buf.clear();
+            let n = reader.read_until(b'\n', &mut buf)?;
+            if n == 0 {
+                break;
+            }
+            offset += n as u64;
+            let line = String::from_utf8_lossy(&buf).trim_end_matches(['\n', '\r']).to_string();
             if line.trim().is_empty() {
                 continue;
             }
-            if let Ok(v) = serde_json::from_str::<serde_json::Value>(&line) {
-                if let Some(ts) = v.get("timestamp").and_then(|t| t.as_str()) {
-                    if let Ok(t) = chrono::DateTime::parse_from_rfc3339(ts) {
-                        if t.with_timezone(&Utc) >= cutoff {
-                            keep.push(line);
-                        }
-                        continue;
-                    }
-                }
-            }
-            // If we can't parse timestamp, keep by default
-            keep.push(line);
-        }
+            let before = running.take().unwrap_or_else(|| chain::referenced_hash(&line));
+            running = Some(chain::next_hash(&line));
 
-        // Write back atomically
-        let mut temp = self.cfg.queue_file.clone();
-        temp.set_extension("tmp");
-        {
-            #[cfg(unix)]
+            let reason = drop(&line, offset);
+            if let Some((r, _, len)) = run.as_mut()
+                && reason == Some(*r)
             {
-                use std::os::unix::fs::OpenOptionsExt;
-                let mut f = OpenOptions::new().create(true).write(true).mode(0o600).open(&temp)?;
-                for l in &keep {
-                    writeln!(f, "{}", l)?;
-                }
+                *len += 1;
+                dropped += 1;
+                continue;
             }
-            #[cfg(not(unix))]
-            {
-                let mut f = OpenOptions::new().create(true).write(true).open(&temp)?;
-                for l in &keep {
-                    writeln!(f, "{}", l)?;
-                }
+            if let Some((r, prev, len)) = run.take() {
+                keep.push(chain::checkpoint_line(self.chain_key()?, r, prev, before.clone(), len, None));
             }
+            if let Some(r) = reason {
+                run = Some((r, before, 1));
+                dropped += 1;
+                continue;
+            }
+            if chain::parse_checkpoint(&line).is_none() {
+                kept_events += 1;
+            }
+            keep.push(line);
+        }
+        if dropped == 0 {
+            return Ok((kept_events, 0));
+        }
+        if let Some((r, prev, len)) = run.take() {
+            keep.push(chain::checkpoint_line(self.chain_key()?, r, prev, running.flatten(), len, None));
         }
 
-        std::fs::rename(&temp, &self.cfg.queue_file)?;
-        log::info!("Pruned telemetry queue; kept {} events", keep.len());
+        self.layout.fetch_add(1, Ordering::SeqCst);
+        write_cursor(segment, 0)?;
+        let mut temp = segment.as_os_str().to_owned();
+        temp.push(".tmp");
+        write_lines_atomic(Path::new(&temp), segment, &keep)?;
+        Ok((kept_events, dropped))
+    }
+}
 
-        Ok(())
+/// Open a queue file for appending, creating it with restrictive permissions when possible.
+fn open_append(path: &Path) -> std::io::Result<File> {
+    let mut opts = OpenOptions::new();
+    opts.create(true).append(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        opts.mode(0o600);
+    }
+    opts.open(path)
+}
+
+/// True if the file is empty or its last byte is a newline.
+fn ends_with_newline(path: &Path) -> std::io::Result<bool> {
+    let mut f = File::open(path)?;
+    let len = f.metadata()?.len();
+    if len == 0 {
+        return Ok(true);
+    }
+    f.seek(SeekFrom::Start(len - 1))?;
+    let mut b = [0u8; 1];
+    std::io::Read::read_exact(&mut f, &mut b)?;
+    Ok(b[0] == b'\n')
+}
+
+/// Write `lines` to `temp`, fsync, and rename over `dest`.
+fn write_lines_atomic(temp: &Path, dest: &Path, lines: &[String]) -> std::io::Result<()> {
+    let mut opts = OpenOptions::new();
+    opts.create(true).write(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        opts.mode(0o600);
+    }
+    {
+        let mut f = opts.open(temp)?;
+        for l in lines {
+            writeln!(f, "{}", l)?;
+        }
+        f.sync_all()?;
+    }
+    std::fs::rename(temp, dest)
+}
+
+/// Timestamp of a queued event line, if it has a parseable one.
+fn event_time(line: &str) -> Option<chrono::DateTime<Utc>> {
+    let v = serde_json::from_str::<serde_json::Value>(line).ok()?;
+    let ts = v.get("timestamp")?.as_str()?;
+    chrono::DateTime::parse_from_rfc3339(ts).ok().map(|t| t.with_timezone(&Utc))
+}
+
+/// Timestamp of the first event in `segment`, with `open` turning a queue line into event JSON:
+/// `None` if it holds no events, `Some(None)` if the first event has no readable timestamp.
+fn first_event_time(
+    segment: &Path,
+    open: impl Fn(&str) -> Option<String>,
+) -> std::io::Result<Option<Option<chrono::DateTime<Utc>>>> {
+    let f = match File::open(segment) {
+        Ok(f) => f,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    for line in BufReader::new(f).lines() {
+        let line = line?;
+        if line.trim().is_empty() || chain::parse_checkpoint(&line).is_some() {
+            continue;
+        }
+        return Ok(Some(open(&line).and_then(|l| event_time(&l))));
     }
+    Ok(None)
+}
+
+/// Committed delivery cursor of a queue segment: `{segment}.cursor`.
+fn cursor_path(segment: &Path) -> PathBuf {
+    let mut s = segment.as_os_str().to_owned();
+    s.push(".cursor");
+    PathBuf::from(s)
+}
+
+/// Byte offset in `segment` up to which events have been delivered (0 if none).
+fn read_cursor(segment: &Path) -> u64 {
+    std::fs::read_to_string(cursor_path(segment))
+        .ok()
+        .and_then(|s| s.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+/// Durably record the delivery cursor (temp file + fsync + rename).
+fn write_cursor(segment: &Path, offset: u64) -> std::io::Result<()> {
+    let path = cursor_path(segment);
+    if offset == 0 && !path.exists() {
+        return Ok(());
+    }
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    write_lines_atomic(Path::new(&tmp), &path, &[offset.to_string()])
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /// Telemetry whose queue and install id live in a temp dir.
+    fn telemetry(dir: &Path) -> Telemetry {
+        Telemetry::new(TelemetryConfig { queue_file: dir.join("queue.jsonl"), state_dir: dir.to_path_buf(), ..Default::default() })
+    }
+
     #[test]
     fn create_event_serializes() {
-        let cfg = TelemetryConfig::default();
-        let t = Telemetry::new(cfg);
-        let ev = t.make_event("Stripe", "blocked", Some("Slack".to_string()), Some("denylist-default".to_string()));
+        let dir = tempfile::tempdir().unwrap();
+        let t = telemetry(dir.path());
+        let ev = t.make_event(Detector::Stripe, Action::Blocked, Some("Slack".to_string()), Some("denylist-default".to_string()));
         let s = serde_json::to_string(&ev).unwrap();
         assert!(s.contains("Stripe"));
         assert!(s.contains("blocked"));
+        assert!(s.contains("\"id\":"));
+        // Install-id mode by default: an HMAC, not the raw hostname hash
+        assert_eq!(ev.machine_id_hashed.as_ref().map(|m| m.len()), Some(64));
+        assert!(dir.path().join("install_id").exists());
+
+        // Read once at construction, not per event
+        std::fs::remove_file(dir.path().join("install_id")).unwrap();
+        let again = t.make_event(Detector::Stripe, Action::Blocked, None, None);
+        assert_eq!(again.machine_id_hashed, ev.machine_id_hashed);
+        assert!(!dir.path().join("install_id").exists());
+    }
+
+    #[test]
+    fn hashed_app_names_require_a_salt() {
+        let mut cfg = TelemetryConfig::default();
+        cfg.privacy.app_name = AppNameLevel::Hash;
+        assert!(cfg.validate().unwrap_err().contains("salt"));
+        cfg.machine_id.salt = Some("org-salt".to_string());
+        assert!(cfg.validate().is_ok());
+    }
+
+    #[test]
+    fn events_roundtrip_and_schema_lists_enums() {
+        let dir = tempfile::tempdir().unwrap();
+        let t = telemetry(dir.path());
+        let ev = t.make_event(Detector::Npm, Action::ClipboardWriteFailed, None, None);
+        let s = serde_json::to_string(&ev).unwrap();
+        assert!(s.contains("\"secret_type\":\"npm\""));
+        assert!(s.contains("\"schema_version\":5"));
+        assert!(!s.contains("heartbeat"));
+        let back: TelemetryEvent = serde_json::from_str(&s).unwrap();
+        assert_eq!((back.secret_type, back.action, back.timestamp), (ev.secret_type, ev.action, ev.timestamp));
+
+        // Free-form values no longer parse
+        assert!(serde_json::from_str::<TelemetryEvent>(&s.replace("npm", "Npm")).is_err());
+        let bad_ts = serde_json::to_value(&ev).unwrap();
+        let mut bad_ts = bad_ts.as_object().unwrap().clone();
+        bad_ts.insert("timestamp".to_string(), "t1".into());
+        assert!(serde_json::from_value::<TelemetryEvent>(bad_ts.into()).is_err());
+
+        let schema = json_schema();
+        let defs = &schema["definitions"];
+        assert_eq!(schema["properties"]["schema_version"]["type"], "integer");
+        assert_eq!(defs["Detector"]["enum"], serde_json::json!(["AWS", "Stripe", "GitHub", "npm", "Slack"]));
+        assert_eq!(defs["TelemetryEvent"]["properties"]["timestamp"]["format"], "date-time");
+        assert!(defs["Action"].to_string().contains("clipboard_write_failed"));
+        assert!(defs["Heartbeat"]["properties"]["uptime_secs"].is_object());
+    }
+
+    #[test]
+    fn checked_in_schema_matches_the_schema_command() {
+        // The dashboard's validators are tested against this copy
+        let checked_in: serde_json::Value = serde_json::from_str(include_str!("../dashboard/lib/event-schema.json")).unwrap();
+        assert!(
+            checked_in == json_schema(),
+            "dashboard/lib/event-schema.json is stale; regenerate it with `sentinel_pii schema > dashboard/lib/event-schema.json`"
+        );
+    }
+
+    #[test]
+    fn chunk_events_respects_count_and_bytes() {
+        let dir = tempfile::tempdir().unwrap();
+        let t = telemetry(dir.path());
+        let events: Vec<TelemetryEvent> = (0..5).map(|_| t.make_event(Detector::Aws, Action::Blocked, None, None)).collect();
+        let by_count = chunk_events(&events, 2, usize::MAX);
+        assert_eq!(by_count.iter().map(|c| c.len()).collect::<Vec<_>>(), vec![2, 2, 1]);
+
+        let one = serde_json::to_vec(&events[0]).unwrap().len();
+        let by_bytes = chunk_events(&events, 100, one * 3 + 3);
+        assert_eq!(by_bytes.iter().map(|c| c.len()).collect::<Vec<_>>(), vec![3, 2]);
+
+        // An event larger than the byte cap still gets sent on its own
+        assert_eq!(chunk_events(&events, 100, 1).len(), 5);
     }
 }
@@ -0,0 +1,288 @@
+//! Rate limiting for what the agent emits per detected secret: desktop notifications and
+//! telemetry events. Copying a log full of keys should produce a handful of notifications and
+//! events, not one per clipboard change.
+//!
+//! Notifications get a cooldown per detector and per app, coalesced summaries for what the
+//! cooldown held back ("3 secrets blocked in Slack in the last minute"), and quiet hours.
+//! Telemetry events get at most `max_events` per window for each (action, detector, app).
+
+use crate::scanner::Detector;
+use chrono::NaiveTime;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+/// Default notification cooldown per detector and per app.
+pub const DEFAULT_NOTIFY_COOLDOWN: Duration = Duration::from_secs(60);
+
+/// At most `burst` admitted events per key within `window` of the first one.
+pub struct RateLimiter<K> {
+    window: Duration,
+    burst: u32,
+    keys: HashMap<K, Window>,
+}
+
+struct Window {
+    start: Instant,
+    admitted: u32,
+    suppressed: u32,
+}
+
+impl<K: Hash + Eq + Clone> RateLimiter<K> {
+    pub fn new(window: Duration, burst: u32) -> Self {
+        Self { window, burst, keys: HashMap::new() }
+    }
+
+    /// Whether `admit` would let an event for `key` through, without counting it.
+    pub fn allows(&self, key: &K, now: Instant) -> bool {
+        self.keys
+            .get(key)
+            .is_none_or(|w| now.duration_since(w.start) >= self.window || w.admitted < self.burst)
+    }
+
+    /// Count an event for `key`; returns whether it is let through.
+    pub fn admit(&mut self, key: K, now: Instant) -> bool {
+        let allowed = self.allows(&key, now);
+        let w = self.keys.entry(key).or_insert(Window { start: now, admitted: 0, suppressed: 0 });
+        if now.duration_since(w.start) >= self.window {
+            *w = Window { start: now, admitted: 0, suppressed: 0 };
+        }
+        if allowed {
+            w.admitted += 1;
+        } else {
+            w.suppressed += 1;
+        }
+        allowed
+    }
+
+    /// Forget windows that have ended. Returns the keys that had events suppressed in them,
+    /// with the count.
+    pub fn ended(&mut self, now: Instant) -> Vec<(K, u32)> {
+        let mut ended = Vec::new();
+        self.keys.retain(|k, w| {
+            let live = now.duration_since(w.start) < self.window;
+            if !live && w.suppressed > 0 {
+                ended.push((k.clone(), w.suppressed));
+            }
+            live
+        });
+        ended
+    }
+}
+
+/// Daily local-time range without notifications, `HH:MM-HH:MM`. It may wrap past midnight.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(try_from = "String")]
+pub struct QuietHours {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl QuietHours {
+    pub fn contains(&self, t: NaiveTime) -> bool {
+        if self.start <= self.end { self.start <= t && t < self.end } else { t >= self.start || t < self.end }
+    }
+}
+
+impl std::str::FromStr for QuietHours {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let (start, end) = s.split_once('-').ok_or_else(|| format!("quiet hours must be HH:MM-HH:MM, got '{}'", s))?;
+        let time = |t: &str| NaiveTime::parse_from_str(t.trim(), "%H:%M").map_err(|e| format!("invalid time '{}': {}", t.trim(), e));
+        Ok(QuietHours { start: time(start)?, end: time(end)? })
+    }
+}
+
+impl TryFrom<String> for QuietHours {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, String> {
+        s.parse()
+    }
+}
+
+/// Notification throttling settings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NotifyThrottleConfig {
+    /// After a notification, further blocks of the same detector or in the same app within
+    /// this time are coalesced into one summary.
+    pub cooldown: Duration,
+    pub quiet_hours: Option<QuietHours>,
+}
+
+impl Default for NotifyThrottleConfig {
+    fn default() -> Self {
+        Self { cooldown: DEFAULT_NOTIFY_COOLDOWN, quiet_hours: None }
+    }
+}
+
+/// Blocks held back by the cooldown, shown as one notification.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Summary {
+    pub app: Option<String>,
+    pub count: u32,
+    pub window: Duration,
+}
+
+impl Summary {
+    /// E.g. "3 secrets blocked in Slack in the last minute".
+    pub fn message(&self) -> String {
+        let noun = if self.count == 1 { "secret" } else { "secrets" };
+        let place = self.app.as_deref().map(|a| format!(" in {}", a)).unwrap_or_default();
+        format!("{} {} blocked{} in the last {}", self.count, noun, place, describe(self.window))
+    }
+}
+
+fn describe(d: Duration) -> String {
+    let secs = d.as_secs();
+    match secs {
+        60 => "minute".to_string(),
+        s if s % 60 == 0 => format!("{} minutes", s / 60),
+        1 => "second".to_string(),
+        s => format!("{} seconds", s),
+    }
+}
+
+struct Coalesced {
+    count: u32,
+    since: Instant,
+}
+
+/// Decides which blocks get a notification of their own.
+pub struct NotifyThrottle {
+    cfg: NotifyThrottleConfig,
+    per_detector: RateLimiter<Detector>,
+    per_app: RateLimiter<Option<String>>,
+    coalesced: HashMap<Option<String>, Coalesced>,
+}
+
+impl NotifyThrottle {
+    pub fn new(cfg: NotifyThrottleConfig) -> Self {
+        Self {
+            per_detector: RateLimiter::new(cfg.cooldown, 1),
+            per_app: RateLimiter::new(cfg.cooldown, 1),
+            coalesced: HashMap::new(),
+            cfg,
+        }
+    }
+
+    /// A secret was blocked at `now` (`local` is the local time of day). Returns whether to
+    /// show its notification; otherwise it is counted for a summary, or dropped in quiet hours.
+    pub fn on_block(&mut self, detector: Detector, app: Option<&str>, now: Instant, local: NaiveTime) -> bool {
+        if self.cfg.quiet_hours.is_some_and(|q| q.contains(local)) {
+            return false;
+        }
+        let app = app.map(str::to_string);
+        if self.per_detector.allows(&detector, now) && self.per_app.allows(&app, now) {
+            self.per_detector.admit(detector, now);
+            self.per_app.admit(app, now);
+            return true;
+        }
+        self.coalesced.entry(app).or_insert(Coalesced { count: 0, since: now }).count += 1;
+        false
+    }
+
+    /// Summaries whose cooldown has run out. Pending summaries are dropped in quiet hours.
+    pub fn due(&mut self, now: Instant, local: NaiveTime) -> Vec<Summary> {
+        self.per_detector.ended(now);
+        self.per_app.ended(now);
+        if self.cfg.quiet_hours.is_some_and(|q| q.contains(local)) {
+            self.coalesced.clear();
+            return Vec::new();
+        }
+        let cooldown = self.cfg.cooldown;
+        let mut due = Vec::new();
+        self.coalesced.retain(|app, c| {
+            let ready = now.duration_since(c.since) >= cooldown;
+            if ready {
+                due.push(Summary { app: app.clone(), count: c.count, window: cooldown });
+            }
+            !ready
+        });
+        due
+    }
+}
+
+/// Telemetry event throttling settings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TelemetryThrottleConfig {
+    pub window: Duration,
+    /// Events let through per (action, detector, app) and window; 0 turns throttling off.
+    pub max_events: u32,
+}
+
+impl Default for TelemetryThrottleConfig {
+    fn default() -> Self {
+        Self { window: Duration::from_secs(60), max_events: 20 }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(h: u32, m: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(h, m, 0).unwrap()
+    }
+
+    #[test]
+    fn rate_limiter_windows() {
+        let t0 = Instant::now();
+        let mut rl = RateLimiter::new(Duration::from_secs(10), 2);
+        assert!(rl.admit("a", t0));
+        assert!(rl.admit("a", t0 + Duration::from_secs(1)));
+        assert!(!rl.admit("a", t0 + Duration::from_secs(2)));
+        assert!(rl.admit("b", t0 + Duration::from_secs(2)));
+        assert_eq!(rl.ended(t0 + Duration::from_secs(5)), vec![]);
+        assert_eq!(rl.ended(t0 + Duration::from_secs(10)), vec![("a", 1)]);
+        assert!(rl.admit("a", t0 + Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn quiet_hours_parse_and_wrap() {
+        let q: QuietHours = "22:00-07:30".parse().unwrap();
+        assert!(q.contains(at(23, 0)) && q.contains(at(3, 0)) && !q.contains(at(7, 30)) && !q.contains(at(12, 0)));
+        let day: QuietHours = "12:00-13:00".parse().unwrap();
+        assert!(day.contains(at(12, 30)) && !day.contains(at(13, 0)));
+        assert!("22:00".parse::<QuietHours>().is_err());
+        assert!("25:00-07:00".parse::<QuietHours>().is_err());
+    }
+
+    #[test]
+    fn blocks_are_coalesced_per_app_and_detector() {
+        let t0 = Instant::now();
+        let noon = at(12, 0);
+        let mut th = NotifyThrottle::new(NotifyThrottleConfig::default());
+        assert!(th.on_block(Detector::Aws, Some("Slack"), t0, noon));
+        // Same app, other detector, and same detector, other app, are both held back
+        assert!(!th.on_block(Detector::Stripe, Some("Slack"), t0 + Duration::from_secs(1), noon));
+        assert!(!th.on_block(Detector::Aws, Some("Firefox"), t0 + Duration::from_secs(2), noon));
+        assert!(!th.on_block(Detector::Aws, Some("Slack"), t0 + Duration::from_secs(3), noon));
+        assert!(th.on_block(Detector::GitHub, Some("Terminal"), t0 + Duration::from_secs(4), noon));
+        assert!(th.due(t0 + Duration::from_secs(30), noon).is_empty());
+
+        let mut due = th.due(t0 + Duration::from_secs(62), noon);
+        due.sort_by(|a, b| a.app.cmp(&b.app));
+        assert_eq!(due.len(), 2);
+        assert_eq!(due[0].message(), "1 secret blocked in Firefox in the last minute");
+        assert_eq!(due[1].message(), "2 secrets blocked in Slack in the last minute");
+        assert!(th.on_block(Detector::Aws, Some("Slack"), t0 + Duration::from_secs(62), noon));
+    }
+
+    #[test]
+    fn quiet_hours_silence_notifications_and_summaries() {
+        let t0 = Instant::now();
+        let cfg = NotifyThrottleConfig { cooldown: Duration::from_secs(120), quiet_hours: Some("22:00-07:00".parse().unwrap()) };
+        let mut th = NotifyThrottle::new(cfg);
+        assert!(!th.on_block(Detector::Aws, Some("Slack"), t0, at(23, 0)));
+        assert!(th.on_block(Detector::Aws, Some("Slack"), t0, at(21, 59)));
+        assert!(!th.on_block(Detector::Aws, None, t0 + Duration::from_secs(1), at(21, 59)));
+        assert!(th.due(t0 + Duration::from_secs(121), at(22, 1)).is_empty());
+        assert_eq!(
+            Summary { app: None, count: 4, window: Duration::from_secs(120) }.message(),
+            "4 secrets blocked in the last 2 minutes"
+        );
+    }
+}
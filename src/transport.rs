@@ -0,0 +1,264 @@
+//! HTTP client for the ingest endpoint: custom CA bundle, client certificates (PEM or
+//! PKCS#12), SPKI pinning, and an explicit proxy. Requests to third-party hosts use
+//! `build_public_client`, which keeps only the proxy settings.
+//!
+//! TLS is rustls throughout. Pins are checked inside the handshake, after normal chain
+//! validation, so nothing is sent to a server that fails them.
+
+use anyhow::{Context, bail};
+use base64::Engine;
+use reqwest::blocking::Client;
+use rustls::client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier};
+use rustls::{Certificate, ClientConfig, OwnedTrustAnchor, PrivateKey, RootCertStore, ServerName};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+#[derive(Clone, Debug, Default)]
+pub struct TransportConfig {
+    /// PEM bundle of trusted CAs. When set, it replaces the built-in web roots.
+    pub ca_bundle: Option<PathBuf>,
+    /// PEM client certificate (chain) for mutual TLS, used with `client_key`.
+    pub client_cert: Option<PathBuf>,
+    /// PEM private key (PKCS#8, PKCS#1, or SEC1) for `client_cert`.
+    pub client_key: Option<PathBuf>,
+    /// PKCS#12 bundle with the client key and certificate, instead of `client_cert`/`client_key`.
+    pub client_pkcs12: Option<PathBuf>,
+    pub client_pkcs12_password: Option<String>,
+    /// SHA-256 hashes of acceptable SubjectPublicKeyInfos, as `sha256/<base64>` or hex. The
+    /// connection succeeds only if a certificate the server presents (leaf or intermediate)
+    /// matches; trust anchors that the server does not send cannot be pinned.
+    pub pinned_spki: Vec<String>,
+    /// Proxy URL for all ingest traffic. Without it the `HTTPS_PROXY`/`HTTP_PROXY` environment
+    /// variables apply.
+    pub proxy: Option<String>,
+    /// Comma-separated hosts, domains, and CIDRs that bypass `proxy`. Defaults to `NO_PROXY`.
+    pub no_proxy: Option<String>,
+}
+
+/// Build the blocking client for uploads. Certificates and keys are read on every call, so
+/// renewed files are picked up on the next flush.
+pub fn build_client(cfg: &TransportConfig) -> anyhow::Result<Client> {
+    let mut builder = Client::builder()
+        .use_preconfigured_tls(tls_config(cfg)?)
+        .timeout(Duration::from_secs(30));
+    if let Some(url) = &cfg.proxy {
+        let no_proxy = match &cfg.no_proxy {
+            Some(list) => reqwest::NoProxy::from_string(list),
+            None => reqwest::NoProxy::from_env(),
+        };
+        let proxy = reqwest::Proxy::all(url).with_context(|| format!("invalid proxy URL {}", url))?;
+        builder = builder.proxy(proxy.no_proxy(no_proxy));
+    }
+    Ok(builder.build()?)
+}
+
+/// Build a client for third-party hosts such as provider APIs: built-in web roots, no pins,
+/// and no client identity, so the org's CA and certificate stay with the ingest endpoint. Only
+/// the proxy settings carry over.
+pub fn build_public_client(cfg: &TransportConfig) -> anyhow::Result<Client> {
+    build_client(&TransportConfig { proxy: cfg.proxy.clone(), no_proxy: cfg.no_proxy.clone(), ..Default::default() })
+}
+
+fn tls_config(cfg: &TransportConfig) -> anyhow::Result<ClientConfig> {
+    let mut roots = RootCertStore::empty();
+    match &cfg.ca_bundle {
+        Some(path) => {
+            for cert in read_pem_certs(path)? {
+                roots.add(&cert).with_context(|| format!("invalid CA certificate in {:?}", path))?;
+            }
+            if roots.is_empty() {
+                bail!("no certificates in CA bundle {:?}", path);
+            }
+        }
+        None => roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|ta| {
+            OwnedTrustAnchor::from_subject_spki_name_constraints(ta.subject, ta.spki, ta.name_constraints)
+        })),
+    }
+    let pins = cfg
+        .pinned_spki
+        .iter()
+        .map(|p| parse_pin(p).map_err(anyhow::Error::msg))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let builder = ClientConfig::builder().with_safe_defaults().with_root_certificates(roots.clone());
+    let mut config = match client_identity(cfg)? {
+        Some((chain, key)) => builder.with_client_auth_cert(chain, key).context("invalid client certificate or key")?,
+        None => builder.with_no_client_auth(),
+    };
+    if !pins.is_empty() {
+        let inner = WebPkiVerifier::new(roots, None);
+        config.dangerous().set_certificate_verifier(Arc::new(PinnedVerifier { inner, pins }));
+    }
+    Ok(config)
+}
+
+/// Client certificate chain and key from PEM files or a PKCS#12 bundle.
+fn client_identity(cfg: &TransportConfig) -> anyhow::Result<Option<(Vec<Certificate>, PrivateKey)>> {
+    if let Some(path) = &cfg.client_pkcs12 {
+        let data = std::fs::read(path).with_context(|| format!("reading PKCS#12 bundle {:?}", path))?;
+        let password = cfg.client_pkcs12_password.as_deref().unwrap_or("");
+        let store = p12_keystore::KeyStore::from_pkcs12(&data, password)
+            .map_err(|e| anyhow::anyhow!("cannot open PKCS#12 bundle {:?}: {}", path, e))?;
+        let Some((_, entry)) = store.private_key_chain() else {
+            bail!("PKCS#12 bundle {:?} has no private key", path);
+        };
+        let chain = entry.chain().iter().map(|c| Certificate(c.as_der().to_vec())).collect();
+        return Ok(Some((chain, PrivateKey(entry.key().to_vec()))));
+    }
+    match (&cfg.client_cert, &cfg.client_key) {
+        (None, None) => Ok(None),
+        (Some(cert), Some(key)) => Ok(Some((read_pem_certs(cert)?, read_pem_key(key)?))),
+        _ => bail!("client_cert and client_key must be set together"),
+    }
+}
+
+fn read_pem_certs(path: &Path) -> anyhow::Result<Vec<Certificate>> {
+    let data = std::fs::read(path).with_context(|| format!("reading certificate file {:?}", path))?;
+    let certs = rustls_pemfile::certs(&mut data.as_slice()).with_context(|| format!("parsing PEM in {:?}", path))?;
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+fn read_pem_key(path: &Path) -> anyhow::Result<PrivateKey> {
+    let data = std::fs::read(path).with_context(|| format!("reading key file {:?}", path))?;
+    let mut reader = data.as_slice();
+    while let Some(item) = rustls_pemfile::read_one(&mut reader).with_context(|| format!("parsing PEM in {:?}", path))? {
+        match item {
+            rustls_pemfile::Item::PKCS8Key(k) | rustls_pemfile::Item::RSAKey(k) | rustls_pemfile::Item::ECKey(k) => {
+                return Ok(PrivateKey(k));
+            }
+            _ => {}
+        }
+    }
+    bail!("no private key in {:?}", path)
+}
+
+/// Parse a pin: `sha256/<base64>` (as printed by `openssl ... | base64`), bare base64, or hex.
+pub fn parse_pin(pin: &str) -> Result<[u8; 32], String> {
+    let s = pin.trim();
+    let s = match s.strip_prefix("sha256/") {
+        // Also accept `sha256//<base64>`; a pin's own base64 (44 characters) may start with '/'
+        Some(rest) if rest.len() > 44 => rest.strip_prefix('/').unwrap_or(rest),
+        Some(rest) => rest,
+        None => s,
+    };
+    let raw = if s.len() == 64 && s.bytes().all(|b| b.is_ascii_hexdigit()) {
+        hex::decode(s).map_err(|e| e.to_string())?
+    } else {
+        base64::engine::general_purpose::STANDARD
+            .decode(s)
+            .map_err(|e| format!("invalid SPKI pin '{}': {}", pin, e))?
+    };
+    raw.try_into().map_err(|_| format!("SPKI pin '{}' is not a SHA-256 hash", pin))
+}
+
+/// SHA-256 of the DER SubjectPublicKeyInfo in an X.509 certificate.
+pub fn spki_sha256(cert_der: &[u8]) -> Option<[u8; 32]> {
+    // Certificate ::= SEQUENCE { tbsCertificate SEQUENCE { [0] version OPTIONAL, serialNumber,
+    //   signature, issuer, validity, subject, subjectPublicKeyInfo, ... }, ... }
+    let (_, cert) = der_next(cert_der)?;
+    let mut tbs = der_next(cert.content)?.1.content;
+    if tbs.first() == Some(&0xa0) {
+        tbs = der_next(tbs)?.0;
+    }
+    for _ in 0..5 {
+        tbs = der_next(tbs)?.0;
+    }
+    let (_, spki) = der_next(tbs)?;
+    (spki.tag == 0x30).then(|| Sha256::digest(spki.raw).into())
+}
+
+struct Der<'a> {
+    tag: u8,
+    /// The whole element, header included
+    raw: &'a [u8],
+    content: &'a [u8],
+}
+
+/// Split the first DER element off `data`: `(rest, element)`.
+fn der_next(data: &[u8]) -> Option<(&[u8], Der<'_>)> {
+    let tag = *data.first()?;
+    let first = *data.get(1)? as usize;
+    let (header, len) = if first < 0x80 {
+        (2, first)
+    } else {
+        let n = first & 0x7f;
+        if n == 0 || n > 4 {
+            return None;
+        }
+        let len = data.get(2..2 + n)?.iter().fold(0usize, |acc, b| (acc << 8) | *b as usize);
+        (2 + n, len)
+    };
+    let end = header.checked_add(len)?;
+    let raw = data.get(..end)?;
+    Some((&data[end..], Der { tag, raw, content: &raw[header..] }))
+}
+
+/// Normal WebPKI validation plus a match against the pinned SPKI hashes.
+struct PinnedVerifier {
+    inner: WebPkiVerifier,
+    pins: Vec<[u8; 32]>,
+}
+
+impl ServerCertVerifier for PinnedVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self.inner.verify_server_cert(end_entity, intermediates, server_name, scts, ocsp_response, now)?;
+        let pinned = std::iter::once(end_entity)
+            .chain(intermediates)
+            .filter_map(|c| spki_sha256(&c.0))
+            .any(|h| self.pins.contains(&h));
+        if !pinned {
+            log::error!("Ingest server certificate does not match any pinned SPKI hash");
+            return Err(rustls::Error::General("server certificate does not match SPKI pin".to_string()));
+        }
+        Ok(verified)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spki_hash_matches_the_key() {
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let der = cert.serialize_der().unwrap();
+        let expected: [u8; 32] = Sha256::digest(cert.get_key_pair().public_key_der()).into();
+        assert_eq!(spki_sha256(&der), Some(expected));
+        assert_eq!(spki_sha256(&der[..20]), None);
+
+        let b64 = base64::engine::general_purpose::STANDARD.encode(expected);
+        assert_eq!(parse_pin(&format!("sha256/{}", b64)), Ok(expected));
+        assert_eq!(parse_pin(&format!("sha256//{}", b64)), Ok(expected));
+        assert_eq!(parse_pin(&hex::encode(expected)), Ok(expected));
+        assert!(parse_pin("sha256/AAAA").is_err());
+        // Base64 that itself starts with '/'
+        let slash = base64::engine::general_purpose::STANDARD.encode([0xff; 32]);
+        assert_eq!(parse_pin(&format!("sha256/{}", slash)), Ok([0xff; 32]));
+        assert_eq!(parse_pin(&format!("sha256//{}", slash)), Ok([0xff; 32]));
+    }
+
+    #[test]
+    fn public_client_keeps_only_the_proxy() {
+        let cfg = TransportConfig {
+            ca_bundle: Some(PathBuf::from("/nonexistent/ca.pem")),
+            client_cert: Some(PathBuf::from("/nonexistent/client.pem")),
+            pinned_spki: vec!["not-a-pin".to_string()],
+            proxy: Some("http://proxy.internal:3128".to_string()),
+            ..Default::default()
+        };
+        assert!(build_client(&cfg).is_err());
+        assert!(build_public_client(&cfg).is_ok());
+        let bad_proxy = TransportConfig { proxy: Some("::not a url::".to_string()), ..cfg };
+        assert!(build_public_client(&bad_proxy).is_err());
+    }
+}
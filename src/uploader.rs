@@ -0,0 +1,123 @@
+use crate::telemetry::Telemetry;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Debug)]
+pub struct UploaderConfig {
+    /// Flush at least this often while events are pending.
+    pub interval: Duration,
+    /// Flush early once this many events are queued.
+    pub flush_after_events: usize,
+    /// First retry delay after a failed flush; doubles on each consecutive failure.
+    pub backoff_base: Duration,
+    /// Upper bound for the retry delay.
+    pub backoff_max: Duration,
+}
+
+impl Default for UploaderConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(60),
+            flush_after_events: 20,
+            backoff_base: Duration::from_secs(5),
+            backoff_max: Duration::from_secs(15 * 60),
+        }
+    }
+}
+
+/// How often the uploader re-checks the `running` flag while waiting.
+const SHUTDOWN_POLL: Duration = Duration::from_millis(250);
+
+/// Delay before the next attempt after `failures` consecutive failed flushes.
+///
+/// Exponential (`base * 2^(failures-1)`, capped at `max`) with "equal jitter": half the delay
+/// is fixed and the other half is random, so a fleet that lost its ingest endpoint at the same
+/// moment does not retry in lockstep.
+pub fn backoff_delay(cfg: &UploaderConfig, failures: u32) -> Duration {
+    if failures == 0 {
+        return cfg.interval;
+    }
+    let exp = failures.saturating_sub(1).min(31);
+    let raw = cfg.backoff_base.saturating_mul(1u32 << exp).min(cfg.backoff_max);
+    let half = raw / 2;
+    half + half.mul_f64(crate::random::unit())
+}
+
+/// Spawn the background uploader. It flushes every `interval`, or as soon as
+/// `flush_after_events` events are pending, backs off on failure, and performs a final flush
+/// once `running` is cleared.
+pub fn spawn(telemetry: Arc<Telemetry>, running: Arc<AtomicBool>, cfg: UploaderConfig) -> std::io::Result<JoinHandle<()>> {
+    std::thread::Builder::new()
+        .name("telemetry-uploader".to_string())
+        .spawn(move || run(&telemetry, &running, &cfg))
+}
+
+fn run(telemetry: &Telemetry, running: &AtomicBool, cfg: &UploaderConfig) {
+    let mut failures: u32 = 0;
+
+    while running.load(Ordering::SeqCst) {
+        let delay = backoff_delay(cfg, failures);
+        let deadline = Instant::now() + delay;
+
+        // Wait for the deadline, the batch threshold (only when healthy), or shutdown
+        loop {
+            if !running.load(Ordering::SeqCst) {
+                break;
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            let slice = (deadline - now).min(SHUTDOWN_POLL);
+            if failures == 0 && telemetry.wait_for_pending(cfg.flush_after_events.max(1), slice) {
+                break;
+            } else if failures > 0 {
+                std::thread::sleep(slice);
+            }
+        }
+        if !running.load(Ordering::SeqCst) {
+            break;
+        }
+        match telemetry.flush_once() {
+            Ok(()) => failures = 0,
+            Err(e) => {
+                failures = failures.saturating_add(1);
+                log::warn!(
+                    "Telemetry flush failed (attempt {}), retrying in ~{:?}: {}",
+                    failures,
+                    backoff_delay(cfg, failures),
+                    e
+                );
+            }
+        }
+    }
+
+    log::debug!("Telemetry uploader stopping; final flush");
+    if let Err(e) = telemetry.flush_once() {
+        log::warn!("Final telemetry flush failed; events remain queued: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_grows_and_is_capped() {
+        let cfg = UploaderConfig {
+            interval: Duration::from_secs(60),
+            flush_after_events: 1,
+            backoff_base: Duration::from_secs(2),
+            backoff_max: Duration::from_secs(30),
+        };
+        assert_eq!(backoff_delay(&cfg, 0), cfg.interval);
+        for failures in 1..10 {
+            let raw = Duration::from_secs(2 * (1 << (failures - 1))).min(cfg.backoff_max);
+            let d = backoff_delay(&cfg, failures);
+            assert!(d >= raw / 2 && d <= raw, "failures={} delay={:?}", failures, d);
+        }
+        assert!(backoff_delay(&cfg, 100) <= cfg.backoff_max);
+    }
+}
@@ -0,0 +1,90 @@
+//! The user's own allowlist layer: apps the user chose "Always allow in this app" for. It is
+//! kept apart from the admin config and added on top of the effective allowlist, unless the
+//! bypass policy is off.
+
+use crate::config::create_private_dir;
+use crate::context::matches_app;
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+struct UserAllowlistFile {
+    allowlist: Vec<String>,
+}
+
+pub struct UserAllowlist {
+    path: PathBuf,
+}
+
+impl UserAllowlist {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Apps on the list; empty if the file does not exist.
+    pub fn load(&self) -> anyhow::Result<Vec<String>> {
+        match std::fs::read_to_string(&self.path) {
+            Ok(data) => Ok(serde_json::from_str::<UserAllowlistFile>(&data)?.allowlist),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Add `app` unless an entry already matches it. Returns whether the list changed.
+    pub fn add(&self, app: &str) -> anyhow::Result<bool> {
+        let mut allowlist = self.load()?;
+        if allowlist.iter().any(|a| matches_app(app, a)) {
+            return Ok(false);
+        }
+        allowlist.push(app.to_string());
+        self.write(&UserAllowlistFile { allowlist })?;
+        Ok(true)
+    }
+
+    /// Atomically replace the file (0600).
+    fn write(&self, file: &UserAllowlistFile) -> std::io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            create_private_dir(parent)?;
+        }
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+        {
+            let mut opts = OpenOptions::new();
+            opts.create(true).write(true).truncate(true);
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::OpenOptionsExt;
+                opts.mode(0o600);
+            }
+            let mut f = opts.open(&tmp)?;
+            f.write_all(serde_json::to_string_pretty(file)?.as_bytes())?;
+            f.sync_all()?;
+        }
+        std::fs::rename(&tmp, &self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_are_added_once_and_persist() {
+        let dir = tempfile::tempdir().unwrap();
+        let list = UserAllowlist::new(dir.path().join("user_allowlist.json"));
+        assert!(list.load().unwrap().is_empty());
+        assert!(list.add("Firefox").unwrap());
+        assert!(!list.add("firefox").unwrap());
+        assert!(list.add("Terminal").unwrap());
+        let reopened = UserAllowlist::new(list.path().to_path_buf());
+        assert_eq!(reopened.load().unwrap(), vec!["Firefox".to_string(), "Terminal".to_string()]);
+    }
+}
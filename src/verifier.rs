@@ -0,0 +1,342 @@
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use reqwest::blocking::Client;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::Mutex;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::{Duration, Instant};
+
+use crate::scanner::{Detector, Finding};
+
+type HmacSha256 = Hmac<Sha256>;
+
+// AWS secret access keys are 40 chars of base64-ish alphabet; only used to pair with a key ID
+static AWS_SECRET_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?:^|[^A-Za-z0-9/+])([A-Za-z0-9/+]{40})(?:$|[^A-Za-z0-9/+=])").unwrap());
+
+/// Outcome of a live check against a provider API.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Verification {
+    /// The provider accepted the credential.
+    Active,
+    /// The provider rejected the credential as invalid or revoked.
+    Inactive,
+    /// No conclusion: missing data, rate-limited, network error, or unexpected response.
+    Unknown,
+}
+
+impl std::fmt::Display for Verification {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Verification::Active => "active",
+            Verification::Inactive => "inactive",
+            Verification::Unknown => "unknown",
+        };
+        f.write_str(s)
+    }
+}
+
+/// A live validity check for one detector's credentials.
+pub trait Verifier: Send + Sync {
+    /// Detector this verifier handles, matching `Finding::detector`.
+    fn detector(&self) -> Detector;
+
+    /// Check whether `secret` is live. `context` is the full scanned text, for providers that
+    /// need a companion value (e.g. the AWS secret access key next to a key ID).
+    fn verify(&self, secret: &str, context: &str) -> anyhow::Result<Verification>;
+}
+
+#[derive(Clone, Debug)]
+pub struct VerifierConfig {
+    /// Live checks are opt-in.
+    pub enabled: bool,
+    /// Base URL for AWS STS, e.g. `https://sts.amazonaws.com`.
+    pub aws_sts_url: String,
+    /// Base URL for the Stripe API, e.g. `https://api.stripe.com`.
+    pub stripe_api_url: String,
+    /// Minimum time between two live checks, across all providers.
+    pub min_interval: Duration,
+}
+
+impl Default for VerifierConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            aws_sts_url: "https://sts.amazonaws.com".to_string(),
+            stripe_api_url: "https://api.stripe.com".to_string(),
+            min_interval: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Simple minimum-interval limiter so a clipboard full of keys cannot hammer provider APIs.
+pub struct RateLimiter {
+    min_interval: Duration,
+    last: Mutex<Option<Instant>>,
+}
+
+impl RateLimiter {
+    pub fn new(min_interval: Duration) -> Self {
+        Self { min_interval, last: Mutex::new(None) }
+    }
+
+    /// Returns true and records the call if enough time has passed since the last allowed call.
+    pub fn try_acquire(&self) -> bool {
+        let mut last = self.last.lock().unwrap();
+        let now = Instant::now();
+        match *last {
+            Some(t) if now.duration_since(t) < self.min_interval => false,
+            _ => {
+                *last = Some(now);
+                true
+            }
+        }
+    }
+}
+
+/// Registry of live verifiers, gated by config and a shared rate limiter.
+pub struct LiveVerifier {
+    enabled: bool,
+    verifiers: Vec<Box<dyn Verifier>>,
+    limiter: RateLimiter,
+}
+
+impl LiveVerifier {
+    /// Build the built-in verifiers (AWS STS, Stripe) sharing one HTTP client.
+    pub fn new(cfg: &VerifierConfig, client: Client) -> Self {
+        let verifiers: Vec<Box<dyn Verifier>> = vec![
+            Box::new(AwsStsVerifier::new(client.clone(), &cfg.aws_sts_url)),
+            Box::new(StripeVerifier::new(client, &cfg.stripe_api_url)),
+        ];
+        Self::with_verifiers(cfg, verifiers)
+    }
+
+    pub fn with_verifiers(cfg: &VerifierConfig, verifiers: Vec<Box<dyn Verifier>>) -> Self {
+        Self {
+            enabled: cfg.enabled,
+            verifiers,
+            limiter: RateLimiter::new(cfg.min_interval),
+        }
+    }
+
+    /// Whether live checks are on and one of the verifiers handles `detector`.
+    pub fn supports(&self, detector: Detector) -> bool {
+        self.enabled && self.verifiers.iter().any(|v| v.detector() == detector)
+    }
+
+    /// Run the live check for a finding within `text`. Returns `Unknown` when disabled, when no
+    /// verifier handles the detector, when rate-limited, or when the check itself fails.
+    pub fn verify(&self, finding: &Finding, text: &str) -> Verification {
+        if !self.enabled {
+            return Verification::Unknown;
+        }
+        let Some(v) = self.verifiers.iter().find(|v| v.detector() == finding.detector) else {
+            return Verification::Unknown;
+        };
+        if !self.limiter.try_acquire() {
+            log::debug!("Live verification rate-limited for {}", finding.detector);
+            return Verification::Unknown;
+        }
+        match v.verify(&text[finding.start..finding.end], text) {
+            Ok(r) => r,
+            Err(e) => {
+                log::warn!("Live verification for {} failed: {}", finding.detector, e);
+                Verification::Unknown
+            }
+        }
+    }
+}
+
+/// A finding queued for a live check, with caller data in `tag`.
+pub struct LiveCheck<T> {
+    pub tag: T,
+    pub finding: Finding,
+    pub text: String,
+    /// Filled in by the check; stays `None` when no verifier handles the detector.
+    pub result: Option<Verification>,
+}
+
+/// Sender for queued checks and receiver for finished ones.
+pub type LiveChecks<T> = (Sender<LiveCheck<T>>, Receiver<LiveCheck<T>>);
+
+/// Run live checks on a background thread ("live-verify") so provider round trips never hold
+/// up the clipboard loop. Checks come back in the order they were sent. The thread ends once
+/// the returned sender is dropped and the queued checks are done.
+pub fn spawn<T: Send + 'static>(verifier: LiveVerifier) -> std::io::Result<LiveChecks<T>> {
+    let (check_tx, check_rx) = mpsc::channel::<LiveCheck<T>>();
+    let (result_tx, result_rx) = mpsc::channel();
+    std::thread::Builder::new().name("live-verify".to_string()).spawn(move || {
+        for mut check in check_rx {
+            let detector = check.finding.detector;
+            check.result = verifier.supports(detector).then(|| verifier.verify(&check.finding, &check.text));
+            match check.result {
+                Some(Verification::Active) => log::warn!("Live check: detected {} credential is ACTIVE", detector),
+                Some(live) => log::info!("Live check for {} credential: {}", detector, live),
+                None => {}
+            }
+            if result_tx.send(check).is_err() {
+                break;
+            }
+        }
+    })?;
+    Ok((check_tx, result_rx))
+}
+
+/// Stripe: `GET /v1/balance` with the key as bearer token.
+pub struct StripeVerifier {
+    client: Client,
+    base_url: String,
+}
+
+impl StripeVerifier {
+    pub fn new(client: Client, base_url: &str) -> Self {
+        Self { client, base_url: base_url.trim_end_matches('/').to_string() }
+    }
+}
+
+impl Verifier for StripeVerifier {
+    fn detector(&self) -> Detector {
+        Detector::Stripe
+    }
+
+    fn verify(&self, secret: &str, _context: &str) -> anyhow::Result<Verification> {
+        let resp = self
+            .client
+            .get(format!("{}/v1/balance", self.base_url))
+            .bearer_auth(secret)
+            .send()?;
+        Ok(match resp.status() {
+            s if s.is_success() => Verification::Active,
+            StatusCode::UNAUTHORIZED => Verification::Inactive,
+            s => {
+                log::debug!("Stripe verification returned unexpected status {}", s);
+                Verification::Unknown
+            }
+        })
+    }
+}
+
+/// AWS: SigV4-signed `sts:GetCallerIdentity`. Needs the secret access key, which is looked up
+/// in the surrounding clipboard text; without it the result is `Unknown`.
+pub struct AwsStsVerifier {
+    client: Client,
+    base_url: String,
+    region: String,
+}
+
+impl AwsStsVerifier {
+    pub fn new(client: Client, base_url: &str) -> Self {
+        Self {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            region: "us-east-1".to_string(),
+        }
+    }
+
+    fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+        let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts any key length");
+        mac.update(data.as_bytes());
+        mac.finalize().into_bytes().to_vec()
+    }
+
+    /// Build the SigV4 `Authorization` header value for a POST of `body` to `host`.
+    fn authorization(&self, key_id: &str, secret: &str, host: &str, amz_date: &str, body: &str) -> String {
+        let date = &amz_date[..8];
+        let content_type = "application/x-www-form-urlencoded; charset=utf-8";
+        let signed_headers = "content-type;host;x-amz-date";
+        let canonical = format!(
+            "POST\n/\n\ncontent-type:{}\nhost:{}\nx-amz-date:{}\n\n{}\n{}",
+            content_type,
+            host,
+            amz_date,
+            signed_headers,
+            hex::encode(Sha256::digest(body.as_bytes()))
+        );
+        let scope = format!("{}/{}/sts/aws4_request", date, self.region);
+        let to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex::encode(Sha256::digest(canonical.as_bytes()))
+        );
+        let k_date = Self::hmac(format!("AWS4{}", secret).as_bytes(), date);
+        let k_region = Self::hmac(&k_date, &self.region);
+        let k_service = Self::hmac(&k_region, "sts");
+        let k_signing = Self::hmac(&k_service, "aws4_request");
+        let signature = hex::encode(Self::hmac(&k_signing, &to_sign));
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            key_id, scope, signed_headers, signature
+        )
+    }
+}
+
+impl Verifier for AwsStsVerifier {
+    fn detector(&self) -> Detector {
+        Detector::Aws
+    }
+
+    fn verify(&self, key_id: &str, context: &str) -> anyhow::Result<Verification> {
+        let Some(secret) = AWS_SECRET_RE.captures(context).and_then(|c| c.get(1)) else {
+            log::debug!("No AWS secret access key found next to key ID; skipping live check");
+            return Ok(Verification::Unknown);
+        };
+
+        let url = reqwest::Url::parse(&format!("{}/", self.base_url))?;
+        let host = match (url.host_str(), url.port()) {
+            (Some(h), Some(p)) => format!("{}:{}", h, p),
+            (Some(h), None) => h.to_string(),
+            _ => anyhow::bail!("AWS STS URL has no host: {}", url),
+        };
+        let amz_date = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+        let body = "Action=GetCallerIdentity&Version=2011-06-15";
+        let auth = self.authorization(key_id, secret.as_str(), &host, &amz_date, body);
+
+        let resp = self
+            .client
+            .post(url)
+            .header("Content-Type", "application/x-www-form-urlencoded; charset=utf-8")
+            .header("X-Amz-Date", &amz_date)
+            .header("Authorization", auth)
+            .body(body)
+            .send()?;
+        Ok(match resp.status() {
+            s if s.is_success() => Verification::Active,
+            StatusCode::FORBIDDEN | StatusCode::UNAUTHORIZED => Verification::Inactive,
+            s => {
+                log::debug!("AWS STS verification returned unexpected status {}", s);
+                Verification::Unknown
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rate_limiter_enforces_min_interval() {
+        let l = RateLimiter::new(Duration::from_secs(60));
+        assert!(l.try_acquire());
+        assert!(!l.try_acquire());
+
+        let l = RateLimiter::new(Duration::ZERO);
+        assert!(l.try_acquire());
+        assert!(l.try_acquire());
+    }
+
+    #[test]
+    fn sigv4_authorization_shape() {
+        let v = AwsStsVerifier::new(Client::new(), "https://sts.amazonaws.com");
+        let auth = v.authorization("AKIDEXAMPLE", "secret", "sts.amazonaws.com", "20250101T000000Z", "x");
+        assert!(auth.starts_with("AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20250101/us-east-1/sts/aws4_request"));
+        let sig = auth.rsplit("Signature=").next().unwrap();
+        assert_eq!(sig.len(), 64);
+    }
+}
@@ -0,0 +1,66 @@
+#![cfg(unix)]
+
+use sentinel_pii::bypass::BypassPolicy;
+use sentinel_pii::control::{self, AgentControl};
+use sentinel_pii::heartbeat::Health;
+use sentinel_pii::scanner::Detector;
+use sentinel_pii::telemetry::{Action, Telemetry, TelemetryConfig};
+use serde_json::json;
+use std::sync::Arc;
+use tempfile::tempdir;
+
+#[test]
+fn bypass_is_scoped_reported_and_limited_by_policy() {
+    let dir = tempdir().unwrap();
+    let telemetry = Arc::new(Telemetry::new(TelemetryConfig {
+        queue_file: dir.path().join("tele_queue.jsonl"),
+        state_dir: dir.path().to_path_buf(),
+        enabled: true,
+        ..Default::default()
+    }));
+    let agent = Arc::new(AgentControl::new(Arc::new(Health::new(None)), telemetry.clone()));
+    let socket = dir.path().join("control.sock");
+    control::serve(control::bind(&socket).unwrap(), agent.clone()).unwrap();
+
+    let err = control::call(&socket, "bypass", json!({"minutes": 10, "justification": " "})).unwrap_err();
+    assert!(err.to_string().contains("justification is required"), "{}", err);
+
+    let b = control::call(
+        &socket,
+        "bypass",
+        json!({"minutes": 10, "justification": "AWS console setup", "detector": "AWS", "app": "Firefox"}),
+    )
+    .unwrap();
+    assert_eq!(b["detector"], json!("AWS"));
+    assert!(agent.bypass_covering(Detector::Aws, Some("Firefox")).is_some());
+    assert!(agent.bypass_covering(Detector::Aws, Some("Slack")).is_none());
+    assert!(agent.bypass_covering(Detector::GitHub, Some("Firefox")).is_none());
+    assert_eq!(control::call(&socket, "status", json!(null)).unwrap()["bypasses"].as_array().unwrap().len(), 1);
+
+    let events = telemetry.queued_events().unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].action, Action::BypassGranted);
+    assert_eq!(events[0].secret_type, Some(Detector::Aws));
+    assert_eq!(events[0].app_name.as_deref(), Some("Firefox"));
+    assert_eq!(events[0].rule.as_deref(), Some("bypass"));
+    let grant = events[0].bypass.as_ref().unwrap();
+    assert_eq!(grant.justification, "AWS console setup");
+    assert_eq!(grant.duration_secs, 600);
+
+    control::call(&socket, "pause", json!({"minutes": 5, "justification": "demo"})).unwrap();
+    assert_eq!(telemetry.queued_events().unwrap()[1].rule.as_deref(), Some("pause"));
+    control::call(&socket, "resume", json!(null)).unwrap();
+    assert!(!agent.is_paused());
+    assert!(agent.bypass_covering(Detector::Aws, Some("Firefox")).is_none());
+
+    agent.set_bypass_policy(BypassPolicy { enabled: None, max_minutes: Some(5) });
+    let err = control::call(&socket, "bypass", json!({"minutes": 10, "justification": "x"})).unwrap_err();
+    assert!(err.to_string().contains("at most 5 minutes"), "{}", err);
+
+    control::call(&socket, "pause", json!({"minutes": 5, "justification": "demo"})).unwrap();
+    agent.set_bypass_policy(BypassPolicy { enabled: Some(false), max_minutes: None });
+    assert!(!agent.is_paused());
+    let err = control::call(&socket, "pause", json!({"minutes": 1, "justification": "x"})).unwrap_err();
+    assert!(err.to_string().contains("disabled by policy"), "{}", err);
+    assert_eq!(telemetry.queued_events().unwrap().len(), 3);
+}
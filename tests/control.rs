@@ -0,0 +1,84 @@
+#![cfg(unix)]
+
+use sentinel_pii::control::{self, AgentControl};
+use sentinel_pii::heartbeat::Health;
+use sentinel_pii::telemetry::{Telemetry, TelemetryConfig};
+use serde_json::json;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::sync::Arc;
+use tempfile::tempdir;
+
+fn agent(dir: &Path) -> Arc<AgentControl> {
+    let telemetry = Arc::new(Telemetry::new(TelemetryConfig {
+        queue_file: dir.join("tele_queue.jsonl"),
+        state_dir: dir.to_path_buf(),
+        ..Default::default()
+    }));
+    Arc::new(AgentControl::new(Arc::new(Health::new(None)), telemetry))
+}
+
+#[test]
+fn socket_answers_json_rpc_and_is_private() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("run").join("control.sock");
+    let agent = agent(dir.path());
+    control::serve(control::bind(&path).unwrap(), agent.clone()).unwrap();
+
+    let mode = |p: &Path| std::fs::metadata(p).unwrap().permissions().mode() & 0o777;
+    assert_eq!(mode(&path), 0o600);
+    assert_eq!(mode(path.parent().unwrap()), 0o700);
+
+    let status = control::call(&path, "status", json!(null)).unwrap();
+    assert_eq!(status["pid"], json!(std::process::id()));
+    assert_eq!(status["paused_until"], json!(null));
+
+    control::call(&path, "pause", json!({"minutes": 10, "justification": "demo"})).unwrap();
+    assert!(agent.is_paused());
+    let status = control::call(&path, "status", json!(null)).unwrap();
+    assert!(status["paused_until"].is_string());
+    control::call(&path, "resume", json!(null)).unwrap();
+    assert!(!agent.is_paused());
+
+    assert_eq!(control::call(&path, "recent_events", json!({"limit": 5})).unwrap(), json!([]));
+    let err = control::call(&path, "explain", json!(null)).unwrap_err();
+    assert!(err.to_string().contains("no decisions yet"), "{}", err);
+
+    // Several requests on one connection, and a malformed one
+    let mut stream = std::os::unix::net::UnixStream::connect(&path).unwrap();
+    stream.write_all(b"not json\n{\"jsonrpc\":\"2.0\",\"id\":\"a\",\"method\":\"reload\"}\n").unwrap();
+    let mut lines = BufReader::new(stream).lines();
+    let first: serde_json::Value = serde_json::from_str(&lines.next().unwrap().unwrap()).unwrap();
+    assert_eq!(first["error"]["code"], json!(control::PARSE_ERROR));
+    let second: serde_json::Value = serde_json::from_str(&lines.next().unwrap().unwrap()).unwrap();
+    assert_eq!(second["id"], json!("a"));
+    assert_eq!(second["result"]["reload"], json!("scheduled"));
+    assert!(agent.take_reload());
+}
+
+#[test]
+fn stale_sockets_are_replaced_but_live_ones_are_not() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("control.sock");
+    drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+    assert!(path.exists());
+    let listener = control::bind(&path).unwrap();
+
+    let err = control::bind(&path).err().unwrap();
+    assert_eq!(err.kind(), std::io::ErrorKind::AddrInUse);
+    drop(listener);
+    assert!(!path.exists());
+
+    let file = dir.path().join("not-a-socket");
+    std::fs::write(&file, "keep").unwrap();
+    assert!(control::bind(&file).is_err());
+    assert_eq!(std::fs::read_to_string(&file).unwrap(), "keep");
+}
+
+#[test]
+fn calls_fail_clearly_without_an_agent() {
+    let dir = tempdir().unwrap();
+    let err = control::call(&dir.path().join("control.sock"), "status", json!(null)).unwrap_err();
+    assert!(err.to_string().contains("agent is not running"), "{}", err);
+}
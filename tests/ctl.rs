@@ -0,0 +1,73 @@
+#![cfg(unix)]
+
+use sentinel_pii::audit::AuditRecord;
+use sentinel_pii::control::{self, AgentControl};
+use sentinel_pii::heartbeat::Health;
+use sentinel_pii::scanner::Detector;
+use sentinel_pii::telemetry::{Action, Telemetry, TelemetryConfig};
+use std::path::Path;
+use std::process::{Command, Output};
+use std::sync::Arc;
+use tempfile::tempdir;
+
+fn ctl(dir: &Path, socket: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_sentinel_pii"))
+        .env("XDG_CONFIG_HOME", dir)
+        .arg("--control-socket")
+        .arg(socket)
+        .arg("ctl")
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn stdout(out: &Output) -> String {
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    String::from_utf8(out.stdout.clone()).unwrap()
+}
+
+#[test]
+fn ctl_commands_drive_the_agent() {
+    let dir = tempdir().unwrap();
+    let socket = dir.path().join("control.sock");
+    let telemetry = Arc::new(Telemetry::new(TelemetryConfig {
+        queue_file: dir.path().join("tele_queue.jsonl"),
+        state_dir: dir.path().to_path_buf(),
+        ..Default::default()
+    }));
+    let agent = Arc::new(AgentControl::new(Arc::new(Health::new(None)), telemetry.clone()));
+    control::serve(control::bind(&socket).unwrap(), agent.clone()).unwrap();
+
+    let status = stdout(&ctl(dir.path(), &socket, &["status"]));
+    assert!(status.contains("Scanning:   active"), "{}", status);
+    assert!(status.contains("no decisions yet"), "{}", status);
+
+    assert!(stdout(&ctl(dir.path(), &socket, &["pause", "--minutes", "5", "--reason", "demo"])).starts_with("Scanning paused until "));
+    assert!(agent.is_paused());
+    let json: serde_json::Value = serde_json::from_str(&stdout(&ctl(dir.path(), &socket, &["status", "--json"]))).unwrap();
+    assert!(json["paused_until"].is_string());
+    stdout(&ctl(dir.path(), &socket, &["resume"]));
+    assert!(!agent.is_paused());
+
+    let ev = telemetry.make_event(Detector::Aws, Action::Blocked, Some("Slack".to_string()), Some("denylist-default".to_string()));
+    agent.record(AuditRecord { event: ev, fingerprint: "abcd".to_string(), live: None });
+    agent.set_blocked("AKIA...", Detector::Aws, "abcd");
+    let history = stdout(&ctl(dir.path(), &socket, &["history"]));
+    assert!(history.contains("Slack"), "{}", history);
+    assert!(history.contains("on the denylist"), "{}", history);
+    assert_eq!(stdout(&ctl(dir.path(), &socket, &["restore"])).trim(), "Restored the blocked AWS secret to the clipboard");
+    assert_eq!(agent.take_restore().as_deref(), Some("AKIA..."));
+
+    let out = ctl(dir.path(), &socket, &["restore"]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("nothing to restore"));
+}
+
+#[test]
+fn ctl_reports_a_stopped_agent() {
+    let dir = tempdir().unwrap();
+    let out = ctl(dir.path(), &dir.path().join("control.sock"), &["status"]);
+    assert!(!out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("agent is not running"), "{}", stderr);
+}
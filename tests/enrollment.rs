@@ -0,0 +1,170 @@
+use httpmock::Method::POST;
+use httpmock::MockServer;
+use sentinel_pii::enrollment::{Credential, REFRESH_MARGIN_SECS};
+use sentinel_pii::scanner::Detector;
+use sentinel_pii::telemetry::{Action, Telemetry, TelemetryConfig};
+use std::path::Path;
+use tempfile::tempdir;
+
+fn telemetry(dir: &Path, server: &MockServer, api_key: Option<&str>) -> Telemetry {
+    Telemetry::new(TelemetryConfig {
+        url: Some(format!("{}/events", server.base_url())),
+        api_key: api_key.map(str::to_string),
+        queue_file: dir.join("queue").join("tele_queue.jsonl"),
+        state_dir: dir.join("state"),
+        enabled: true,
+        ..Default::default()
+    })
+}
+
+fn queue_one(tele: &Telemetry) {
+    tele.queue_event(tele.make_event(Detector::Aws, Action::Blocked, None, None)).unwrap();
+}
+
+#[test]
+fn enroll_then_upload_with_device_token() {
+    let server = MockServer::start();
+    let dir = tempdir().unwrap();
+    let tele = telemetry(dir.path(), &server, None);
+    let key_id = tele.signing_key().unwrap().key_id();
+
+    let enroll = server.mock(|when, then| {
+        when.method(POST)
+            .path("/api/agents/enroll")
+            .json_body_partial(format!(r#"{{"code": "ABCD-1234", "key_id": "{}"}}"#, key_id));
+        then.status(200).json_body(serde_json::json!({
+            "device_id": "dev-1", "access_token": "tok-1", "refresh_token": "ref-1", "expires_in": 3600
+        }));
+    });
+    let cred = tele.enroll(&server.base_url(), "ABCD-1234").unwrap();
+    enroll.assert();
+    assert_eq!(cred.device_id, "dev-1");
+    assert!(dir.path().join("state").join("credential.json").exists());
+    assert!(!dir.path().join("queue").join("credential.json").exists());
+    assert_eq!(tele.credentials().load().unwrap(), Some(cred));
+
+    let upload = server.mock(|when, then| {
+        when.method(POST).path("/events").header("Authorization", "Bearer tok-1");
+        then.status(200);
+    });
+    queue_one(&tele);
+    tele.flush_once().unwrap();
+    upload.assert();
+    assert_eq!(tele.pending(), 0);
+}
+
+#[test]
+fn rejected_code_stores_nothing() {
+    let server = MockServer::start();
+    server.mock(|when, then| {
+        when.method(POST).path("/api/agents/enroll");
+        then.status(403).body("code expired");
+    });
+    let dir = tempdir().unwrap();
+    let tele = telemetry(dir.path(), &server, None);
+    let err = tele.enroll(&server.base_url(), "OLD").unwrap_err();
+    assert!(err.to_string().contains("403"), "{}", err);
+    assert_eq!(tele.credentials().load().unwrap(), None);
+}
+
+#[test]
+fn token_is_refreshed_before_expiry() {
+    let server = MockServer::start();
+    let dir = tempdir().unwrap();
+    let tele = telemetry(dir.path(), &server, None);
+    tele.credentials()
+        .save(&Credential {
+            server: server.base_url(),
+            device_id: "dev-1".to_string(),
+            access_token: "old".to_string(),
+            refresh_token: "ref-1".to_string(),
+            expires_at: chrono::Utc::now() + chrono::Duration::seconds(REFRESH_MARGIN_SECS / 2),
+        })
+        .unwrap();
+
+    let refresh = server.mock(|when, then| {
+        when.method(POST)
+            .path("/api/agents/token")
+            .json_body_partial(r#"{"grant_type": "refresh_token", "device_id": "dev-1", "refresh_token": "ref-1"}"#);
+        then.status(200).json_body(serde_json::json!({"device_id": "dev-1", "access_token": "new", "expires_in": 3600}));
+    });
+    let upload = server.mock(|when, then| {
+        when.method(POST).path("/events").header("Authorization", "Bearer new");
+        then.status(200);
+    });
+    queue_one(&tele);
+    tele.flush_once().unwrap();
+    refresh.assert();
+    upload.assert();
+
+    // The refresh token is kept when the server does not rotate it
+    let stored = tele.credentials().load().unwrap().unwrap();
+    assert_eq!(stored.access_token, "new");
+    assert_eq!(stored.refresh_token, "ref-1");
+
+    // Still fresh: the next flush does not refresh again
+    queue_one(&tele);
+    tele.flush_once().unwrap();
+    refresh.assert_hits(1);
+    upload.assert_hits(2);
+}
+
+#[test]
+fn revoked_token_is_refreshed_and_retried_once() {
+    let server = MockServer::start();
+    let dir = tempdir().unwrap();
+    let tele = telemetry(dir.path(), &server, None);
+    tele.credentials()
+        .save(&Credential {
+            server: server.base_url(),
+            device_id: "dev-1".to_string(),
+            access_token: "revoked".to_string(),
+            refresh_token: "ref-1".to_string(),
+            expires_at: chrono::Utc::now() + chrono::Duration::hours(1),
+        })
+        .unwrap();
+
+    let rejected = server.mock(|when, then| {
+        when.method(POST).path("/events").header("Authorization", "Bearer revoked");
+        then.status(401);
+    });
+    let refresh = server.mock(|when, then| {
+        when.method(POST).path("/api/agents/token");
+        then.status(200).json_body(serde_json::json!({
+            "device_id": "dev-1", "access_token": "tok-2", "refresh_token": "ref-2", "expires_in": 3600
+        }));
+    });
+    let accepted = server.mock(|when, then| {
+        when.method(POST).path("/events").header("Authorization", "Bearer tok-2");
+        then.status(200);
+    });
+    queue_one(&tele);
+    tele.flush_once().unwrap();
+    rejected.assert();
+    refresh.assert();
+    accepted.assert();
+    assert_eq!(tele.credentials().load().unwrap().unwrap().refresh_token, "ref-2");
+}
+
+#[test]
+fn api_key_takes_precedence_over_the_credential() {
+    let server = MockServer::start();
+    let dir = tempdir().unwrap();
+    let tele = telemetry(dir.path(), &server, Some("static-key"));
+    tele.credentials()
+        .save(&Credential {
+            server: server.base_url(),
+            device_id: "dev-1".to_string(),
+            access_token: "tok".to_string(),
+            refresh_token: "ref".to_string(),
+            expires_at: chrono::Utc::now(),
+        })
+        .unwrap();
+    let upload = server.mock(|when, then| {
+        when.method(POST).path("/events").header("Authorization", "Bearer static-key");
+        then.status(200);
+    });
+    queue_one(&tele);
+    tele.flush_once().unwrap();
+    upload.assert();
+}
@@ -0,0 +1,63 @@
+use httpmock::Method::POST;
+use httpmock::MockServer;
+use sentinel_pii::heartbeat::{self, ClipboardStatus, Health};
+use sentinel_pii::telemetry::{Action, Telemetry, TelemetryConfig};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tempfile::tempdir;
+
+fn telemetry(dir: &std::path::Path, server: &MockServer, actions: Vec<Action>) -> Arc<Telemetry> {
+    Arc::new(Telemetry::new(TelemetryConfig {
+        url: Some(format!("{}/events", server.base_url())),
+        queue_file: dir.join("tele_queue.jsonl"),
+        state_dir: dir.to_path_buf(),
+        enabled: true,
+        actions,
+        ..Default::default()
+    }))
+}
+
+#[test]
+fn heartbeats_are_queued_and_uploaded() {
+    let server = MockServer::start();
+    let mock = server.mock(|when, then| {
+        when.method(POST)
+            .path("/events")
+            .body_contains(r#""action":"heartbeat""#)
+            .body_contains(r#""clipboard":"failing""#)
+            .body_contains(r#""active_app_supported":"#);
+        then.status(200);
+    });
+    let dir = tempdir().unwrap();
+    let tele = telemetry(dir.path(), &server, Action::ALL.to_vec());
+    let health = Arc::new(Health::new(None));
+    health.set_clipboard_ok(false);
+
+    let running = Arc::new(AtomicBool::new(true));
+    let handle = heartbeat::spawn(tele.clone(), health, running.clone(), Duration::from_secs(3600)).unwrap();
+    assert!(tele.wait_for_pending(1, Duration::from_secs(5)));
+    running.store(false, Ordering::SeqCst);
+    handle.join().unwrap();
+
+    let queued = tele.queued_events().unwrap();
+    assert_eq!(queued.len(), 1);
+    let hb = queued[0].heartbeat.as_ref().unwrap();
+    assert_eq!(queued[0].action, Action::Heartbeat);
+    assert_eq!(queued[0].secret_type, None);
+    assert_eq!(hb.clipboard, ClipboardStatus::Failing);
+    assert!(hb.detector_count > 0);
+
+    tele.flush_once().unwrap();
+    mock.assert();
+    assert_eq!(tele.pending(), 0);
+}
+
+#[test]
+fn heartbeats_follow_the_action_selection() {
+    let server = MockServer::start();
+    let dir = tempdir().unwrap();
+    let tele = telemetry(dir.path(), &server, vec![Action::Blocked]);
+    tele.queue_event(tele.make_heartbeat(Health::new(None).snapshot())).unwrap();
+    assert_eq!(tele.pending(), 0);
+}
@@ -0,0 +1,63 @@
+use sentinel_pii::metrics::{self, parse_samples};
+use sentinel_pii::telemetry::{Telemetry, TelemetryConfig};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tempfile::tempdir;
+
+fn telemetry(dir: &std::path::Path) -> Arc<Telemetry> {
+    Arc::new(Telemetry::new(TelemetryConfig {
+        queue_file: dir.join("tele_queue.jsonl"),
+        state_dir: dir.to_path_buf(),
+        enabled: true,
+        ..Default::default()
+    }))
+}
+
+fn samples(text: &str) -> BTreeMap<String, f64> {
+    parse_samples(text).into_iter().collect()
+}
+
+#[test]
+fn endpoint_serves_prometheus_text() {
+    let dir = tempdir().unwrap();
+    let tele = telemetry(dir.path());
+    let addr = metrics::serve("127.0.0.1:0".parse().unwrap(), tele.clone()).unwrap();
+
+    // Metrics are process-wide, so compare against a baseline rather than absolute values
+    let before = samples(&metrics::scrape(addr).unwrap());
+    metrics::global().scans.inc();
+    metrics::global().scan_duration.observe(Duration::from_micros(20));
+    metrics::global().findings.inc("GitHub");
+    tele.queue_event(tele.make_event(sentinel_pii::scanner::Detector::GitHub, sentinel_pii::telemetry::Action::Blocked, None, None))
+        .unwrap();
+
+    let resp = reqwest::blocking::get(format!("http://{}/metrics", addr)).unwrap();
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.headers()["content-type"], "text/plain; version=0.0.4");
+    let text = resp.text().unwrap();
+    assert!(text.contains("# TYPE sentinel_scans_total counter\n"));
+    assert!(text.contains("# TYPE sentinel_telemetry_flush_duration_seconds histogram\n"));
+    let after = samples(&text);
+    assert!(after["sentinel_scans_total"] > before["sentinel_scans_total"]);
+    assert!(after["sentinel_scan_duration_seconds_count"] > before["sentinel_scan_duration_seconds_count"]);
+    assert!(after["sentinel_findings_total{detector=\"GitHub\"}"] >= 1.0);
+    assert_eq!(after["sentinel_telemetry_queue_depth"], 1.0);
+
+    let resp = reqwest::blocking::get(format!("http://{}/other", addr)).unwrap();
+    assert_eq!(resp.status(), 404);
+}
+
+#[test]
+fn endpoint_is_loopback_only() {
+    let dir = tempdir().unwrap();
+    let err = metrics::serve("0.0.0.0:0".parse().unwrap(), telemetry(dir.path())).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn scrape_explains_a_missing_agent() {
+    let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let err = metrics::scrape(closed).unwrap_err();
+    assert!(err.to_string().contains("--metrics-addr"), "{}", err);
+}
@@ -0,0 +1,124 @@
+use httpmock::Method::GET;
+use httpmock::MockServer;
+use sentinel_pii::policy_sync::{PolicyBundle, PolicySync, PolicySyncConfig, default_policy_url};
+use sentinel_pii::signing::{self, AgentKey};
+use sentinel_pii::telemetry::Action;
+use std::path::Path;
+use std::time::Duration;
+use tempfile::tempdir;
+
+const SERVER_SEED: [u8; 32] = [7u8; 32];
+
+fn config(dir: &Path, server: &MockServer) -> PolicySyncConfig {
+    PolicySyncConfig {
+        url: Some(server.url("/api/agents/policy")),
+        public_key: hex::encode(AgentKey::from_seed(&SERVER_SEED).public_key()),
+        interval: Duration::from_secs(60),
+        cache_file: dir.join("policy_cache.json"),
+    }
+}
+
+fn client() -> reqwest::blocking::Client {
+    reqwest::blocking::Client::new()
+}
+
+const BUNDLE: &str = r#"{"version": "v1", "denylist": ["Discord"], "actions": ["blocked"]}"#;
+
+#[test]
+fn signed_bundle_is_applied_cached_and_revalidated() {
+    let server = MockServer::start();
+    // Mocks match in the order they are defined
+    let revalidate = server.mock(|when, then| {
+        when.method(GET).path("/api/agents/policy").header("If-None-Match", "\"v1\"");
+        then.status(304);
+    });
+    let fetch = server.mock(|when, then| {
+        when.method(GET).path("/api/agents/policy").header("Authorization", "Bearer tok");
+        then.status(200)
+            .header("ETag", "\"v1\"")
+            .header(signing::HEADER_POLICY_SIGNATURE, signing::sign_policy(&SERVER_SEED, BUNDLE.as_bytes()))
+            .body(BUNDLE);
+    });
+    let dir = tempdir().unwrap();
+    let sync = PolicySync::new(config(dir.path(), &server)).unwrap();
+    assert_eq!(sync.current(), None);
+    assert!(sync.fetch_once(&client(), Some("tok")).unwrap());
+    fetch.assert();
+    let bundle = sync.current().unwrap();
+    assert_eq!(bundle.version.as_deref(), Some("v1"));
+    assert_eq!(bundle.denylist, Some(vec!["Discord".to_string()]));
+    assert_eq!(bundle.actions, Some(vec![Action::Blocked]));
+    let generation = sync.generation();
+
+    assert!(!sync.fetch_once(&client(), Some("tok")).unwrap());
+    revalidate.assert();
+    assert_eq!(sync.generation(), generation);
+
+    // The server is gone: a restarted agent keeps the last known-good policy
+    let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let cfg = PolicySyncConfig { url: Some(format!("http://{}/api/agents/policy", closed)), ..config(dir.path(), &server) };
+    let restarted = PolicySync::new(cfg).unwrap();
+    assert_eq!(restarted.current(), Some(bundle));
+    assert!(restarted.fetch_once(&client(), None).is_err());
+    assert!(restarted.current().is_some());
+}
+
+#[test]
+fn bundles_that_fail_verification_are_not_applied() {
+    let server = MockServer::start();
+    let dir = tempdir().unwrap();
+    let mut good = server.mock(|when, then| {
+        when.method(GET).path("/api/agents/policy");
+        then.status(200)
+            .header(signing::HEADER_POLICY_SIGNATURE, signing::sign_policy(&SERVER_SEED, BUNDLE.as_bytes()))
+            .body(BUNDLE);
+    });
+    let sync = PolicySync::new(config(dir.path(), &server)).unwrap();
+    sync.fetch_once(&client(), None).unwrap();
+    good.delete();
+
+    // Signed by another key
+    let forged = r#"{"version": "evil", "denylist": []}"#;
+    let mut mock = server.mock(|when, then| {
+        when.method(GET).path("/api/agents/policy");
+        then.status(200).header(signing::HEADER_POLICY_SIGNATURE, signing::sign_policy(&[1u8; 32], forged.as_bytes())).body(forged);
+    });
+    let err = sync.fetch_once(&client(), None).unwrap_err();
+    assert!(err.to_string().contains("signature"), "{}", err);
+    mock.delete();
+
+    // Unsigned
+    mock = server.mock(|when, then| {
+        when.method(GET).path("/api/agents/policy");
+        then.status(200).body(forged);
+    });
+    assert!(sync.fetch_once(&client(), None).is_err());
+    mock.delete();
+
+    // Correctly signed but not a policy bundle
+    let unknown = r#"{"version": "v2", "rules": []}"#;
+    server.mock(|when, then| {
+        when.method(GET).path("/api/agents/policy");
+        then.status(200).header(signing::HEADER_POLICY_SIGNATURE, signing::sign_policy(&SERVER_SEED, unknown.as_bytes())).body(unknown);
+    });
+    assert!(sync.fetch_once(&client(), None).is_err());
+
+    assert_eq!(sync.current().unwrap().version.as_deref(), Some("v1"));
+    let reloaded = PolicySync::new(config(dir.path(), &server)).unwrap();
+    assert_eq!(reloaded.current().unwrap().version.as_deref(), Some("v1"));
+
+    // A cache edited on disk no longer verifies and is ignored
+    let cache = dir.path().join("policy_cache.json");
+    let edited = std::fs::read_to_string(&cache).unwrap().replace("Discord", "Nothing");
+    std::fs::write(&cache, edited).unwrap();
+    assert_eq!(PolicySync::new(config(dir.path(), &server)).unwrap().current(), None::<PolicyBundle>);
+}
+
+#[test]
+fn policy_url_defaults_to_the_telemetry_server() {
+    assert_eq!(
+        default_policy_url("https://ingest.example.com/api/events").as_deref(),
+        Some("https://ingest.example.com/api/agents/policy")
+    );
+    assert_eq!(default_policy_url("not a url"), None);
+}
@@ -0,0 +1,104 @@
+use chrono::Utc;
+use sentinel_pii::scanner::Detector;
+use sentinel_pii::telemetry::{Action, Telemetry, TelemetryConfig};
+use tempfile::tempdir;
+
+fn telemetry(dir: &std::path::Path) -> Telemetry {
+    Telemetry::new(TelemetryConfig {
+        url: None,
+        api_key: None,
+        queue_file: dir.join("tele_queue.jsonl"),
+        state_dir: dir.to_path_buf(),
+        enabled: true,
+        ..Default::default()
+    })
+}
+
+#[test]
+fn chain_verifies_and_detects_deleted_line() {
+    let dir = tempdir().unwrap();
+    let tele = telemetry(dir.path());
+    for app in ["Slack", "Chrome", "Discord"] {
+        let ev = tele.make_event(Detector::Aws, Action::Blocked, Some(app.to_string()), None);
+        tele.queue_event(ev).unwrap();
+    }
+    assert!(tele.verify_chain().unwrap().is_ok());
+
+    // Silently delete the middle record
+    let data = std::fs::read_to_string(tele.queue_file()).unwrap();
+    let lines: Vec<&str> = data.lines().collect();
+    std::fs::write(tele.queue_file(), format!("{}\n{}\n", lines[0], lines[2])).unwrap();
+
+    let report = tele.verify_chain().unwrap();
+    assert!(!report.is_ok());
+    assert!(report.issues.iter().any(|i| i.line == 2));
+}
+
+#[test]
+fn chain_detects_truncated_tail() {
+    let dir = tempdir().unwrap();
+    let tele = telemetry(dir.path());
+    for _ in 0..2 {
+        tele.queue_event(tele.make_event(Detector::Stripe, Action::Blocked, None, None)).unwrap();
+    }
+    let data = std::fs::read_to_string(tele.queue_file()).unwrap();
+    let first = data.lines().next().unwrap().to_string();
+    std::fs::write(tele.queue_file(), format!("{}\n", first)).unwrap();
+
+    let report = tele.verify_chain().unwrap();
+    assert!(!report.is_ok());
+}
+
+#[test]
+fn prune_replaces_dropped_events_with_signed_checkpoint() {
+    let dir = tempdir().unwrap();
+    let tele = telemetry(dir.path());
+
+    tele.queue_event(tele.make_event(Detector::Aws, Action::Blocked, None, None)).unwrap();
+    let mut old = tele.make_event(Detector::Aws, Action::Blocked, None, None);
+    old.timestamp = Utc::now() - chrono::Duration::days(40);
+    tele.queue_event(old).unwrap();
+    tele.queue_event(tele.make_event(Detector::Aws, Action::Blocked, None, None)).unwrap();
+
+    let data = std::fs::read_to_string(tele.queue_file()).unwrap();
+    assert_eq!(data.lines().count(), 3);
+    assert!(data.lines().nth(1).unwrap().starts_with("{\"checkpoint\""));
+
+    let report = tele.verify_chain().unwrap();
+    assert!(report.is_ok(), "{:?}", report.issues);
+    assert_eq!(report.checkpoints, 1);
+
+    // Tampering with the checkpoint is detected
+    std::fs::write(tele.queue_file(), data.replace("\"dropped\":1", "\"dropped\":0")).unwrap();
+    assert!(!tele.verify_chain().unwrap().is_ok());
+}
+
+#[test]
+fn restart_without_drop_keeps_the_chain_and_catches_a_cut_tail() {
+    let dir = tempdir().unwrap();
+    let queue = |n: usize, tele: &Telemetry| {
+        for _ in 0..n {
+            tele.queue_event(tele.make_event(Detector::Aws, Action::Blocked, None, None)).unwrap();
+        }
+    };
+    // The running agent's threads keep the telemetry alive until the process ends, so nothing
+    // is written on drop
+    let tele = telemetry(dir.path());
+    queue(3, &tele);
+    std::mem::forget(tele);
+
+    // A normal restart appends on top of the previous run without a false alarm
+    let tele = telemetry(dir.path());
+    queue(2, &tele);
+    let report = tele.verify_chain().unwrap();
+    assert!(report.is_ok(), "{:?}", report.issues);
+    std::mem::forget(tele);
+
+    // Events cut off the end while the agent was stopped are detected
+    let data = std::fs::read_to_string(dir.path().join("tele_queue.jsonl")).unwrap();
+    let lines: Vec<&str> = data.lines().collect();
+    assert_eq!(lines.len(), 5);
+    std::fs::write(dir.path().join("tele_queue.jsonl"), format!("{}\n", lines[..3].join("\n"))).unwrap();
+    let report = telemetry(dir.path()).verify_chain().unwrap();
+    assert!(report.issues.iter().any(|i| i.message.contains("truncated")), "{:?}", report.issues);
+}
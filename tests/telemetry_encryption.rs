@@ -0,0 +1,103 @@
+use chrono::Utc;
+use httpmock::Method::POST;
+use httpmock::MockServer;
+use sentinel_pii::scanner::Detector;
+use sentinel_pii::telemetry::{Action, Telemetry, TelemetryConfig};
+use tempfile::tempdir;
+
+fn telemetry(dir: &std::path::Path, url: Option<String>, encrypt: bool) -> Telemetry {
+    Telemetry::new(TelemetryConfig {
+        url,
+        queue_file: dir.join("tele_queue.jsonl"),
+        state_dir: dir.to_path_buf(),
+        enabled: true,
+        max_queue_bytes: 2_000,
+        encrypt,
+        ..Default::default()
+    })
+}
+
+#[test]
+fn encrypted_queue_hides_events_and_still_flushes() {
+    let server = MockServer::start();
+    let mock = server.mock(|when, then| {
+        when.method(POST).path("/events").body_contains("SecretChatApp");
+        then.status(200);
+    });
+    let dir = tempdir().unwrap();
+    let tele = telemetry(dir.path(), Some(format!("{}/events", server.base_url())), true);
+
+    // Enough events to rotate at least once
+    for _ in 0..10 {
+        let ev = tele.make_event(Detector::Aws, Action::Blocked, Some("SecretChatApp".to_string()), None);
+        tele.queue_event(ev).unwrap();
+    }
+    assert!(!tele.rotated_segments().is_empty());
+    for seg in tele.rotated_segments().iter().chain(std::iter::once(&tele.queue_file().to_path_buf())) {
+        let data = std::fs::read_to_string(seg).unwrap();
+        assert!(!data.contains("SecretChatApp"));
+        assert!(!data.contains("blocked"));
+    }
+    let report = tele.verify_chain().unwrap();
+    assert!(report.is_ok(), "{:?}", report.issues);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(dir.path().join("tele_queue.key")).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    tele.flush_once().unwrap();
+    assert!(mock.hits() >= 2);
+    assert!(tele.rotated_segments().is_empty());
+    assert_eq!(std::fs::metadata(tele.queue_file()).unwrap().len(), 0);
+}
+
+#[test]
+fn prune_reads_encrypted_timestamps() {
+    let dir = tempdir().unwrap();
+    let tele = telemetry(dir.path(), None, true);
+
+    let mut old = tele.make_event(Detector::Aws, Action::Blocked, None, None);
+    old.timestamp = Utc::now() - chrono::Duration::days(40);
+    tele.queue_event(old).unwrap();
+    tele.queue_event(tele.make_event(Detector::Aws, Action::Blocked, None, None)).unwrap();
+
+    let data = std::fs::read_to_string(tele.queue_file()).unwrap();
+    let lines: Vec<&str> = data.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].contains("\"reason\":\"prune\""));
+    assert!(lines[1].starts_with("{\"sealed\""));
+    assert!(tele.verify_chain().unwrap().is_ok());
+}
+
+#[test]
+fn tampered_records_are_skipped_and_disabling_keeps_old_ones_readable() {
+    let server = MockServer::start();
+    let mock = server.mock(|when, then| {
+        when.method(POST).path("/events");
+        then.status(200);
+    });
+    let dir = tempdir().unwrap();
+    let url = Some(format!("{}/events", server.base_url()));
+    let tele = telemetry(dir.path(), url.clone(), true);
+    tele.queue_event(tele.make_event(Detector::Aws, Action::Blocked, None, None)).unwrap();
+
+    // Flip a ciphertext byte: authentication fails and the record is skipped, not sent
+    let data = std::fs::read_to_string(tele.queue_file()).unwrap();
+    let ct_at = data.find("\"ct\":\"").unwrap() + 6;
+    let flipped = if &data[ct_at..ct_at + 1] == "0" { "1" } else { "0" };
+    let tampered = format!("{}{}{}", &data[..ct_at], flipped, &data[ct_at + 1..]);
+    std::fs::write(tele.queue_file(), tampered).unwrap();
+    tele.flush_once().unwrap();
+    mock.assert_hits(0);
+
+    // With encryption turned off, existing sealed records stay readable with the stored key
+    let dir2 = tempdir().unwrap();
+    let writer = telemetry(dir2.path(), url.clone(), true);
+    writer.queue_event(writer.make_event(Detector::Aws, Action::Blocked, None, None)).unwrap();
+    let reader = telemetry(dir2.path(), url, false);
+    reader.flush_once().unwrap();
+    mock.assert_hits(1);
+}
@@ -1,10 +1,11 @@
 use httpmock::MockServer;
 use httpmock::Method::POST;
-use httpmock::Mock;
 use std::fs::OpenOptions;
 use std::io::Write;
 use tempfile::tempdir;
-use sentinel_pii::telemetry::{TelemetryConfig, Telemetry, TelemetryEvent};
+use sentinel_pii::scanner::Detector;
+use chrono::Utc;
+use sentinel_pii::telemetry::{Action, TelemetryConfig, Telemetry, TelemetryEvent, SCHEMA_VERSION};
 
 #[test]
 fn telem_flush_posts_and_truncates_queue() {
@@ -22,25 +23,35 @@ fn telem_flush_posts_and_truncates_queue() {
     let mut f = OpenOptions::new().create(true).append(true).open(&qpath).unwrap();
 
     let ev1 = TelemetryEvent {
+        schema_version: SCHEMA_VERSION,
         event_id: "1".to_string(),
-        timestamp: "t1".to_string(),
-        secret_type: "Stripe".to_string(),
-        action: "blocked".to_string(),
+        timestamp: Utc::now(),
+        secret_type: Some(Detector::Stripe),
+        action: Action::Blocked,
         app_name: Some("Slack".to_string()),
         rule: Some("denylist-default".to_string()),
         machine_id_hashed: Some("m1".to_string()),
         agent_version: "0.1.0".to_string(),
+        heartbeat: None,
+        bypass: None,
+        fingerprint: None,
+        prev_hash: None,
     };
 
     let ev2 = TelemetryEvent {
+        schema_version: SCHEMA_VERSION,
         event_id: "2".to_string(),
-        timestamp: "t2".to_string(),
-        secret_type: "AWS".to_string(),
-        action: "blocked".to_string(),
+        timestamp: Utc::now(),
+        secret_type: Some(Detector::Aws),
+        action: Action::Blocked,
         app_name: Some("Chrome".to_string()),
         rule: Some("denylist-default".to_string()),
         machine_id_hashed: Some("m1".to_string()),
         agent_version: "0.1.0".to_string(),
+        heartbeat: None,
+        bypass: None,
+        fingerprint: None,
+        prev_hash: None,
     };
 
     writeln!(f, "{}", serde_json::to_string(&ev1).unwrap()).unwrap();
@@ -51,7 +62,9 @@ fn telem_flush_posts_and_truncates_queue() {
         url: Some(format!("{}/events", server.base_url())),
         api_key: Some("testkey".to_string()),
         queue_file: qpath.clone(),
+        state_dir: dir.path().to_path_buf(),
         enabled: true,
+        ..Default::default()
     };
 
     let tele = Telemetry::new(cfg);
@@ -65,3 +78,208 @@ fn telem_flush_posts_and_truncates_queue() {
     let metadata = std::fs::metadata(&qpath).unwrap();
     assert_eq!(metadata.len(), 0);
 }
+
+#[test]
+fn telem_flush_sends_chunked_envelopes_and_keeps_unacked_events() {
+    let server = MockServer::start();
+    let dir = tempdir().unwrap();
+    let qpath = dir.path().join("tele_queue.jsonl");
+
+    let tele = Telemetry::new(TelemetryConfig {
+        url: Some(format!("{}/events", server.base_url())),
+        queue_file: qpath.clone(),
+        state_dir: dir.path().to_path_buf(),
+        enabled: true,
+        max_batch_events: 2,
+        ..Default::default()
+    });
+    let mut ids = Vec::new();
+    for _ in 0..3 {
+        let ev = tele.make_event(Detector::Aws, Action::Blocked, None, None);
+        ids.push(ev.event_id.clone());
+        tele.queue_event(ev).unwrap();
+    }
+
+    // First chunk: the server accepts only the first of its two events and says nothing of the other
+    let first = server.mock(|when, then| {
+        when.method(POST)
+            .path("/events")
+            .json_body_partial(r#"{"schema_version": 5}"#)
+            .body_contains(ids[0].as_str());
+        then.status(200).json_body(serde_json::json!({"accepted": [ids[0]], "rejected": []}));
+    });
+    // Second chunk: a server without per-event acks accepts everything on 2xx
+    let second = server.mock(|when, then| {
+        when.method(POST).path("/events").body_contains(ids[2].as_str());
+        then.status(201).body("ok");
+    });
+
+    tele.flush_once().expect("flush should succeed");
+    first.assert();
+    second.assert();
+
+    let data = std::fs::read_to_string(&qpath).unwrap();
+    assert!(!data.contains(&ids[0]));
+    assert!(data.contains(&ids[1]));
+    assert!(!data.contains(&ids[2]));
+    assert_eq!(tele.pending(), 1);
+    assert!(tele.verify_chain().unwrap().is_ok());
+}
+
+#[test]
+fn rejected_events_are_dead_lettered_once() {
+    let server = MockServer::start();
+    let dir = tempdir().unwrap();
+    let tele = Telemetry::new(TelemetryConfig {
+        url: Some(format!("{}/events", server.base_url())),
+        queue_file: dir.path().join("tele_queue.jsonl"),
+        state_dir: dir.path().to_path_buf(),
+        enabled: true,
+        ..Default::default()
+    });
+    let ok = tele.make_event(Detector::Aws, Action::Blocked, None, None);
+    let bad = tele.make_event(Detector::Aws, Action::Blocked, None, None);
+    let unnamed = tele.make_event(Detector::Stripe, Action::Blocked, None, None);
+    for ev in [&ok, &bad, &unnamed] {
+        tele.queue_event(ev.clone()).unwrap();
+    }
+    // The same id is rejected twice, and the third event only by its position in the batch
+    let mock = server.mock(|when, then| {
+        when.method(POST).path("/events");
+        then.status(200).json_body(serde_json::json!({
+            "accepted": [ok.event_id],
+            "rejected": [
+                {"id": bad.event_id, "error": "invalid event"},
+                {"id": bad.event_id, "error": "invalid event"},
+                {"index": 2, "error": "missing id"},
+            ],
+        }));
+    });
+
+    let before = sentinel_pii::metrics::global().telemetry_rejected.get();
+    tele.flush_once().unwrap();
+    tele.flush_once().unwrap();
+    // Nothing was re-queued, so the second flush had nothing to send
+    mock.assert_hits(1);
+    let queued = std::fs::read_to_string(tele.queue_file()).unwrap();
+    assert!(!queued.contains(&bad.event_id) && !queued.contains(&unnamed.event_id));
+    assert_eq!(tele.pending(), 0);
+
+    let dead = std::fs::read_to_string(tele.rejected_file()).unwrap();
+    let lines: Vec<serde_json::Value> = dead.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0]["event"]["id"], bad.event_id.as_str());
+    assert_eq!(lines[0]["error"], "invalid event");
+    assert_eq!(lines[1]["event"]["id"], unnamed.event_id.as_str());
+    assert!(sentinel_pii::metrics::global().telemetry_rejected.get() >= before + 2);
+    assert!(tele.verify_chain().unwrap().is_ok());
+}
+
+#[test]
+fn privacy_controls_apply_before_queueing() {
+    use sentinel_pii::privacy::{AppNameLevel, PrivacyConfig};
+
+    let dir = tempdir().unwrap();
+    let tele = Telemetry::new(TelemetryConfig {
+        queue_file: dir.path().join("tele_queue.jsonl"),
+        state_dir: dir.path().to_path_buf(),
+        enabled: true,
+        privacy: PrivacyConfig {
+            app_name: AppNameLevel::Category,
+            sample_rates: [(Action::DetectedButSkipped, 0.0)].into(),
+            timestamp_granularity_secs: 3600,
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+
+    tele.queue_event(tele.make_event(Detector::Aws, Action::Blocked, Some("Notes - salaries.txt - Slack".to_string()), None)).unwrap();
+    tele.queue_event(tele.make_event(Detector::Aws, Action::DetectedButSkipped, Some("Slack".to_string()), None)).unwrap();
+
+    let data = std::fs::read_to_string(tele.queue_file()).unwrap();
+    assert_eq!(data.lines().count(), 1);
+    assert!(!data.contains("salaries"));
+    let ev: TelemetryEvent = serde_json::from_str(data.lines().next().unwrap()).unwrap();
+    assert_eq!(ev.app_name.as_deref(), Some("chat"));
+    assert_eq!(ev.timestamp.timestamp() % 3600, 0);
+}
+
+#[test]
+fn only_selected_actions_are_queued() {
+    let dir = tempdir().unwrap();
+    let tele = Telemetry::new(TelemetryConfig {
+        queue_file: dir.path().join("tele_queue.jsonl"),
+        state_dir: dir.path().to_path_buf(),
+        enabled: true,
+        actions: vec![Action::Blocked, Action::ClipboardWriteFailed],
+        ..Default::default()
+    });
+
+    for action in Action::ALL {
+        tele.queue_event(tele.make_event(Detector::Aws, action, None, None)).unwrap();
+    }
+
+    let data = std::fs::read_to_string(tele.queue_file()).unwrap();
+    let actions: Vec<Action> = data
+        .lines()
+        .map(|l| serde_json::from_str::<TelemetryEvent>(l).unwrap().action)
+        .collect();
+    assert_eq!(actions, vec![Action::Blocked, Action::ClipboardWriteFailed]);
+}
+
+#[test]
+fn queue_lines_that_do_not_match_the_schema_are_skipped() {
+    let server = MockServer::start();
+    let mock = server.mock(|when, then| {
+        when.method(POST).path("/events").body_contains("\"id\":\"good\"");
+        then.status(200);
+    });
+    let dir = tempdir().unwrap();
+    let qpath = dir.path().join("tele_queue.jsonl");
+
+    let mut good = serde_json::to_value(TelemetryEvent {
+        schema_version: SCHEMA_VERSION,
+        event_id: "good".to_string(),
+        timestamp: Utc::now(),
+        secret_type: Some(Detector::Aws),
+        action: Action::Blocked,
+        app_name: None,
+        rule: None,
+        machine_id_hashed: None,
+        agent_version: "0.1.0".to_string(),
+        heartbeat: None,
+        bypass: None,
+        fingerprint: None,
+        prev_hash: None,
+    })
+    .unwrap();
+    // Written before events carried a schema version
+    good.as_object_mut().unwrap().remove("schema_version");
+    let mut bad = good.clone();
+    bad["id"] = "bad".into();
+    bad["timestamp"] = "t1".into();
+    std::fs::write(&qpath, format!("{}\n{}\n", bad, good)).unwrap();
+
+    let tele = Telemetry::new(TelemetryConfig {
+        url: Some(format!("{}/events", server.base_url())),
+        queue_file: qpath.clone(),
+        state_dir: dir.path().to_path_buf(),
+        enabled: true,
+        ..Default::default()
+    });
+    let queued = tele.queued_events().unwrap();
+    assert_eq!(queued.len(), 1);
+    assert_eq!(queued[0].event_id, "good");
+    assert_eq!(queued[0].schema_version, SCHEMA_VERSION);
+
+    tele.flush_once().unwrap();
+    mock.assert();
+
+    // The bad line is moved to the dead-letter file once instead of being skipped forever
+    tele.flush_once().unwrap();
+    let dead = std::fs::read_to_string(tele.rejected_file()).unwrap();
+    let lines: Vec<serde_json::Value> = dead.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    assert_eq!(lines.len(), 1);
+    assert!(lines[0]["line"].as_str().unwrap().contains("\"id\":\"bad\""));
+    assert!(lines[0]["event"].is_null());
+}
@@ -1,4 +1,5 @@
-use sentinel_pii::telemetry::{TelemetryConfig, Telemetry, TelemetryEvent};
+use sentinel_pii::scanner::Detector;
+use sentinel_pii::telemetry::{Action, TelemetryConfig, Telemetry, TelemetryEvent, SCHEMA_VERSION};
 use tempfile::tempdir;
 use std::fs::OpenOptions;
 use std::io::Write;
@@ -10,29 +11,39 @@ fn prune_old_events_removes_old() {
     let qpath = dir.path().join("tele_queue.jsonl");
 
     // Create two events: one old, one recent
-    let old_ts = (Utc::now() - chrono::Duration::days(40)).to_rfc3339();
-    let recent_ts = Utc::now().to_rfc3339();
+    let old_ts = Utc::now() - chrono::Duration::days(40);
+    let recent_ts = Utc::now();
 
     let ev_old = TelemetryEvent {
+        schema_version: SCHEMA_VERSION,
         event_id: "old".to_string(),
-        timestamp: old_ts.clone(),
-        secret_type: "Stripe".to_string(),
-        action: "blocked".to_string(),
+        timestamp: old_ts,
+        secret_type: Some(Detector::Stripe),
+        action: Action::Blocked,
         app_name: Some("Slack".to_string()),
         rule: Some("denylist-default".to_string()),
         machine_id_hashed: Some("m1".to_string()),
         agent_version: "0.1.0".to_string(),
+        heartbeat: None,
+        bypass: None,
+        fingerprint: None,
+        prev_hash: None,
     };
 
     let ev_recent = TelemetryEvent {
+        schema_version: SCHEMA_VERSION,
         event_id: "recent".to_string(),
-        timestamp: recent_ts.clone(),
-        secret_type: "AWS".to_string(),
-        action: "blocked".to_string(),
+        timestamp: recent_ts,
+        secret_type: Some(Detector::Aws),
+        action: Action::Blocked,
         app_name: Some("Chrome".to_string()),
         rule: Some("denylist-default".to_string()),
         machine_id_hashed: Some("m1".to_string()),
         agent_version: "0.1.0".to_string(),
+        heartbeat: None,
+        bypass: None,
+        fingerprint: None,
+        prev_hash: None,
     };
 
     let mut f = OpenOptions::new().create(true).append(true).open(&qpath).unwrap();
@@ -40,7 +51,14 @@ fn prune_old_events_removes_old() {
     writeln!(f, "{}", serde_json::to_string(&ev_recent).unwrap()).unwrap();
     drop(f);
 
-    let cfg = TelemetryConfig { url: None, api_key: None, queue_file: qpath.clone(), enabled: true };
+    let cfg = TelemetryConfig {
+        url: None,
+        api_key: None,
+        queue_file: qpath.clone(),
+        state_dir: dir.path().to_path_buf(),
+        enabled: true,
+        ..Default::default()
+    };
     let tele = Telemetry::new(cfg);
 
     tele.prune_old_events().unwrap();
@@ -49,3 +67,32 @@ fn prune_old_events_removes_old() {
     assert!(data.contains("recent"));
     assert!(!data.contains("old"));
 }
+
+#[test]
+fn prune_honors_configured_max_age() {
+    let dir = tempdir().unwrap();
+    let cfg = TelemetryConfig {
+        queue_file: dir.path().join("state").join("tele_queue.jsonl"),
+        state_dir: dir.path().join("state"),
+        enabled: true,
+        max_age_days: 1,
+        ..Default::default()
+    };
+    let tele = Telemetry::new(cfg);
+
+    let mut ev = tele.make_event(Detector::Aws, Action::Blocked, None, None);
+    ev.event_id = "two-days".to_string();
+    ev.timestamp = Utc::now() - chrono::Duration::days(2);
+    tele.queue_event(ev).unwrap();
+    tele.queue_event(tele.make_event(Detector::Aws, Action::Blocked, None, None)).unwrap();
+
+    let data = std::fs::read_to_string(tele.queue_file()).unwrap();
+    assert!(!data.contains("two-days"));
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(dir.path().join("state")).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+    }
+}
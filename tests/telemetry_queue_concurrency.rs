@@ -0,0 +1,133 @@
+use sentinel_pii::scanner::Detector;
+use sentinel_pii::telemetry::{Action, Telemetry, TelemetryConfig};
+use sentinel_pii::throttle::TelemetryThrottleConfig;
+use std::collections::HashSet;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tempfile::tempdir;
+
+/// Minimal ingest server that records every received event id and acks all of them.
+fn start_ingest_server(received: Arc<Mutex<Vec<String>>>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut content_length = 0usize;
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap_or(0) == 0 || line == "\r\n" {
+                    break;
+                }
+                if let Some(v) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                    content_length = v.trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0u8; content_length];
+            reader.read_exact(&mut body).unwrap();
+            let batch: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            let ids: Vec<String> = batch["events"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|e| e["id"].as_str().unwrap().to_string())
+                .collect();
+            received.lock().unwrap().extend(ids.iter().cloned());
+
+            let ack = serde_json::json!({ "accepted": ids }).to_string();
+            let resp = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                ack.len(),
+                ack
+            );
+            let _ = stream.write_all(resp.as_bytes());
+        }
+    });
+    format!("http://{}/events", addr)
+}
+
+#[test]
+fn concurrent_producer_and_flusher_deliver_every_event() {
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let url = start_ingest_server(received.clone());
+    let dir = tempdir().unwrap();
+    let tele = Arc::new(Telemetry::new(TelemetryConfig {
+        url: Some(url),
+        queue_file: dir.path().join("tele_queue.jsonl"),
+        state_dir: dir.path().to_path_buf(),
+        enabled: true,
+        max_batch_events: 7,
+        // Identical events on purpose; the throttle would drop most of them
+        throttle: TelemetryThrottleConfig { max_events: 0, ..Default::default() },
+        ..Default::default()
+    }));
+
+    let done = Arc::new(AtomicBool::new(false));
+    let producer = {
+        let tele = tele.clone();
+        thread::spawn(move || {
+            let mut ids = Vec::new();
+            for _ in 0..150 {
+                let ev = tele.make_event(Detector::Aws, Action::Blocked, None, None);
+                ids.push(ev.event_id.clone());
+                tele.queue_event(ev).unwrap();
+            }
+            ids
+        })
+    };
+    let flusher = {
+        let tele = tele.clone();
+        let done = done.clone();
+        thread::spawn(move || {
+            while !done.load(Ordering::SeqCst) {
+                tele.flush_once().unwrap();
+                thread::sleep(Duration::from_millis(2));
+            }
+            tele.flush_once().unwrap();
+        })
+    };
+
+    let produced = producer.join().unwrap();
+    done.store(true, Ordering::SeqCst);
+    flusher.join().unwrap();
+
+    // At-least-once: every produced event reached the server
+    let got: HashSet<String> = received.lock().unwrap().iter().cloned().collect();
+    let missing: Vec<&String> = produced.iter().filter(|id| !got.contains(*id)).collect();
+    assert!(missing.is_empty(), "lost {} events", missing.len());
+
+    // Nothing left undelivered, and the chain survived concurrent compaction
+    let data = std::fs::read_to_string(tele.queue_file()).unwrap();
+    assert!(!data.lines().any(|l| l.contains("\"blocked\"")), "queue still has events");
+    let report = tele.verify_chain().unwrap();
+    assert!(report.is_ok(), "{:?}", report.issues);
+}
+
+#[test]
+fn torn_line_does_not_swallow_next_event() {
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let url = start_ingest_server(received.clone());
+    let dir = tempdir().unwrap();
+    let qpath = dir.path().join("tele_queue.jsonl");
+    let tele = Telemetry::new(TelemetryConfig {
+        url: Some(url),
+        queue_file: qpath.clone(),
+        state_dir: dir.path().to_path_buf(),
+        enabled: true,
+        ..Default::default()
+    });
+
+    // Simulate a crash mid-append
+    std::fs::write(&qpath, "{\"id\":\"torn").unwrap();
+    let ev = tele.make_event(Detector::Aws, Action::Blocked, None, None);
+    let id = ev.event_id.clone();
+    tele.queue_event(ev).unwrap();
+
+    tele.flush_once().unwrap();
+    assert_eq!(*received.lock().unwrap(), vec![id]);
+}
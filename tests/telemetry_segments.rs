@@ -0,0 +1,130 @@
+use chrono::Utc;
+use httpmock::Method::POST;
+use httpmock::MockServer;
+use sentinel_pii::scanner::Detector;
+use sentinel_pii::telemetry::{Action, Telemetry, TelemetryConfig, TelemetryEvent};
+use tempfile::tempdir;
+
+fn telemetry(dir: &std::path::Path, url: Option<String>, max_total_bytes: u64) -> Telemetry {
+    Telemetry::new(TelemetryConfig {
+        url,
+        queue_file: dir.join("tele_queue.jsonl"),
+        state_dir: dir.to_path_buf(),
+        enabled: true,
+        max_queue_bytes: 2_000,
+        max_total_bytes,
+        ..Default::default()
+    })
+}
+
+/// An event padded to roughly 1.5 KB, so two of them push the queue past the rotation size.
+fn big_event(tele: &Telemetry) -> TelemetryEvent {
+    tele.make_event(Detector::Aws, Action::Blocked, Some("x".repeat(1_200)), None)
+}
+
+#[test]
+fn flush_sends_rotated_segments_first_and_deletes_them() {
+    let server = MockServer::start();
+    let dir = tempdir().unwrap();
+    let tele = telemetry(dir.path(), Some(format!("{}/events", server.base_url())), u64::MAX);
+
+    let old = [big_event(&tele), big_event(&tele)];
+    for ev in &old {
+        tele.queue_event(ev.clone()).unwrap();
+    }
+    assert_eq!(tele.rotated_segments().len(), 1);
+    let newer = tele.make_event(Detector::Stripe, Action::Blocked, None, None);
+    tele.queue_event(newer.clone()).unwrap();
+
+    // Older events fail: nothing newer may be sent ahead of them
+    let mut fail_old = server.mock(|when, then| {
+        when.method(POST).path("/events").body_contains(old[0].event_id.as_str());
+        then.status(503);
+    });
+    let newer_mock = server.mock(|when, then| {
+        when.method(POST).path("/events").body_contains(newer.event_id.as_str());
+        then.status(200);
+    });
+    assert!(tele.flush_once().is_err());
+    fail_old.assert_hits(1);
+    newer_mock.assert_hits(0);
+    assert_eq!(tele.rotated_segments().len(), 1);
+
+    fail_old.delete();
+    let ok_old = server.mock(|when, then| {
+        when.method(POST).path("/events").body_contains(r#""app_name":"xxx"#);
+        then.status(200);
+    });
+    tele.flush_once().unwrap();
+    ok_old.assert_hits(1);
+    newer_mock.assert_hits(1);
+    assert!(tele.rotated_segments().is_empty());
+    assert!(!dir.path().read_dir().unwrap().any(|e| e.unwrap().file_name().to_string_lossy().ends_with(".old.cursor")));
+
+    let report = tele.verify_chain().unwrap();
+    assert!(report.is_ok(), "{:?}", report.issues);
+}
+
+#[test]
+fn prune_deletes_expired_rotated_segments() {
+    let dir = tempdir().unwrap();
+    let tele = telemetry(dir.path(), None, u64::MAX);
+
+    for _ in 0..2 {
+        let mut ev = big_event(&tele);
+        ev.timestamp = Utc::now() - chrono::Duration::days(40);
+        tele.queue_event(ev).unwrap();
+    }
+    tele.queue_event(tele.make_event(Detector::Aws, Action::Blocked, None, None)).unwrap();
+    // The expired segment is dropped on the next append, before it is ever uploaded
+    assert!(tele.rotated_segments().is_empty());
+
+    tele.prune_old_events().unwrap();
+    let report = tele.verify_chain().unwrap();
+    assert!(report.is_ok(), "{:?}", report.issues);
+    let data = std::fs::read_to_string(tele.queue_file()).unwrap();
+    assert_eq!(data.lines().filter(|l| !l.starts_with("{\"checkpoint\"")).count(), 1);
+}
+
+#[test]
+fn total_cap_drops_oldest_segments_first() {
+    let dir = tempdir().unwrap();
+    let tele = telemetry(dir.path(), None, 4_000);
+
+    let first = big_event(&tele);
+    tele.queue_event(first.clone()).unwrap();
+    for _ in 0..5 {
+        tele.queue_event(big_event(&tele)).unwrap();
+    }
+
+    let mut total = std::fs::metadata(tele.queue_file()).unwrap().len();
+    for seg in tele.rotated_segments() {
+        total += std::fs::metadata(&seg).unwrap().len();
+        assert!(!std::fs::read_to_string(&seg).unwrap().contains(&first.event_id));
+    }
+    assert!(total <= 4_000, "queue is {} bytes", total);
+
+    let report = tele.verify_chain().unwrap();
+    assert!(report.is_ok(), "{:?}", report.issues);
+}
+
+#[test]
+fn total_cap_trims_oldest_lines_of_active_file() {
+    let dir = tempdir().unwrap();
+    // Below the rotation size, so only the active file can be trimmed
+    let tele = telemetry(dir.path(), None, 1_500);
+
+    let first = tele.make_event(Detector::Aws, Action::Blocked, None, None);
+    tele.queue_event(first.clone()).unwrap();
+    for _ in 0..30 {
+        tele.queue_event(tele.make_event(Detector::Aws, Action::Blocked, None, None)).unwrap();
+    }
+
+    let data = std::fs::read_to_string(tele.queue_file()).unwrap();
+    assert!(data.len() <= 1_500, "queue is {} bytes", data.len());
+    assert!(!data.contains(&first.event_id));
+    assert!(data.contains("\"reason\":\"cap\""));
+
+    let report = tele.verify_chain().unwrap();
+    assert!(report.is_ok(), "{:?}", report.issues);
+}
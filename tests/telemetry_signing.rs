@@ -0,0 +1,108 @@
+use httpmock::Method::POST;
+use httpmock::MockServer;
+use sentinel_pii::scanner::Detector;
+use sentinel_pii::signing::{self, BatchSignature, ReplayGuard, SignatureError};
+use sentinel_pii::telemetry::{Action, Telemetry, TelemetryConfig};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::path::Path;
+use std::sync::mpsc::{Receiver, channel};
+use std::time::Duration;
+use tempfile::tempdir;
+
+/// A request as received by `capture_server`: lower-cased headers and the raw body.
+struct Captured {
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+}
+
+/// HTTP server on 127.0.0.1 that answers 200 and hands every request to the receiver.
+fn capture_server() -> (String, Receiver<Captured>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/events", listener.local_addr().unwrap());
+    let (tx, rx) = channel();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            let mut reader = BufReader::new(&mut stream);
+            let mut headers = HashMap::new();
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap_or(0) == 0 || line == "\r\n" {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+                }
+            }
+            let len = headers.get("content-length").and_then(|v| v.parse().ok()).unwrap_or(0);
+            let mut body = vec![0; len];
+            reader.read_exact(&mut body).unwrap();
+            let _ = stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+            let _ = tx.send(Captured { headers, body });
+        }
+    });
+    (url, rx)
+}
+
+fn telemetry(dir: &Path, url: String) -> Telemetry {
+    let tele = Telemetry::new(TelemetryConfig {
+        url: Some(url),
+        queue_file: dir.join("queue").join("tele_queue.jsonl"),
+        state_dir: dir.join("state"),
+        enabled: true,
+        ..Default::default()
+    });
+    tele.queue_event(tele.make_event(Detector::Stripe, Action::Blocked, None, None)).unwrap();
+    tele
+}
+
+#[test]
+fn uploads_are_signed_and_verify_once() {
+    let dir = tempdir().unwrap();
+    let (url, requests) = capture_server();
+    let tele = telemetry(dir.path(), url);
+    let public_key = tele.signing_key().unwrap().public_key();
+    tele.flush_once().unwrap();
+
+    let req = requests.recv_timeout(Duration::from_secs(5)).unwrap();
+    let sig = BatchSignature::from_headers(|name| req.headers.get(&name.to_ascii_lowercase()).cloned()).unwrap();
+    assert_eq!(sig.key_id, signing::key_id(&public_key));
+    let now = chrono::Utc::now().timestamp();
+    let mut guard = ReplayGuard::default();
+    assert_eq!(signing::verify_batch(&public_key, &req.body, &sig, now, &mut guard), Ok(()));
+    assert_eq!(signing::verify_batch(&public_key, &req.body, &sig, now, &mut guard), Err(SignatureError::Replayed));
+
+    let mut tampered = req.body.clone();
+    tampered.extend_from_slice(b" ");
+    let mut guard = ReplayGuard::default();
+    assert_eq!(signing::verify_batch(&public_key, &tampered, &sig, now, &mut guard), Err(SignatureError::BadSignature));
+
+    // The key persists in the state directory, wherever the queue is, so a restarted agent
+    // keeps its enrolled identity
+    let restarted = Telemetry::new(TelemetryConfig {
+        queue_file: dir.path().join("elsewhere").join("tele_queue.jsonl"),
+        state_dir: dir.path().join("state"),
+        ..Default::default()
+    });
+    assert_eq!(restarted.signing_key().unwrap().public_key(), public_key);
+    assert!(dir.path().join("state").join("agent_signing.key").exists());
+    assert!(!dir.path().join("queue").join("agent_signing.key").exists());
+}
+
+#[test]
+fn nothing_is_sent_without_a_signing_key() {
+    let server = MockServer::start();
+    let mock = server.mock(|when, then| {
+        when.method(POST).path("/events");
+        then.status(200);
+    });
+    let dir = tempdir().unwrap();
+    // The state directory is a file, so no signing key can be created
+    std::fs::write(dir.path().join("state"), b"").unwrap();
+    let tele = telemetry(dir.path(), format!("{}/events", server.base_url()));
+    assert!(tele.flush_once().is_err());
+    mock.assert_hits(0);
+    assert_eq!(tele.pending(), 1);
+}
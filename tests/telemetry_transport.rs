@@ -0,0 +1,256 @@
+use httpmock::Method::POST;
+use httpmock::MockServer;
+use rcgen::{BasicConstraints, Certificate, CertificateParams, DnType, IsCa, SanType};
+use rustls::server::AllowAnyAuthenticatedClient;
+use rustls::{RootCertStore, ServerConfig, ServerConnection, StreamOwned};
+use sentinel_pii::scanner::Detector;
+use sentinel_pii::telemetry::{Action, Telemetry, TelemetryConfig};
+use sentinel_pii::transport::{TransportConfig, spki_sha256};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::mpsc::{Receiver, channel};
+use std::time::Duration;
+use tempfile::tempdir;
+
+/// A test CA with a server certificate for 127.0.0.1 and a client certificate, written as PEM.
+struct Pki {
+    ca: Certificate,
+    ca_pem: PathBuf,
+    server: Certificate,
+    client_cert: PathBuf,
+    client_key: PathBuf,
+    client: Certificate,
+}
+
+fn pki(dir: &Path) -> Pki {
+    let mut params = CertificateParams::new(vec![]);
+    params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    params.distinguished_name.push(DnType::CommonName, "Sentinel Test CA");
+    let ca = Certificate::from_params(params).unwrap();
+
+    let mut params = CertificateParams::new(vec!["localhost".to_string()]);
+    params.subject_alt_names.push(SanType::IpAddress("127.0.0.1".parse().unwrap()));
+    let server = Certificate::from_params(params).unwrap();
+    let client = Certificate::from_params(CertificateParams::new(vec!["agent".to_string()])).unwrap();
+
+    let ca_pem = dir.join("ca.pem");
+    std::fs::write(&ca_pem, ca.serialize_pem().unwrap()).unwrap();
+    let client_cert = dir.join("client.pem");
+    std::fs::write(&client_cert, client.serialize_pem_with_signer(&ca).unwrap()).unwrap();
+    let client_key = dir.join("client.key");
+    std::fs::write(&client_key, client.serialize_private_key_pem()).unwrap();
+    Pki { ca, ca_pem, server, client_cert, client_key, client }
+}
+
+/// What the local TLS server saw for one request.
+struct Seen {
+    client_cert: bool,
+    body: String,
+}
+
+/// HTTPS server on 127.0.0.1 answering every request with 200. With `require_client_cert`, only
+/// clients presenting a certificate issued by the test CA complete the handshake.
+fn tls_server(pki: &Pki, require_client_cert: bool) -> (String, Receiver<Seen>) {
+    let chain = vec![rustls::Certificate(pki.server.serialize_der_with_signer(&pki.ca).unwrap())];
+    let key = rustls::PrivateKey(pki.server.serialize_private_key_der());
+    let builder = ServerConfig::builder().with_safe_defaults();
+    let config = if require_client_cert {
+        let mut roots = RootCertStore::empty();
+        roots.add(&rustls::Certificate(pki.ca.serialize_der().unwrap())).unwrap();
+        builder.with_client_cert_verifier(AllowAnyAuthenticatedClient::new(roots).boxed())
+    } else {
+        builder.with_no_client_auth()
+    };
+    let config = Arc::new(config.with_single_cert(chain, key).unwrap());
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("https://127.0.0.1:{}/events", listener.local_addr().unwrap().port());
+    let (tx, rx) = channel();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else { continue };
+            stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            let mut tls = StreamOwned::new(ServerConnection::new(config.clone()).unwrap(), stream);
+            // A failed handshake surfaces as a read error here
+            let Some(body) = read_request(&mut tls) else { continue };
+            let client_cert = tls.conn.peer_certificates().is_some();
+            let _ = tls.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+            tls.conn.send_close_notify();
+            let _ = tls.flush();
+            let _ = tx.send(Seen { client_cert, body });
+        }
+    });
+    (url, rx)
+}
+
+/// Read one HTTP/1.1 request and return its body.
+fn read_request(stream: &mut impl Read) -> Option<String> {
+    let mut reader = BufReader::new(stream);
+    let mut len = 0;
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).ok()?;
+        if line.is_empty() {
+            return None;
+        }
+        if let Some((name, value)) = line.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            len = value.trim().parse().ok()?;
+        }
+        if line == "\r\n" {
+            break;
+        }
+    }
+    let mut body = vec![0; len];
+    reader.read_exact(&mut body).ok()?;
+    String::from_utf8(body).ok()
+}
+
+/// Telemetry with one queued event, uploading to `url` with `transport`.
+fn telemetry(dir: &Path, url: String, transport: TransportConfig) -> (Telemetry, String) {
+    let tele = Telemetry::new(TelemetryConfig {
+        url: Some(url),
+        queue_file: dir.join("tele_queue.jsonl"),
+        state_dir: dir.to_path_buf(),
+        enabled: true,
+        transport,
+        ..Default::default()
+    });
+    let ev = tele.make_event(Detector::Aws, Action::Blocked, None, None);
+    let id = ev.event_id.clone();
+    tele.queue_event(ev).unwrap();
+    (tele, id)
+}
+
+#[test]
+fn mutual_tls_with_custom_ca_and_pem_client_cert() {
+    let dir = tempdir().unwrap();
+    let pki = pki(dir.path());
+    let (url, seen) = tls_server(&pki, true);
+
+    let transport = TransportConfig {
+        ca_bundle: Some(pki.ca_pem.clone()),
+        client_cert: Some(pki.client_cert.clone()),
+        client_key: Some(pki.client_key.clone()),
+        ..Default::default()
+    };
+    let (tele, id) = telemetry(dir.path(), url.clone(), transport.clone());
+    tele.flush_once().unwrap();
+    let req = seen.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert!(req.client_cert);
+    assert!(req.body.contains(&id));
+    assert_eq!(tele.pending(), 0);
+
+    // Without a client certificate the server refuses the handshake and nothing is delivered
+    let dir2 = tempdir().unwrap();
+    let (tele, _) = telemetry(dir2.path(), url.clone(), TransportConfig { client_cert: None, client_key: None, ..transport });
+    assert!(tele.flush_once().is_err());
+    assert_eq!(tele.pending(), 1);
+
+    // Without the CA bundle the server certificate is not trusted
+    let dir3 = tempdir().unwrap();
+    let (tele, _) = telemetry(dir3.path(), url, TransportConfig::default());
+    assert!(tele.flush_once().is_err());
+    assert!(seen.try_recv().is_err());
+}
+
+#[test]
+fn pkcs12_client_identity() {
+    use p12_keystore::{KeyStore, KeyStoreEntry, PrivateKeyChain};
+
+    let dir = tempdir().unwrap();
+    let pki = pki(dir.path());
+    let (url, seen) = tls_server(&pki, true);
+
+    let cert = p12_keystore::Certificate::from_der(&pki.client.serialize_der_with_signer(&pki.ca).unwrap()).unwrap();
+    let chain = PrivateKeyChain::new(pki.client.serialize_private_key_der(), [1u8; 20], [cert]);
+    let mut store = KeyStore::new();
+    store.add_entry("agent", KeyStoreEntry::PrivateKeyChain(chain));
+    let p12 = dir.path().join("client.p12");
+    std::fs::write(&p12, store.writer("hunter2").write().unwrap()).unwrap();
+
+    let transport = TransportConfig {
+        ca_bundle: Some(pki.ca_pem.clone()),
+        client_pkcs12: Some(p12),
+        client_pkcs12_password: Some("hunter2".to_string()),
+        ..Default::default()
+    };
+    let (tele, _) = telemetry(dir.path(), url.clone(), transport.clone());
+    tele.flush_once().unwrap();
+    assert!(seen.recv_timeout(Duration::from_secs(5)).unwrap().client_cert);
+
+    let dir2 = tempdir().unwrap();
+    let (tele, _) = telemetry(dir2.path(), url, TransportConfig { client_pkcs12_password: Some("wrong".to_string()), ..transport });
+    assert!(tele.flush_once().is_err());
+}
+
+#[test]
+fn spki_pins_are_enforced_before_sending() {
+    let dir = tempdir().unwrap();
+    let pki = pki(dir.path());
+    let (url, seen) = tls_server(&pki, false);
+    let pin = |cert_der: &[u8]| format!("sha256/{}", base64_encode(&spki_sha256(cert_der).unwrap()));
+
+    let server_pin = pin(&pki.server.serialize_der_with_signer(&pki.ca).unwrap());
+    let transport = TransportConfig { ca_bundle: Some(pki.ca_pem.clone()), pinned_spki: vec![server_pin], ..Default::default() };
+    let (tele, id) = telemetry(dir.path(), url.clone(), transport.clone());
+    tele.flush_once().unwrap();
+    assert!(seen.recv_timeout(Duration::from_secs(5)).unwrap().body.contains(&id));
+
+    // A chain-valid server whose keys match no pin gets nothing
+    let other = rcgen::generate_simple_self_signed(vec!["other".to_string()]).unwrap();
+    let dir2 = tempdir().unwrap();
+    let (tele, _) = telemetry(
+        dir2.path(),
+        url,
+        TransportConfig { pinned_spki: vec![pin(&other.serialize_der().unwrap())], ..transport },
+    );
+    let err = tele.flush_once().unwrap_err();
+    assert!(format!("{:?}", err).contains("SPKI pin"), "{:?}", err);
+    assert!(seen.recv_timeout(Duration::from_millis(500)).is_err());
+    assert_eq!(tele.pending(), 1);
+}
+
+#[test]
+fn proxy_and_no_proxy() {
+    // A fake HTTP proxy that records the request line and answers 200
+    let proxy = TcpListener::bind("127.0.0.1:0").unwrap();
+    let proxy_url = format!("http://{}", proxy.local_addr().unwrap());
+    let (tx, proxied) = channel();
+    std::thread::spawn(move || {
+        for stream in proxy.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            let mut line = String::new();
+            BufReader::new(&mut stream).read_line(&mut line).unwrap();
+            let _ = stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+            let _ = tx.send(line);
+        }
+    });
+
+    let dir = tempdir().unwrap();
+    let transport = TransportConfig { proxy: Some(proxy_url), no_proxy: Some("127.0.0.1,localhost".to_string()), ..Default::default() };
+    let (tele, _) = telemetry(dir.path(), "http://ingest.example.invalid/events".to_string(), transport.clone());
+    tele.flush_once().unwrap();
+    let line = proxied.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert!(line.starts_with("POST http://ingest.example.invalid/events"), "{}", line);
+
+    // Hosts on the no-proxy list are reached directly
+    let server = MockServer::start();
+    let mock = server.mock(|when, then| {
+        when.method(POST).path("/events");
+        then.status(200);
+    });
+    let dir2 = tempdir().unwrap();
+    let (tele, _) = telemetry(dir2.path(), format!("{}/events", server.base_url()), transport);
+    tele.flush_once().unwrap();
+    mock.assert();
+    assert!(proxied.try_recv().is_err());
+}
+
+fn base64_encode(data: &[u8]) -> String {
+    use base64::Engine;
+    base64::engine::general_purpose::STANDARD.encode(data)
+}
@@ -0,0 +1,57 @@
+use sentinel_pii::scanner::Detector;
+use sentinel_pii::telemetry::{Action, Telemetry, TelemetryConfig};
+use sentinel_pii::throttle::TelemetryThrottleConfig;
+use std::time::Duration;
+use tempfile::tempdir;
+
+#[test]
+fn telemetry_events_are_throttled_per_action_detector_and_app() {
+    let dir = tempdir().unwrap();
+    let tele = Telemetry::new(TelemetryConfig {
+        queue_file: dir.path().join("tele_queue.jsonl"),
+        state_dir: dir.path().to_path_buf(),
+        enabled: true,
+        throttle: TelemetryThrottleConfig { window: Duration::from_secs(3600), max_events: 3 },
+        ..Default::default()
+    });
+
+    // A log full of keys copied into Slack
+    for _ in 0..50 {
+        tele.queue_event(tele.make_event(Detector::Aws, Action::Blocked, Some("Slack".to_string()), None)).unwrap();
+    }
+    tele.queue_event(tele.make_event(Detector::Aws, Action::Blocked, Some("Firefox".to_string()), None)).unwrap();
+    tele.queue_event(tele.make_event(Detector::GitHub, Action::Blocked, Some("Slack".to_string()), None)).unwrap();
+    tele.queue_event(tele.make_event(Detector::Aws, Action::Allowed, Some("Slack".to_string()), None)).unwrap();
+
+    let events = tele.queued_events().unwrap();
+    assert_eq!(events.len(), 6);
+    let slack_aws_blocks = events
+        .iter()
+        .filter(|e| e.action == Action::Blocked && e.secret_type == Some(Detector::Aws) && e.app_name.as_deref() == Some("Slack"))
+        .count();
+    assert_eq!(slack_aws_blocks, 3);
+}
+
+#[test]
+fn telemetry_throttle_runs_after_privacy_controls() {
+    use sentinel_pii::privacy::{AppNameLevel, PrivacyConfig};
+
+    let dir = tempdir().unwrap();
+    let tele = Telemetry::new(TelemetryConfig {
+        queue_file: dir.path().join("tele_queue.jsonl"),
+        state_dir: dir.path().to_path_buf(),
+        enabled: true,
+        throttle: TelemetryThrottleConfig { window: Duration::from_secs(3600), max_events: 2 },
+        privacy: PrivacyConfig { app_name: AppNameLevel::Category, ..Default::default() },
+        ..Default::default()
+    });
+
+    // Slack and Discord are both "chat" once generalized, so they share one budget
+    for app in ["Slack", "Discord", "Slack"] {
+        tele.queue_event(tele.make_event(Detector::Aws, Action::Blocked, Some(app.to_string()), None)).unwrap();
+    }
+
+    let events = tele.queued_events().unwrap();
+    assert_eq!(events.len(), 2);
+    assert!(events.iter().all(|e| e.action == Action::Blocked && e.app_name.as_deref() == Some("chat")));
+}
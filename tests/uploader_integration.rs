@@ -0,0 +1,128 @@
+use httpmock::Method::POST;
+use httpmock::MockServer;
+use sentinel_pii::scanner::Detector;
+use sentinel_pii::telemetry::{Action, Telemetry, TelemetryConfig};
+use sentinel_pii::uploader::{self, UploaderConfig};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tempfile::tempdir;
+
+fn setup(server: &MockServer, dir: &std::path::Path) -> Arc<Telemetry> {
+    Arc::new(Telemetry::new(TelemetryConfig {
+        url: Some(format!("{}/events", server.base_url())),
+        api_key: None,
+        queue_file: dir.join("tele_queue.jsonl"),
+        state_dir: dir.to_path_buf(),
+        enabled: true,
+        ..Default::default()
+    }))
+}
+
+fn wait_until(timeout: Duration, mut f: impl FnMut() -> bool) -> bool {
+    let start = Instant::now();
+    while start.elapsed() < timeout {
+        if f() {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    f()
+}
+
+#[test]
+fn uploader_flushes_after_n_events_and_on_shutdown() {
+    let server = MockServer::start();
+    let mock = server.mock(|when, then| {
+        when.method(POST).path("/events");
+        then.status(200).body("ok");
+    });
+    let dir = tempdir().unwrap();
+    let tele = setup(&server, dir.path());
+    let running = Arc::new(AtomicBool::new(true));
+
+    let cfg = UploaderConfig {
+        interval: Duration::from_secs(3600),
+        flush_after_events: 2,
+        ..Default::default()
+    };
+    let handle = uploader::spawn(tele.clone(), running.clone(), cfg).unwrap();
+
+    for _ in 0..2 {
+        tele.queue_event(tele.make_event(Detector::Aws, Action::Blocked, None, None)).unwrap();
+    }
+    assert!(wait_until(Duration::from_secs(5), || mock.hits() == 1));
+    // The cursor is committed just after the server answers
+    assert!(wait_until(Duration::from_secs(5), || tele.pending() == 0), "pending={}", tele.pending());
+
+    // One more event below the threshold is delivered by the final flush on shutdown
+    tele.queue_event(tele.make_event(Detector::Aws, Action::Blocked, None, None)).unwrap();
+    running.store(false, Ordering::SeqCst);
+    tele.wake();
+    handle.join().unwrap();
+    mock.assert_hits(2);
+    assert_eq!(std::fs::metadata(tele.queue_file()).unwrap().len(), 0);
+}
+
+#[test]
+fn uploader_backs_off_and_keeps_events_on_failure() {
+    let server = MockServer::start();
+    let mock = server.mock(|when, then| {
+        when.method(POST).path("/events");
+        then.status(503);
+    });
+    let dir = tempdir().unwrap();
+    let tele = setup(&server, dir.path());
+    let running = Arc::new(AtomicBool::new(true));
+
+    let cfg = UploaderConfig {
+        interval: Duration::from_secs(3600),
+        flush_after_events: 1,
+        backoff_base: Duration::from_secs(3600),
+        backoff_max: Duration::from_secs(3600),
+    };
+    let handle = uploader::spawn(tele.clone(), running.clone(), cfg).unwrap();
+    tele.queue_event(tele.make_event(Detector::Aws, Action::Blocked, None, None)).unwrap();
+    assert!(wait_until(Duration::from_secs(5), || mock.hits() == 1));
+
+    // While backing off no further attempts are made
+    std::thread::sleep(Duration::from_millis(300));
+    assert_eq!(mock.hits(), 1);
+
+    running.store(false, Ordering::SeqCst);
+    handle.join().unwrap();
+    // Final flush attempted, events stay queued
+    mock.assert_hits(2);
+    assert!(std::fs::read_to_string(tele.queue_file()).unwrap().contains("blocked"));
+}
+
+#[test]
+fn uploader_backs_off_when_nothing_is_acknowledged() {
+    let server = MockServer::start();
+    let mock = server.mock(|when, then| {
+        when.method(POST).path("/events");
+        then.status(200).json_body(serde_json::json!({"accepted": [], "rejected": []}));
+    });
+    let dir = tempdir().unwrap();
+    let tele = setup(&server, dir.path());
+    let running = Arc::new(AtomicBool::new(true));
+
+    let cfg = UploaderConfig {
+        interval: Duration::from_secs(3600),
+        flush_after_events: 1,
+        backoff_base: Duration::from_secs(3600),
+        backoff_max: Duration::from_secs(3600),
+    };
+    let handle = uploader::spawn(tele.clone(), running.clone(), cfg).unwrap();
+    tele.queue_event(tele.make_event(Detector::Aws, Action::Blocked, None, None)).unwrap();
+    assert!(wait_until(Duration::from_secs(5), || mock.hits() == 1));
+
+    // The event is still pending, but no progress counts as a failure: no tight retry loop
+    std::thread::sleep(Duration::from_millis(300));
+    assert_eq!(mock.hits(), 1);
+    assert_eq!(tele.pending(), 1);
+
+    running.store(false, Ordering::SeqCst);
+    handle.join().unwrap();
+    mock.assert_hits(2);
+}
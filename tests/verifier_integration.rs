@@ -0,0 +1,122 @@
+use httpmock::Method::{GET, POST};
+use httpmock::MockServer;
+use reqwest::blocking::Client;
+use sentinel_pii::scanner;
+use sentinel_pii::verifier::{self, LiveCheck, LiveVerifier, Verification, VerifierConfig};
+use std::time::Duration;
+
+/// Fixture keys are built at runtime so the source never contains key-shaped literals.
+fn fixture_key(prefix: &str, fill: &str, len: usize) -> String {
+    format!("{}{}", prefix, fill.repeat(len))
+}
+
+fn config(server: &MockServer) -> VerifierConfig {
+    VerifierConfig {
+        enabled: true,
+        aws_sts_url: server.base_url(),
+        stripe_api_url: server.base_url(),
+        min_interval: Duration::ZERO,
+    }
+}
+
+#[test]
+fn stripe_balance_active_and_inactive() {
+    let server = MockServer::start();
+    let live = fixture_key("sk_test_", "a", 24);
+    let dead = fixture_key("sk_test_", "b", 24);
+
+    let ok = server.mock(|when, then| {
+        when.method(GET).path("/v1/balance").header("Authorization", format!("Bearer {}", live));
+        then.status(200).body("{}");
+    });
+    let unauthorized = server.mock(|when, then| {
+        when.method(GET).path("/v1/balance").header("Authorization", format!("Bearer {}", dead));
+        then.status(401).body("{}");
+    });
+
+    let lv = LiveVerifier::new(&config(&server), Client::new());
+
+    let f = scanner::scan(&live);
+    assert_eq!(lv.verify(&f[0], &live), Verification::Active);
+    let f = scanner::scan(&dead);
+    assert_eq!(lv.verify(&f[0], &dead), Verification::Inactive);
+
+    ok.assert();
+    unauthorized.assert();
+}
+
+#[test]
+fn aws_sts_signed_get_caller_identity() {
+    let server = MockServer::start();
+    let mock = server.mock(|when, then| {
+        when.method(POST)
+            .path("/")
+            .body("Action=GetCallerIdentity&Version=2011-06-15")
+            .header_exists("X-Amz-Date")
+            .matches(|req| {
+                req.headers.as_ref().is_some_and(|h| {
+                    h.iter().any(|(k, v)| {
+                        k.eq_ignore_ascii_case("authorization") && v.starts_with("AWS4-HMAC-SHA256 Credential=AKIA")
+                    })
+                })
+            });
+        then.status(403).body("<ErrorResponse><Error><Code>InvalidClientTokenId</Code></Error></ErrorResponse>");
+    });
+
+    let key_id = fixture_key("AKIA", "Q", 16);
+    let text = format!("aws_access_key_id={}\naws_secret_access_key={}\n", key_id, fixture_key("", "s", 40));
+    let lv = LiveVerifier::new(&config(&server), Client::new());
+    let f = scanner::scan(&text);
+    assert_eq!(lv.verify(&f[0], &text), Verification::Inactive);
+    mock.assert();
+
+    // Without a companion secret there is nothing to sign with
+    let f = scanner::scan(&key_id);
+    assert_eq!(lv.verify(&f[0], &key_id), Verification::Unknown);
+    mock.assert_hits(1);
+}
+
+#[test]
+fn disabled_and_rate_limited_checks_do_not_call_provider() {
+    let server = MockServer::start();
+    let mock = server.mock(|when, then| {
+        when.method(GET).path("/v1/balance");
+        then.status(200).body("{}");
+    });
+    let key = fixture_key("sk_live_", "c", 24);
+    let f = scanner::scan(&key);
+
+    let disabled = LiveVerifier::new(&VerifierConfig { enabled: false, ..config(&server) }, Client::new());
+    assert_eq!(disabled.verify(&f[0], &key), Verification::Unknown);
+    mock.assert_hits(0);
+
+    let limited = LiveVerifier::new(
+        &VerifierConfig { min_interval: Duration::from_secs(3600), ..config(&server) },
+        Client::new(),
+    );
+    assert_eq!(limited.verify(&f[0], &key), Verification::Active);
+    assert_eq!(limited.verify(&f[0], &key), Verification::Unknown);
+    mock.assert_hits(1);
+}
+
+#[test]
+fn checks_run_off_thread_and_come_back_in_order() {
+    let server = MockServer::start();
+    server.mock(|when, then| {
+        when.method(GET).path("/v1/balance");
+        then.status(401).body("{}");
+    });
+    let (checks, results) = verifier::spawn::<u32>(LiveVerifier::new(&config(&server), Client::new())).unwrap();
+
+    let stripe = fixture_key("sk_live_", "d", 24);
+    let github = fixture_key("ghp_", "e", 36);
+    for (tag, text) in [(1, &stripe), (2, &github)] {
+        let finding = scanner::scan(text)[0].clone();
+        checks.send(LiveCheck { tag, finding, text: text.clone(), result: None }).unwrap();
+    }
+    drop(checks);
+
+    let done: Vec<(u32, Option<Verification>)> = results.iter().map(|c| (c.tag, c.result)).collect();
+    // No live verifier for GitHub tokens
+    assert_eq!(done, vec![(1, Some(Verification::Inactive)), (2, None)]);
+}